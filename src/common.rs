@@ -1,26 +1,72 @@
+mod commands;
+mod dns;
 mod interface;
+mod logs;
+mod ntp;
 mod services;
+mod syslog;
+mod ufw;
 
 use anyhow::{anyhow, Result};
-pub use interface::{Nic, NicOutput};
+pub use commands::{commands, CommandSpec};
+pub use dns::{DnsScope, EffectiveDns};
+pub use interface::{
+    validate_address_family, AccessPoint, AddressDrift, AddressFamily, Bridge, BridgeParams,
+    Dhcp4Overrides, FieldChange, InterfaceChange, Nameservers, NetplanDiff, NetworkSnapshot, Nic,
+    NicMatch, NicOutput, RingSize, Route, Verification, Vlan, Wifi, MAX_NAMESERVERS, PASSWORD_MASK,
+};
+pub use logs::{JournalVacuum, LogRotation};
+pub use ntp::NtpPeer;
 use serde::{Deserialize, Serialize};
-pub use services::waitfor_up;
+pub use services::{
+    service_failure_reason, service_resources, service_state, service_status,
+    system_service_status, waitfor_service, waitfor_up, waitfor_up_with, Backoff, ServiceResources,
+    ServiceState, ServiceStatus, SYSTEM_SERVICES,
+};
+use std::time::SystemTime;
+pub use syslog::{SyslogFacility, SyslogProtocol, SyslogServer};
+use thiserror::Error;
+pub use ufw::{FirewallAction, FirewallDirection, FirewallProtocol, FirewallRule};
 
 pub const DEFAULT_PATH_ENV: &str = "/usr/sbin:/usr/bin:/sbin:/bin:/usr/local/aice/bin";
 
+/// The revision of the protocol between the library and the roxy helper. It
+/// is increased when `Node`, `SubCommand`, or an argument type changes.
+pub const PROTOCOL_VERSION: u32 = 14;
+
+/// The maximum size in bytes of a decoded response from roxy. A larger
+/// response fails with [`ResponseTooLarge`] instead of being allocated.
+pub const MAX_RESPONSE_SIZE: u64 = 64 * 1024 * 1024;
+
+// TODO: should change this path to /usr/local/aice/conf/version?
+/// The file storing OS and product versions. `set_os_version` and
+/// `set_product_version` write to this file, and `version` reads it.
+pub const VERSION_PATH: &str = "/etc/version";
+
 /// Types of command to node.
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub enum Node {
+    Backup(SubCommand),
+    Banner(SubCommand),
+    Capabilities,
+    Dns(SubCommand),
     Hostname(SubCommand),
+    Hosts(SubCommand),
     Interface(SubCommand),
+    Journal(SubCommand),
+    Logrotate(SubCommand),
+    Modules(SubCommand),
     Ntp(SubCommand),
+    Ping,
     PowerOff,
     Reboot,
     Service(SubCommand),
     Sshd(SubCommand),
+    Sysctl(SubCommand),
     Syslog(SubCommand),
     Ufw(SubCommand),
     Version(SubCommand),
+    Wifi(SubCommand),
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -29,6 +75,24 @@ pub struct NodeRequest {
     pub kind: Node,
     /// command arguments
     pub arg: Vec<u8>,
+    /// [`PROTOCOL_VERSION`] of the caller. Zero if the caller is older than
+    /// the protocol revision.
+    #[serde(default)]
+    pub protocol_version: u32,
+    /// Asks roxy to write a successful response as a [`NodeResponse`] line
+    /// with an empty result, followed by the base64 payload, so that the
+    /// caller can decode the payload as it reads it.
+    #[serde(default)]
+    pub stream: bool,
+}
+
+/// Response message from roxy, with the protocol revision of roxy.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NodeResponse {
+    /// [`PROTOCOL_VERSION`] of roxy.
+    pub protocol_version: u32,
+    /// base64-encoded bincode of the result, or of [`RemoteError`].
+    pub result: std::result::Result<String, String>,
 }
 
 impl NodeRequest {
@@ -44,24 +108,148 @@ impl NodeRequest {
         T: Serialize,
     {
         match bincode::serialize(&cmd) {
-            Ok(arg) => Ok(NodeRequest { kind, arg }),
+            Ok(arg) => Ok(NodeRequest {
+                kind,
+                arg,
+                protocol_version: PROTOCOL_VERSION,
+                stream: false,
+            }),
             Err(e) => Err(anyhow!("Error: {}", e)),
         }
     }
 }
 
+/// The version of the installed roxy helper, returned by `ping_roxy`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RoxyInfo {
+    /// The package version of the roxy binary.
+    pub version: String,
+    /// The protocol revision of the roxy binary.
+    pub protocol: u32,
+}
+
+impl RoxyInfo {
+    /// True if the roxy binary speaks the same protocol as this library.
+    #[must_use]
+    pub fn is_compatible(&self) -> bool {
+        self.protocol == PROTOCOL_VERSION
+    }
+}
+
+/// Acknowledgement that roxy accepted a reboot or power off and scheduled it.
+/// The system may still be running when the caller receives this.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ShutdownAck {
+    /// When the reboot or power off starts.
+    pub scheduled_at: SystemTime,
+}
+
+/// Error codes returned by roxy.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Another command holding the same resource is in progress.
+    Busy,
+    /// Failed to execute the command.
+    Fail,
+    /// Unknown command or invalid argument.
+    InvalidCommand,
+    /// The response message is too long.
+    MessageTooLong,
+    /// Every netplan yaml file fails to be parsed.
+    NetplanInvalid,
+    /// The netplan directory does not exist, or is not readable.
+    NetplanUnreadable,
+    /// The netplan directory has no yaml file, e.g. on a fresh image.
+    NoNetplanYaml,
+    /// The library and roxy speak different protocol revisions.
+    ProtocolMismatch,
+    /// Failed to serialize the response message.
+    SerializeFail,
+    /// The error is not recognized, e.g. from an older roxy.
+    Unknown,
+}
+
+/// Error returned by roxy. `run_roxy` returns this error within
+/// `anyhow::Error`, so callers can get it with `downcast_ref`.
+///
+/// # Example
+///
+/// ```ignore
+/// if let Err(e) = roxy::reboot() {
+///     if let Some(e) = e.downcast_ref::<roxy::common::RemoteError>() {
+///         println!("code = {:?}, message = {}", e.code, e.message);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Error)]
+#[error("{message}")]
+pub struct RemoteError {
+    pub code: ErrorCode,
+    /// The cause of the error if roxy knows it, e.g. the stderr of the failed
+    /// command, or the description of the code.
+    pub message: String,
+}
+
+/// Error returned when a response from roxy is larger than
+/// [`MAX_RESPONSE_SIZE`], e.g. because of a corrupt length in it.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("response from roxy is larger than {limit} bytes")]
+pub struct ResponseTooLarge {
+    pub limit: u64,
+}
+
 #[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub enum SubCommand {
     Add,
+    AddNameservers,
+    AddSearch,
+    Apply,
+    Backup,
+    Bootstrap,
+    Clear,
     Delete,
+    Diff,
     Disable,
     Enable,
+    Export,
+    Flush,
+    Generate,
     Get,
+    GetLink,
+    GetMerged,
+    GetRenderer,
+    GetRing,
+    GetSearch,
+    Import,
     Init,
     List,
+    ListConfigs,
+    Pending,
+    Remove,
+    Rename,
+    Restart,
+    Restore,
     Set,
+    SetAcceptRa,
+    SetAllOptional,
+    SetByMac,
+    SetDescription,
+    SetDhcpOverrides,
+    SetLink,
+    SetMany,
     SetOsVersion,
+    SetPretty,
     SetProductVersion,
+    SetRing,
+    SetSearch,
+    SetServe,
+    SetWithSearch,
+    SourceFile,
     Status,
+    Stop,
+    Sync,
     Update,
+    Validate,
+    ValidateGenerate,
+    Verify,
 }
@@ -0,0 +1,482 @@
+use super::{Node, SubCommand};
+use serde::Serialize;
+
+/// A command of roxy, with the type names of its argument and its response.
+/// A front end can use this to build forms without knowing every command.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CommandSpec {
+    /// The command.
+    pub node: Node,
+    /// The type of the argument, e.g. "(String, NicOutput, bool)". The
+    /// argument of a command taking nothing is `Option<String>` set to `None`.
+    pub input: &'static str,
+    /// The type of the response, e.g. "Vec<String>". "String" of a command
+    /// only acknowledging the request is "Ok".
+    pub output: &'static str,
+}
+
+const COMMAND_SPECS: &[CommandSpec] = &[
+    CommandSpec {
+        node: Node::Backup(SubCommand::Restore),
+        input: "String",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Banner(SubCommand::Get),
+        input: "Option<String>",
+        output: "Option<String>",
+    },
+    CommandSpec {
+        node: Node::Banner(SubCommand::Set),
+        input: "String",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Capabilities,
+        input: "Option<String>",
+        output: "Vec<String>",
+    },
+    CommandSpec {
+        node: Node::Dns(SubCommand::Flush),
+        input: "Option<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Dns(SubCommand::Get),
+        input: "Option<String>",
+        output: "Vec<String>",
+    },
+    CommandSpec {
+        node: Node::Dns(SubCommand::GetLink),
+        input: "String",
+        output: "(Vec<String>, Vec<String>)",
+    },
+    CommandSpec {
+        node: Node::Dns(SubCommand::GetSearch),
+        input: "Option<String>",
+        output: "Vec<String>",
+    },
+    CommandSpec {
+        node: Node::Dns(SubCommand::Set),
+        input: "Vec<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Dns(SubCommand::SetLink),
+        input: "(String, Vec<String>, Vec<String>)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Dns(SubCommand::SetSearch),
+        input: "Vec<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Dns(SubCommand::SetWithSearch),
+        input: "(Vec<String>, Vec<String>)",
+        output: "Vec<String>",
+    },
+    CommandSpec {
+        node: Node::Dns(SubCommand::Status),
+        input: "Option<String>",
+        output: "EffectiveDns",
+    },
+    CommandSpec {
+        node: Node::Hostname(SubCommand::Get),
+        input: "Option<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Hostname(SubCommand::Set),
+        input: "String",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Hostname(SubCommand::SetPretty),
+        input: "String",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Hosts(SubCommand::Delete),
+        input: "String",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Hosts(SubCommand::Get),
+        input: "Option<String>",
+        output: "Vec<(String, Vec<String>)>",
+    },
+    CommandSpec {
+        node: Node::Hosts(SubCommand::Set),
+        input: "(String, Vec<String>)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::AddNameservers),
+        input: "(String, Vec<String>)",
+        output: "Vec<String>",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::AddSearch),
+        input: "(String, Vec<String>)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Apply),
+        input: "(String, NicOutput)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Backup),
+        input: "String",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Bootstrap),
+        input: "String",
+        output: "bool",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Delete),
+        input: "(String, NicOutput)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Diff),
+        input: "String",
+        output: "NetplanDiff",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Export),
+        input: "Option<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Generate),
+        input: "Option<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Get),
+        input: "Option<String>",
+        output: "(Option<Vec<(String, NicOutput)>>, Vec<(String, String)>)",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::GetMerged),
+        input: "Option<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::GetRenderer),
+        input: "Option<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::GetRing),
+        input: "String",
+        output: "RingSize",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Import),
+        input: "String",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Init),
+        input: "String",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::List),
+        input: "Option<String>",
+        output: "Vec<String>",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::ListConfigs),
+        input: "Option<String>",
+        output: "Vec<(String, Result<String, String>)>",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Pending),
+        input: "Option<String>",
+        output: "NetplanDiff",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Remove),
+        input: "(String, bool)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Rename),
+        input: "(String, String, bool)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Restore),
+        input: "String",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Set),
+        input: "(String, NicOutput, bool, bool)",
+        output: "Vec<String>",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::SetAcceptRa),
+        input: "(String, bool)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::SetAllOptional),
+        input: "Vec<String>",
+        output: "Vec<String>",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::SetByMac),
+        input: "(String, String, NicOutput)",
+        output: "Vec<String>",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::SetDescription),
+        input: "(String, Option<String>)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::SetDhcpOverrides),
+        input: "(String, Option<String>, Option<String>)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::SetMany),
+        input: "(Vec<(String, NicOutput)>, bool)",
+        output: "Vec<String>",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::SetRing),
+        input: "(String, u32, u32)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::SourceFile),
+        input: "String",
+        output: "Option<String>",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Sync),
+        input: "Option<String>",
+        output: "Vec<(String, AddressDrift)>",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Validate),
+        input: "(String, NicOutput)",
+        output: "Option<String>",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::ValidateGenerate),
+        input: "(String, NicOutput)",
+        output: "Option<String>",
+    },
+    CommandSpec {
+        node: Node::Interface(SubCommand::Verify),
+        input: "(String, NicOutput)",
+        output: "Verification",
+    },
+    CommandSpec {
+        node: Node::Journal(SubCommand::Delete),
+        input: "JournalVacuum",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Journal(SubCommand::Get),
+        input: "Option<String>",
+        output: "u64",
+    },
+    CommandSpec {
+        node: Node::Logrotate(SubCommand::Get),
+        input: "String",
+        output: "Option<LogRotation>",
+    },
+    CommandSpec {
+        node: Node::Logrotate(SubCommand::Set),
+        input: "(String, LogRotation)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Modules(SubCommand::Get),
+        input: "Option<String>",
+        output: "Vec<String>",
+    },
+    CommandSpec {
+        node: Node::Modules(SubCommand::Set),
+        input: "Vec<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Ntp(SubCommand::Disable),
+        input: "Option<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Ntp(SubCommand::Enable),
+        input: "Option<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Ntp(SubCommand::Get),
+        input: "Option<String>",
+        output: "Option<Vec<String>>",
+    },
+    CommandSpec {
+        node: Node::Ntp(SubCommand::List),
+        input: "Option<String>",
+        output: "Vec<NtpPeer>",
+    },
+    CommandSpec {
+        node: Node::Ntp(SubCommand::Set),
+        input: "(Vec<String>, bool)",
+        output: "Vec<String>",
+    },
+    CommandSpec {
+        node: Node::Ntp(SubCommand::SetServe),
+        input: "(bool, Vec<String>)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Ntp(SubCommand::Status),
+        input: "Option<String>",
+        output: "bool",
+    },
+    CommandSpec {
+        node: Node::Ping,
+        input: "Option<String>",
+        output: "RoxyInfo",
+    },
+    CommandSpec {
+        node: Node::PowerOff,
+        input: "u64",
+        output: "ShutdownAck",
+    },
+    CommandSpec {
+        node: Node::Reboot,
+        input: "u64",
+        output: "ShutdownAck",
+    },
+    CommandSpec {
+        node: Node::Service(SubCommand::Disable),
+        input: "String",
+        output: "bool",
+    },
+    CommandSpec {
+        node: Node::Service(SubCommand::Enable),
+        input: "String",
+        output: "bool",
+    },
+    CommandSpec {
+        node: Node::Service(SubCommand::Restart),
+        input: "(String, u16, u64)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Service(SubCommand::Status),
+        input: "String",
+        output: "bool",
+    },
+    CommandSpec {
+        node: Node::Service(SubCommand::Stop),
+        input: "(String, u64)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Service(SubCommand::Update),
+        input: "String",
+        output: "bool",
+    },
+    CommandSpec {
+        node: Node::Sshd(SubCommand::Get),
+        input: "Option<String>",
+        output: "u16",
+    },
+    CommandSpec {
+        node: Node::Sshd(SubCommand::Set),
+        input: "(String, bool)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Sysctl(SubCommand::Get),
+        input: "String",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Sysctl(SubCommand::Set),
+        input: "(String, String)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Syslog(SubCommand::Clear),
+        input: "Option<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Syslog(SubCommand::Get),
+        input: "Option<String>",
+        output: "Option<Vec<SyslogServer>>",
+    },
+    CommandSpec {
+        node: Node::Syslog(SubCommand::Init),
+        input: "Option<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Syslog(SubCommand::Set),
+        input: "Vec<SyslogServer>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Syslog(SubCommand::Update),
+        input: "Option<String>",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Ufw(SubCommand::Add),
+        input: "(FirewallAction, u16, FirewallProtocol)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Ufw(SubCommand::Delete),
+        input: "(FirewallAction, u16, FirewallProtocol)",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Ufw(SubCommand::Get),
+        input: "Option<String>",
+        output: "Vec<FirewallRule>",
+    },
+    CommandSpec {
+        node: Node::Version(SubCommand::SetOsVersion),
+        input: "String",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Version(SubCommand::SetProductVersion),
+        input: "String",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Wifi(SubCommand::Get),
+        input: "bool",
+        output: "String",
+    },
+    CommandSpec {
+        node: Node::Wifi(SubCommand::Set),
+        input: "(String, String, Option<String>, NicOutput)",
+        output: "String",
+    },
+];
+
+/// Returns the commands of this revision. roxy reports its own list for
+/// `Node::Capabilities`, so that a library can find the commands an older
+/// roxy does not implement.
+#[must_use]
+pub fn commands() -> Vec<CommandSpec> {
+    COMMAND_SPECS.to_vec()
+}
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+/// The DNS servers and domains of systemd-resolved in one scope, i.e.
+/// globally or on one link.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DnsScope {
+    /// The server systemd-resolved is talking to now. `None` if it has not
+    /// picked one, e.g. no query has been made yet.
+    pub current_server: Option<String>,
+    /// The DNS servers, in the order systemd-resolved tries them.
+    pub servers: Vec<String>,
+    /// The search and routing domains. A routing-only domain starts with
+    /// "~", e.g. "~." routes all queries to this scope.
+    pub domains: Vec<String>,
+}
+
+/// The DNS configuration systemd-resolved uses at runtime, reported by
+/// `resolvectl status`. It merges the settings from netplan, DHCP, and
+/// `resolved.conf`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct EffectiveDns {
+    /// The scope used regardless of links, e.g. from `resolved.conf`.
+    pub global: DnsScope,
+    /// The link name and its scope, in the order of the link index.
+    pub links: Vec<(String, DnsScope)>,
+}
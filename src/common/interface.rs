@@ -1,5 +1,14 @@
+use anyhow::{anyhow, Result};
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+/// The maximum number of nameservers used by the resolver. Nameservers after
+/// this are ignored silently in `/etc/resolv.conf`.
+pub const MAX_NAMESERVERS: usize = 3;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Nic {
@@ -13,6 +22,243 @@ pub struct Nic {
     pub nameservers: Option<HashMap<String, Vec<String>>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub optional: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routes: Option<Vec<Route>>,
+    #[serde(rename = "dhcp4-overrides", skip_serializing_if = "Option::is_none")]
+    pub dhcp4_overrides: Option<Dhcp4Overrides>,
+    #[serde(rename = "dhcp-identifier", skip_serializing_if = "Option::is_none")]
+    pub dhcp_identifier: Option<String>,
+    #[serde(rename = "accept-ra", skip_serializing_if = "Option::is_none")]
+    pub accept_ra: Option<bool>,
+    #[serde(rename = "match", skip_serializing_if = "Option::is_none")]
+    pub matching: Option<NicMatch>,
+    #[serde(rename = "set-name", skip_serializing_if = "Option::is_none")]
+    pub set_name: Option<String>,
+}
+
+/// The rule netplan uses to find the physical interface of an ethernet entry,
+/// instead of the entry name.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct NicMatch {
+    /// The MAC address of the interface, e.g. "00:11:22:33:44:55".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub macaddress: Option<String>,
+}
+
+/// Options overriding what netplan takes from the DHCP server. Unset options
+/// keep the defaults of netplan.
+///
+/// Over the roxy protocol, this is delivered in JSON because it skips empty
+/// fields.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Dhcp4Overrides {
+    /// Uses the DNS servers from the DHCP server.
+    #[serde(rename = "use-dns", skip_serializing_if = "Option::is_none")]
+    pub use_dns: Option<bool>,
+    /// Uses the NTP servers from the DHCP server.
+    #[serde(rename = "use-ntp", skip_serializing_if = "Option::is_none")]
+    pub use_ntp: Option<bool>,
+    /// Installs the routes from the DHCP server.
+    #[serde(rename = "use-routes", skip_serializing_if = "Option::is_none")]
+    pub use_routes: Option<bool>,
+    /// Uses the hostname from the DHCP server.
+    #[serde(rename = "use-hostname", skip_serializing_if = "Option::is_none")]
+    pub use_hostname: Option<bool>,
+    /// Sends the hostname to the DHCP server.
+    #[serde(rename = "send-hostname", skip_serializing_if = "Option::is_none")]
+    pub send_hostname: Option<bool>,
+    /// The metric of the routes from the DHCP server.
+    #[serde(rename = "route-metric", skip_serializing_if = "Option::is_none")]
+    pub route_metric: Option<u32>,
+}
+
+/// A static route of an interface in netplan.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Route {
+    pub to: String,
+    /// The gateway. `None` for a route without one, e.g. an on-link route or
+    /// a route of type "unreachable".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub via: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metric: Option<u32>,
+    /// The other keys of the route, e.g. "table", "scope", "on-link", "from",
+    /// and "type", kept as read so that rewriting the file does not drop them.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_yaml::Value>,
+}
+
+impl Route {
+    /// True if this is a default route, i.e. `to` is "default", "0.0.0.0/0",
+    /// or "::/0".
+    #[must_use]
+    pub fn is_default(&self) -> bool {
+        matches!(self.to.as_str(), "default" | "0.0.0.0/0" | "::/0")
+    }
+}
+
+/// Nameservers of a bridge in netplan.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Nameservers {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search: Option<Vec<String>>,
+    pub addresses: Option<Vec<String>>,
+}
+
+/// A bridge in netplan.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Bridge {
+    pub interfaces: Vec<String>,
+    pub addresses: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway4: Option<String>,
+    pub nameservers: Nameservers,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routes: Option<Vec<Route>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<BridgeParams>,
+}
+
+/// The spanning tree parameters of a bridge in netplan. Unset parameters keep
+/// the defaults of the kernel.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct BridgeParams {
+    /// Enables the spanning tree protocol.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stp: Option<bool>,
+    /// The seconds a port spends in the listening and learning states, from
+    /// 2 to 30.
+    #[serde(rename = "forward-delay", skip_serializing_if = "Option::is_none")]
+    pub forward_delay: Option<u32>,
+    /// The seconds between the hello packets of the root bridge, from 1 to
+    /// 10.
+    #[serde(rename = "hello-time", skip_serializing_if = "Option::is_none")]
+    pub hello_time: Option<u32>,
+    /// The seconds a hello packet is kept before it is discarded, from 6 to
+    /// 40.
+    #[serde(rename = "max-age", skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u32>,
+    /// The bridge priority, from 0 to 65535. The bridge with the lowest
+    /// priority becomes the root bridge.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u16>,
+}
+
+impl BridgeParams {
+    /// The range of `forward_delay` the kernel accepts.
+    pub const FORWARD_DELAY_RANGE: std::ops::RangeInclusive<u32> = 2..=30;
+
+    /// The range of `hello_time` the kernel accepts.
+    pub const HELLO_TIME_RANGE: std::ops::RangeInclusive<u32> = 1..=10;
+
+    /// The range of `max_age` the kernel accepts.
+    pub const MAX_AGE_RANGE: std::ops::RangeInclusive<u32> = 6..=40;
+
+    /// Checks the parameters. `priority` is in range by its type.
+    ///
+    /// # Errors
+    ///
+    /// * `forward_delay` is out of [`Self::FORWARD_DELAY_RANGE`]
+    /// * `hello_time` is out of [`Self::HELLO_TIME_RANGE`]
+    /// * `max_age` is out of [`Self::MAX_AGE_RANGE`]
+    pub fn validate(&self) -> Result<()> {
+        let checks = [
+            (
+                "forward-delay",
+                self.forward_delay,
+                Self::FORWARD_DELAY_RANGE,
+            ),
+            ("hello-time", self.hello_time, Self::HELLO_TIME_RANGE),
+            ("max-age", self.max_age, Self::MAX_AGE_RANGE),
+        ];
+        for (name, value, range) in checks {
+            match value {
+                Some(value) if !range.contains(&value) => {
+                    return Err(anyhow!(
+                        "{} should be {} to {} seconds: {}",
+                        name,
+                        range.start(),
+                        range.end(),
+                        value
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A vlan in netplan.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Vlan {
+    pub id: u16,
+    pub link: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addresses: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway4: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nameservers: Option<HashMap<String, Vec<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routes: Option<Vec<Route>>,
+}
+
+/// The password shown instead of the real one when passwords are hidden.
+pub const PASSWORD_MASK: &str = "********";
+
+/// An access point of a wifi in netplan.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AccessPoint {
+    /// The WPA password. `None` for an open network.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+/// A wifi in netplan.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Wifi {
+    /// Access points by SSID.
+    #[serde(rename = "access-points")]
+    pub access_points: HashMap<String, AccessPoint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addresses: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dhcp4: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway4: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nameservers: Option<HashMap<String, Vec<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optional: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routes: Option<Vec<Route>>,
+}
+
+impl Wifi {
+    /// Replaces the passwords of access points with [`PASSWORD_MASK`].
+    pub fn mask_passwords(&mut self) {
+        for ap in self.access_points.values_mut() {
+            if ap.password.is_some() {
+                ap.password = Some(PASSWORD_MASK.to_string());
+            }
+        }
+    }
+}
+
+/// The whole network configuration of the system, to back up and restore it.
+///
+/// Static routes are included in each interface. Over the roxy protocol, this
+/// is delivered in JSON because `Nic` skips empty fields.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct NetworkSnapshot {
+    pub version: Option<u32>,
+    pub renderer: Option<String>,
+    pub ethernets: Vec<(String, Nic)>,
+    pub bridges: Vec<(String, Bridge)>,
+    pub vlans: Vec<(String, Vlan)>,
+    #[serde(default)]
+    pub wifis: Vec<(String, Wifi)>,
 }
 
 impl fmt::Display for Nic {
@@ -40,8 +286,173 @@ impl Nic {
             gateway4,
             nameservers,
             optional,
+            routes: None,
+            dhcp4_overrides: None,
+            dhcp_identifier: None,
+            accept_ra: None,
+            matching: None,
+            set_name: None,
+        }
+    }
+
+    /// Removes duplicated nameserver addresses, keeping the first-seen order.
+    pub fn dedup_nameservers(&mut self) {
+        if let Some(nameservers) = &mut self.nameservers {
+            if let Some(addrs) = nameservers.get_mut("addresses") {
+                *addrs = dedup_addresses(addrs);
+            }
+        }
+    }
+}
+
+/// Differences between the addresses in netplan yaml and the addresses of
+/// the running interface.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AddressDrift {
+    /// Addresses of the running interface which are not in netplan yaml.
+    pub missing_in_yaml: Vec<String>,
+
+    /// Addresses in netplan yaml which are not set to the running interface.
+    pub missing_in_link: Vec<String>,
+}
+
+impl AddressDrift {
+    /// True if netplan yaml and the running interface have the same addresses.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.missing_in_yaml.is_empty() && self.missing_in_link.is_empty()
+    }
+}
+
+/// The result of comparing an interface setting with the running interface.
+/// Each item is described like "address 192.168.0.5/24" or "gateway
+/// 192.168.0.1".
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Verification {
+    /// Items of the setting which the running interface has.
+    pub matched: Vec<String>,
+
+    /// Items of the setting which the running interface does not have, e.g.
+    /// because the link is unplugged.
+    pub unmatched: Vec<String>,
+}
+
+impl Verification {
+    /// True if the running interface has every item of the setting.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.unmatched.is_empty()
+    }
+}
+
+/// The ring buffer sizes of a network adapter, from `ethtool -g`.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RingSize {
+    /// The current number of RX ring entries.
+    pub rx: u32,
+    /// The current number of TX ring entries.
+    pub tx: u32,
+    /// The maximum number of RX ring entries the adapter supports.
+    pub rx_max: u32,
+    /// The maximum number of TX ring entries the adapter supports.
+    pub tx_max: u32,
+}
+
+/// The change of a field, e.g. "addresses" of an interface. The values are in
+/// JSON, and `None` if the field is not set.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// The changed fields of an ethernet, bridge, vlan, or wifi.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct InterfaceChange {
+    pub name: String,
+    pub fields: Vec<FieldChange>,
+}
+
+/// The difference between two network configurations. Interfaces are sorted
+/// by name, and wifi passwords are masked with [`PASSWORD_MASK`].
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct NetplanDiff {
+    /// Changes of `version` and `renderer`.
+    pub network: Vec<FieldChange>,
+    /// Interfaces which exist only in the new configuration.
+    pub added: Vec<String>,
+    /// Interfaces which exist only in the old configuration.
+    pub removed: Vec<String>,
+    /// Interfaces which exist in both, but with different settings.
+    pub changed: Vec<InterfaceChange>,
+}
+
+impl NetplanDiff {
+    /// True if the two configurations are the same.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.network.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+/// The address families allowed in the addresses of an interface.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// Any IPv4 or IPv6 addresses.
+    Any,
+    /// IPv4 addresses only.
+    V4Only,
+    /// IPv6 addresses only.
+    V6Only,
+    /// At least one IPv4 address and one IPv6 address.
+    DualStack,
+}
+
+/// Checks that `addresses` in CIDR notation, e.g. "192.168.0.5/24" or
+/// "2001:db8::5/64", meet the policy of `family`.
+///
+/// # Errors
+///
+/// * If an address is invalid, then an error is returned.
+/// * If an address of the forbidden family is found, or a family required
+///   by `DualStack` is missing, then an error is returned.
+///
+/// # Example
+///
+/// ```ignore
+/// validate_address_family(&addresses, AddressFamily::V4Only)?;
+/// roxy::set_interface(dev, Some(addresses), None, gateway4, None, None, None, false, false)?;
+/// ```
+pub fn validate_address_family(addresses: &[String], family: AddressFamily) -> Result<()> {
+    let mut has_v4 = false;
+    let mut has_v6 = false;
+    for addr in addresses {
+        match addr.parse::<IpNet>() {
+            Ok(IpNet::V4(_)) => has_v4 = true,
+            Ok(IpNet::V6(_)) => has_v6 = true,
+            Err(e) => return Err(anyhow!("invalid address: {}. {:?}", addr, e)),
+        }
+        match family {
+            AddressFamily::V4Only if has_v6 => {
+                return Err(anyhow!("IPv6 address is not allowed: {}", addr))
+            }
+            AddressFamily::V6Only if has_v4 => {
+                return Err(anyhow!("IPv4 address is not allowed: {}", addr))
+            }
+            _ => {}
         }
     }
+    if family == AddressFamily::DualStack && !(has_v4 && has_v6) {
+        return Err(anyhow!(
+            "both IPv4 and IPv6 addresses are required: {}",
+            addresses.join(", ")
+        ));
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -49,7 +460,19 @@ pub struct NicOutput {
     pub addresses: Option<Vec<String>>,
     pub dhcp4: Option<bool>,
     pub gateway4: Option<String>,
+    /// Nameservers in the order the resolver tries them. The order is kept as
+    /// given through `to` and `from`, and only later duplicates are removed.
     pub nameservers: Option<Vec<String>>,
+    /// If true, booting does not wait for the interface to be online.
+    pub optional: Option<bool>,
+    /// The metric of the default route via `gateway4`. If set, the gateway is
+    /// saved as a default route with this metric, so that multiple interfaces
+    /// can have gateways with different priorities. Lower is preferred.
+    pub gateway_metric: Option<u32>,
+    /// The description of the interface, e.g. "management". This is returned
+    /// by `interfaces`, and ignored by `set_interface`. Use
+    /// `set_interface_description` to change it.
+    pub description: Option<String>,
 }
 
 impl fmt::Display for NicOutput {
@@ -70,9 +493,24 @@ impl fmt::Display for NicOutput {
             writeln!(f, "\tgateway4: -")?;
         }
         if let Some(v) = &self.nameservers {
-            write!(f, "\tnameservers: {v:?}")
+            writeln!(f, "\tnameservers: {v:?}")?;
         } else {
-            write!(f, "\tnameservers: -")
+            writeln!(f, "\tnameservers: -")?;
+        }
+        if let Some(v) = self.optional {
+            writeln!(f, "\toptional: {v}")?;
+        } else {
+            writeln!(f, "\toptional: -")?;
+        }
+        if let Some(v) = self.gateway_metric {
+            writeln!(f, "\tgateway_metric: {v}")?;
+        } else {
+            writeln!(f, "\tgateway_metric: -")?;
+        }
+        if let Some(v) = &self.description {
+            write!(f, "\tdescription: {v}")
+        } else {
+            write!(f, "\tdescription: -")
         }
     }
 }
@@ -84,12 +522,17 @@ impl NicOutput {
         dhcp4: Option<bool>,
         gateway4: Option<String>,
         nameservers: Option<Vec<String>>,
+        optional: Option<bool>,
+        gateway_metric: Option<u32>,
     ) -> Self {
         NicOutput {
             addresses,
             dhcp4,
             gateway4,
             nameservers,
+            optional,
+            gateway_metric,
+            description: None,
         }
     }
 
@@ -97,18 +540,36 @@ impl NicOutput {
     pub fn to(&self) -> Nic {
         let nameservers = if let Some(nm) = &self.nameservers {
             let mut m = HashMap::new();
-            m.insert("addresses".to_string(), nm.clone());
+            m.insert("addresses".to_string(), dedup_addresses(nm));
             m.insert("search".to_string(), Vec::new());
             Some(m)
         } else {
             None
         };
+        let (gateway4, routes) = match (&self.gateway4, self.gateway_metric) {
+            (Some(via), Some(metric)) => (
+                None,
+                Some(vec![Route {
+                    to: "default".to_string(),
+                    via: Some(via.clone()),
+                    metric: Some(metric),
+                    extra: BTreeMap::new(),
+                }]),
+            ),
+            (gateway4, _) => (gateway4.clone(), None),
+        };
         Nic {
             addresses: self.addresses.clone(),
             dhcp4: self.dhcp4,
-            gateway4: self.gateway4.clone(),
+            gateway4,
             nameservers,
-            optional: None,
+            optional: self.optional,
+            routes,
+            dhcp4_overrides: None,
+            dhcp_identifier: None,
+            accept_ra: None,
+            matching: None,
+            set_name: None,
         }
     }
 
@@ -116,16 +577,42 @@ impl NicOutput {
     pub fn from(nic: &Nic) -> Self {
         let nameservers = {
             if let Some(nm) = &nic.nameservers {
-                nm.get("addresses").cloned()
+                nm.get("addresses").map(|addrs| dedup_addresses(addrs))
             } else {
                 None
             }
         };
+        let (gateway4, gateway_metric) = if nic.gateway4.is_some() {
+            (nic.gateway4.clone(), None)
+        } else if let Some(route) = nic
+            .routes
+            .iter()
+            .flatten()
+            .find(|r| r.is_default() && r.via.is_some())
+        {
+            (route.via.clone(), route.metric)
+        } else {
+            (None, None)
+        };
         NicOutput {
             addresses: nic.addresses.clone(),
             dhcp4: nic.dhcp4,
-            gateway4: nic.gateway4.clone(),
+            gateway4,
             nameservers,
+            optional: nic.optional,
+            gateway_metric,
+            description: None,
+        }
+    }
+}
+
+// Removes duplicated addresses, keeping the first-seen order.
+fn dedup_addresses(addrs: &[String]) -> Vec<String> {
+    let mut ret: Vec<String> = Vec::new();
+    for addr in addrs {
+        if !ret.contains(addr) {
+            ret.push(addr.clone());
         }
     }
+    ret
 }
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// How to reduce the disk usage of the journal.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum JournalVacuum {
+    /// Removes archived journal files until the disk usage is below this
+    /// size in megabytes.
+    Size(u64),
+    /// Removes archived journal files older than this number of days.
+    Time(u32),
+}
+
+/// The logrotate policy of the log files of a service.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct LogRotation {
+    /// Rotates a log file when it grows bigger than this size in megabytes.
+    pub max_size_mb: u32,
+    /// The number of rotated log files to keep.
+    pub keep: u32,
+}
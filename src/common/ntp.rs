@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// A peer of the NTP daemon reported by `ntpq -pn`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct NtpPeer {
+    /// The address of the peer.
+    pub remote: String,
+    /// The reference of the peer, e.g. an upstream address or ".GPS.".
+    pub refid: String,
+    /// The stratum of the peer. 16 means unsynchronized.
+    pub stratum: u8,
+    /// The polling interval in seconds.
+    pub poll: u32,
+    /// The reachability register. Each bit is one of the last eight polls.
+    pub reach: u8,
+    /// The round-trip delay in milliseconds.
+    pub delay: f64,
+    /// The offset of the peer from the local clock in milliseconds.
+    pub offset: f64,
+    /// The dispersion of the offset in milliseconds.
+    pub jitter: f64,
+    /// True if the local clock is synchronized to this peer.
+    pub selected: bool,
+}
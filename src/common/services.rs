@@ -1,30 +1,402 @@
-use anyhow::Result;
+use super::DEFAULT_PATH_ENV;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 use std::{
     net::{IpAddr, SocketAddr, TcpStream},
+    process::Command,
     thread,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
+/// System services whose state can be read with `system_service_status`.
+/// These are dependencies of AICE services, and are not controlled by roxy.
+pub const SYSTEM_SERVICES: &[&str] = &[
+    "kafka",
+    "ntp",
+    "postgresql",
+    "rsyslog",
+    "sshd",
+    "systemd-resolved",
+    "ufw",
+    "zookeeper",
+];
+
+/// The state of a systemd unit reported by `systemctl is-active`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ServiceState {
+    Active,
+    Reloading,
+    Inactive,
+    Failed,
+    Activating,
+    Deactivating,
+    /// Other states, e.g. "maintenance" or "refreshing".
+    Other(String),
+}
+
+impl ServiceState {
+    fn parse(s: &str) -> Self {
+        match s.trim() {
+            "active" => Self::Active,
+            "reloading" => Self::Reloading,
+            "inactive" => Self::Inactive,
+            "failed" => Self::Failed,
+            "activating" => Self::Activating,
+            "deactivating" => Self::Deactivating,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// True if the unit is changing its state, so that the caller should wait
+    /// rather than declare failure.
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::Activating | Self::Deactivating | Self::Reloading
+        )
+    }
+}
+
+/// The status of a systemd unit from `systemctl show`, serializable for APIs
+/// as it is.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ServiceStatus {
+    /// The unit name.
+    pub name: String,
+    /// The active state, e.g. `ServiceState::Active`.
+    pub active_state: ServiceState,
+    /// The sub-state, e.g. "running", "exited" or "dead".
+    pub sub_state: String,
+    /// True if the unit is enabled to start at boot.
+    pub enabled: bool,
+    /// The PID of the main process. `None` if it is not running.
+    pub main_pid: Option<u32>,
+}
+
+/// The resources a systemd unit uses, from the accounting of systemd.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ServiceResources {
+    /// The unit name.
+    pub name: String,
+    /// The memory in bytes the unit uses now. `None` if the unit is not
+    /// running or memory accounting is off.
+    pub memory_bytes: Option<u64>,
+    /// The CPU time in nanoseconds the unit has used since it started.
+    /// `None` if the unit is not running or CPU accounting is off.
+    pub cpu_nsec: Option<u64>,
+}
+
+/// Returns the state of a systemd unit.
+///
+/// # Errors
+///
+/// * invalid unit name
+/// * fail to execute `systemctl`
+/// * `systemctl` reports no state
+pub fn service_state(unit: &str) -> Result<ServiceState> {
+    if unit.is_empty() || unit.starts_with('-') {
+        return Err(anyhow!("invalid unit name: {}", unit));
+    }
+    // `systemctl is-active` exits with non-zero status unless the unit is
+    // active, but it still prints the state.
+    let output = Command::new("systemctl")
+        .env("PATH", DEFAULT_PATH_ENV)
+        .args(["is-active", unit])
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if stdout.trim().is_empty() {
+        return Err(anyhow!(
+            "no state of {}: {}",
+            unit,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(ServiceState::parse(&stdout))
+}
+
+/// Returns the active state, sub-state, enabled-at-boot and main PID of a
+/// systemd unit at once.
+///
+/// # Errors
+///
+/// * invalid unit name
+/// * fail to execute `systemctl`
+/// * the unit is not found
+///
+/// # Example
+///
+/// ```ignore
+/// let status = roxy::service_status("ntp")?;
+/// let json = serde_json::to_string(&status)?;
+/// ```
+pub fn service_status(unit: &str) -> Result<ServiceStatus> {
+    let output = systemctl_show(unit, "LoadState,ActiveState,SubState,UnitFileState,MainPID")?;
+    parse_status(unit, &output)
+}
+
+/// Returns the memory and CPU time a systemd unit uses, e.g. to attribute
+/// resource consumption to each service.
+///
+/// # Errors
+///
+/// * invalid unit name
+/// * fail to execute `systemctl`
+/// * the unit is not found
+pub fn service_resources(unit: &str) -> Result<ServiceResources> {
+    let output = systemctl_show(unit, "LoadState,MemoryCurrent,CPUUsageNSec")?;
+    parse_resources(unit, &output)
+}
+
+/// Returns why a systemd unit failed, e.g. "exited with status 1: config
+/// error on line 12", or `None` if its last run succeeded. The reason ends
+/// with the last error line of the unit in the journal, if any.
+///
+/// # Errors
+///
+/// * invalid unit name
+/// * fail to execute `systemctl`
+/// * the unit is not found
+pub fn service_failure_reason(unit: &str) -> Result<Option<String>> {
+    let output = systemctl_show(unit, "LoadState,Result,ExecMainStatus")?;
+    let Some(reason) = parse_failure(unit, &output)? else {
+        return Ok(None);
+    };
+    match last_error_line(unit) {
+        Some(line) => Ok(Some(format!("{reason}: {line}"))),
+        None => Ok(Some(reason)),
+    }
+}
+
+// Returns the last message of the unit with priority "err" or higher in the
+// journal. The journal may not be readable without root or the
+// systemd-journal group, so this is best effort.
+fn last_error_line(unit: &str) -> Option<String> {
+    let output = Command::new("journalctl")
+        .env("PATH", DEFAULT_PATH_ENV)
+        .args([
+            "-u",
+            unit,
+            "-p",
+            "err",
+            "-n",
+            "1",
+            "--no-pager",
+            "-o",
+            "cat",
+        ])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .last()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+// Parses the output of `systemctl show -p` like:
+//
+// Result=exit-code
+// ExecMainStatus=1
+// LoadState=loaded
+//
+// `ExecMainStatus` is the exit status, or the signal number if the main
+// process is killed by a signal.
+fn parse_failure(unit: &str, output: &str) -> Result<Option<String>> {
+    let mut result = "";
+    let mut status = "";
+    for (key, value) in output.lines().filter_map(|line| line.split_once('=')) {
+        match key {
+            "LoadState" if value == "not-found" => {
+                return Err(anyhow!("unit {} is not found", unit));
+            }
+            "Result" => result = value,
+            "ExecMainStatus" => status = value,
+            _ => {}
+        }
+    }
+    let reason = match result {
+        "" | "success" => return Ok(None),
+        "exit-code" => format!("exited with status {status}"),
+        "signal" => format!("killed by signal {status}"),
+        "core-dump" => format!("dumped core with signal {status}"),
+        other => format!("failed with result {other}"),
+    };
+    Ok(Some(reason))
+}
+
+// Runs `systemctl show -p <properties> <unit>`, and returns the output.
+fn systemctl_show(unit: &str, properties: &str) -> Result<String> {
+    if unit.is_empty() || unit.starts_with('-') {
+        return Err(anyhow!("invalid unit name: {}", unit));
+    }
+    let output = Command::new("systemctl")
+        .env("PATH", DEFAULT_PATH_ENV)
+        .args(["show", "-p", properties, unit])
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "systemctl show {} failed: {}",
+            unit,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Parses the output of `systemctl show -p` like:
+//
+// LoadState=loaded
+// MemoryCurrent=104857600
+// CPUUsageNSec=[not set]
+//
+// systemd reports "[not set]" or `u64::MAX` if the value is not available.
+fn parse_resources(unit: &str, output: &str) -> Result<ServiceResources> {
+    let mut resources = ServiceResources {
+        name: unit.to_string(),
+        memory_bytes: None,
+        cpu_nsec: None,
+    };
+    for (key, value) in output.lines().filter_map(|line| line.split_once('=')) {
+        let amount = value.parse::<u64>().ok().filter(|v| *v != u64::MAX);
+        match key {
+            "LoadState" if value == "not-found" => {
+                return Err(anyhow!("unit {} is not found", unit));
+            }
+            "MemoryCurrent" => resources.memory_bytes = amount,
+            "CPUUsageNSec" => resources.cpu_nsec = amount,
+            _ => {}
+        }
+    }
+    Ok(resources)
+}
+
+// Parses the output of `systemctl show -p` like:
+//
+// MainPID=1234
+// LoadState=loaded
+// ActiveState=active
+// SubState=running
+// UnitFileState=enabled
+fn parse_status(unit: &str, output: &str) -> Result<ServiceStatus> {
+    let mut status = ServiceStatus {
+        name: unit.to_string(),
+        active_state: ServiceState::Other(String::new()),
+        sub_state: String::new(),
+        enabled: false,
+        main_pid: None,
+    };
+    for (key, value) in output.lines().filter_map(|line| line.split_once('=')) {
+        match key {
+            "LoadState" if value == "not-found" => {
+                return Err(anyhow!("unit {} is not found", unit));
+            }
+            "ActiveState" => status.active_state = ServiceState::parse(value),
+            "SubState" => status.sub_state = value.to_string(),
+            "UnitFileState" => status.enabled = value == "enabled",
+            "MainPID" => status.main_pid = value.parse::<u32>().ok().filter(|pid| *pid != 0),
+            _ => {}
+        }
+    }
+    Ok(status)
+}
+
+/// Returns the state of a system service in [`SYSTEM_SERVICES`], e.g. to
+/// monitor dependencies like postgresql or kafka. This is read-only. Other
+/// services are rejected.
+///
+/// # Errors
+///
+/// * the service is not in [`SYSTEM_SERVICES`]
+/// * fail to execute `systemctl`
+/// * `systemctl` reports no state
+pub fn system_service_status(name: &str) -> Result<ServiceState> {
+    if !SYSTEM_SERVICES.contains(&name) {
+        return Err(anyhow!("{} is not a system service", name));
+    }
+    service_state(name)
+}
+
+/// Waits while the unit is activating, deactivating, or reloading, and
+/// returns the settled state. The transient state is returned if it does not
+/// settle within `timeout` seconds.
+///
+/// # Errors
+///
+/// * fail to get the state of the unit
+pub fn waitfor_service(unit: &str, timeout: u64) -> Result<ServiceState> {
+    let start = SystemTime::now();
+    loop {
+        let state = service_state(unit)?;
+        if !state.is_transient() || SystemTime::now().duration_since(start)?.as_secs() >= timeout {
+            return Ok(state);
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Retry policy of [`waitfor_up_with`]. The delay between attempts starts
+/// at `initial_delay` and doubles after each failed attempt, up to
+/// `max_delay`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    /// Timeout of each connection attempt.
+    pub connect_timeout: Duration,
+    /// Delay after the first failed attempt.
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between attempts.
+    pub max_delay: Duration,
+}
+
+impl Default for Backoff {
+    /// One second of connect timeout, and delays of 1, 2, 4, and 8 seconds.
+    fn default() -> Self {
+        Backoff {
+            connect_timeout: Duration::from_secs(1),
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(8),
+        }
+    }
+}
+
 /// Check the port is open (service is available).
 /// * Be careful! The opened ports does not mean that service is available. Sometimes it takes more time.
 /// * The service running in docker container should wait more time until service is ready.
 ///
+/// Attempts are retried with the default [`Backoff`] until `timeout` seconds
+/// pass.
+///
 /// # Errors
 ///
 /// * invalid ipaddress or port number
 pub fn waitfor_up(addr: &str, port: &str, timeout: u64) -> Result<bool> {
+    waitfor_up_with(addr, port, timeout, &Backoff::default())
+}
+
+/// Same as [`waitfor_up`], but retries with the given `backoff`. The last
+/// delay is shortened so that it does not wait longer than `timeout`
+/// seconds.
+///
+/// # Errors
+///
+/// * invalid ipaddress or port number
+pub fn waitfor_up_with(addr: &str, port: &str, timeout: u64, backoff: &Backoff) -> Result<bool> {
     let remote_sock = SocketAddr::new(addr.parse::<IpAddr>()?, port.parse::<u16>()?);
-    let start = SystemTime::now();
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    let mut delay = backoff.initial_delay;
     loop {
-        match TcpStream::connect_timeout(&remote_sock, Duration::from_secs(1)) {
-            Ok(_) => return Ok(true),
-            Err(_) => {
-                if SystemTime::now().duration_since(start)?.as_secs() < timeout {
-                    thread::sleep(Duration::from_secs(1));
-                } else {
-                    return Ok(false);
-                }
-            }
+        if TcpStream::connect_timeout(&remote_sock, backoff.connect_timeout).is_ok() {
+            return Ok(true);
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(false);
         }
+        thread::sleep(delay.min(remaining));
+        delay = delay.saturating_mul(2).min(backoff.max_delay);
     }
 }
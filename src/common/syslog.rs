@@ -0,0 +1,162 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+/// Facility of the messages forwarded to a remote syslog server.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum SyslogFacility {
+    /// All facilities, i.e. `*`.
+    All,
+    Auth,
+    Authpriv,
+    Cron,
+    Daemon,
+    Kern,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+    Mail,
+    Syslog,
+    User,
+}
+
+impl fmt::Display for SyslogFacility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SyslogFacility::All => "*",
+            SyslogFacility::Auth => "auth",
+            SyslogFacility::Authpriv => "authpriv",
+            SyslogFacility::Cron => "cron",
+            SyslogFacility::Daemon => "daemon",
+            SyslogFacility::Kern => "kern",
+            SyslogFacility::Local0 => "local0",
+            SyslogFacility::Local1 => "local1",
+            SyslogFacility::Local2 => "local2",
+            SyslogFacility::Local3 => "local3",
+            SyslogFacility::Local4 => "local4",
+            SyslogFacility::Local5 => "local5",
+            SyslogFacility::Local6 => "local6",
+            SyslogFacility::Local7 => "local7",
+            SyslogFacility::Mail => "mail",
+            SyslogFacility::Syslog => "syslog",
+            SyslogFacility::User => "user",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for SyslogFacility {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "*" => Ok(SyslogFacility::All),
+            "auth" => Ok(SyslogFacility::Auth),
+            "authpriv" => Ok(SyslogFacility::Authpriv),
+            "cron" => Ok(SyslogFacility::Cron),
+            "daemon" => Ok(SyslogFacility::Daemon),
+            "kern" => Ok(SyslogFacility::Kern),
+            "local0" => Ok(SyslogFacility::Local0),
+            "local1" => Ok(SyslogFacility::Local1),
+            "local2" => Ok(SyslogFacility::Local2),
+            "local3" => Ok(SyslogFacility::Local3),
+            "local4" => Ok(SyslogFacility::Local4),
+            "local5" => Ok(SyslogFacility::Local5),
+            "local6" => Ok(SyslogFacility::Local6),
+            "local7" => Ok(SyslogFacility::Local7),
+            "mail" => Ok(SyslogFacility::Mail),
+            "syslog" => Ok(SyslogFacility::Syslog),
+            "user" => Ok(SyslogFacility::User),
+            _ => Err(anyhow!("invalid syslog facility: {}", s)),
+        }
+    }
+}
+
+/// Transport protocol to a remote syslog server.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum SyslogProtocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for SyslogProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyslogProtocol::Tcp => write!(f, "tcp"),
+            SyslogProtocol::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+/// A remote syslog server rsyslog forwards messages to.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SyslogServer {
+    /// Facility of the forwarded messages.
+    pub facility: SyslogFacility,
+    /// Transport protocol.
+    pub proto: SyslogProtocol,
+    /// Address of the server.
+    pub addr: IpAddr,
+    /// Port of the server.
+    pub port: u16,
+}
+
+/// Formats the server as an rsyslog forwarding rule, e.g.
+/// `user.* @@192.168.0.205:7500`.
+impl fmt::Display for SyslogServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = match self.proto {
+            SyslogProtocol::Tcp => "@@",
+            SyslogProtocol::Udp => "@",
+        };
+        write!(
+            f,
+            "{}.* {}{}",
+            self.facility,
+            prefix,
+            SocketAddr::new(self.addr, self.port)
+        )
+    }
+}
+
+/// Parses an rsyslog forwarding rule, e.g. `user.* @@192.168.0.205:7500`.
+impl FromStr for SyslogServer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (selector, action) = s
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| anyhow!("invalid syslog rule: {}", s))?;
+        let facility = selector
+            .strip_suffix(".*")
+            .ok_or_else(|| anyhow!("invalid syslog selector: {}", selector))?
+            .parse::<SyslogFacility>()?;
+        let action = action.trim();
+        let (proto, dest) = if let Some(dest) = action.strip_prefix("@@") {
+            (SyslogProtocol::Tcp, dest)
+        } else if let Some(dest) = action.strip_prefix('@') {
+            (SyslogProtocol::Udp, dest)
+        } else {
+            return Err(anyhow!("invalid syslog action: {}", action));
+        };
+        let dest = dest
+            .parse::<SocketAddr>()
+            .map_err(|e| anyhow!("invalid address: {:?}", e))?;
+        Ok(SyslogServer {
+            facility,
+            proto,
+            addr: dest.ip(),
+            port: dest.port(),
+        })
+    }
+}
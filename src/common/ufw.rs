@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Action of a firewall rule.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum FirewallAction {
+    Allow,
+    Deny,
+    Limit,
+    Reject,
+}
+
+impl fmt::Display for FirewallAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FirewallAction::Allow => write!(f, "allow"),
+            FirewallAction::Deny => write!(f, "deny"),
+            FirewallAction::Limit => write!(f, "limit"),
+            FirewallAction::Reject => write!(f, "reject"),
+        }
+    }
+}
+
+/// Transport protocol of a firewall rule.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum FirewallProtocol {
+    Any,
+    Tcp,
+    Udp,
+}
+
+/// Direction of the traffic a firewall rule applies to.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum FirewallDirection {
+    In,
+    Out,
+    /// Traffic routed through the host, i.e. `ufw route` rules.
+    Forward,
+}
+
+/// A firewall rule reported by `ufw status`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FirewallRule {
+    /// Destination, e.g. "22/tcp", "80", "OpenSSH", "22/tcp (v6)".
+    pub to: String,
+
+    /// Action of the rule.
+    pub action: FirewallAction,
+
+    /// Source, e.g. "Anywhere", "192.168.0.0/24".
+    pub from: String,
+
+    /// Direction of the traffic. `ufw status` omits it for incoming rules.
+    pub direction: FirewallDirection,
+
+    /// Comment of the rule, e.g. "ssh" set with `ufw allow 22 comment ssh`.
+    pub comment: Option<String>,
+}
+
+impl FirewallRule {
+    /// Returns the port and protocol of the destination. `None` if the
+    /// destination is not a single port, e.g. an application profile or a
+    /// port range.
+    #[must_use]
+    pub fn port(&self) -> Option<(u16, FirewallProtocol)> {
+        let to = self.to.trim_end_matches("(v6)").trim();
+        let (port, proto) = match to.split_once('/') {
+            Some((port, "tcp")) => (port, FirewallProtocol::Tcp),
+            Some((port, "udp")) => (port, FirewallProtocol::Udp),
+            Some(_) => return None,
+            None => (to, FirewallProtocol::Any),
+        };
+        port.parse::<u16>().ok().map(|port| (port, proto))
+    }
+
+    /// True if the rule is for IPv6.
+    #[must_use]
+    pub fn is_v6(&self) -> bool {
+        self.to.ends_with("(v6)")
+    }
+}
@@ -2,12 +2,37 @@ pub mod common;
 mod user;
 
 use anyhow::{anyhow, Result};
-pub use common::waitfor_up;
-use common::{NicOutput, Node, NodeRequest, SubCommand};
+use bincode::Options;
+pub use common::{
+    commands, service_failure_reason, service_resources, service_state, service_status,
+    system_service_status, validate_address_family, waitfor_service, waitfor_up, waitfor_up_with,
+    AddressFamily, Backoff, CommandSpec, ServiceResources, ServiceState, ServiceStatus,
+};
+use common::{
+    AddressDrift, Dhcp4Overrides, EffectiveDns, ErrorCode, FirewallAction, FirewallProtocol,
+    FirewallRule, JournalVacuum, LogRotation, NetplanDiff, NetworkSnapshot, NicOutput, Node,
+    NodeRequest, NodeResponse, NtpPeer, RemoteError, ResponseTooLarge, RingSize, RoxyInfo,
+    ShutdownAck, SubCommand, SyslogServer, Verification, Wifi, MAX_RESPONSE_SIZE, PROTOCOL_VERSION,
+};
 use data_encoding::BASE64;
 use serde::Deserialize;
-use std::process::{Command, Stdio};
-pub use user::hwinfo::{uptime, version};
+use std::{
+    io::{BufRead, BufReader, Read},
+    process::{Child, Command, Stdio},
+    sync::{PoisonError, RwLock},
+};
+pub use user::health::{
+    check as health_check, HealthReport, HealthStatus, DISK_CRITICAL_PERCENT, DISK_DEGRADED_PERCENT,
+};
+pub use user::hwinfo::{
+    disk_usage_bytes, fans, fqdn, kernel_cmdline, kernel_version, os_version, pretty_hostname,
+    product_version, sysinfo, thermals, uptime, uptime_secs, version, DiskUsageBytes, Fan, SysInfo,
+    ThermalZone,
+};
+pub use user::net::{
+    default_route, driver_info, link_info, neighbors, physical_interfaces, ping, routes,
+    DriverInfo, Duplex, LinkInfo, Neighbor, PingResult, RouteEntry,
+};
 pub use user::usg::{resource_usage, ResourceUsage};
 const FAIL_REQUEST: &str = "Failed to create a request";
 
@@ -26,74 +51,2206 @@ pub fn service_control(subcmd: SubCommand, service: String) -> Result<bool> {
     }
 }
 
+/// Restarts the service, and checks that it comes back by waiting until it
+/// accepts connections on the local `port` for up to `timeout` seconds.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the service fails to restart or does not open the port in time, then
+///   an error with `ErrorCode::Fail` and the last journal lines of the
+///   service is returned.
+pub fn restart_service_checked(service: String, port: u16, timeout: u64) -> Result<()> {
+    if let Ok(req) = NodeRequest::new::<(String, u16, u64)>(
+        Node::Service(SubCommand::Restart),
+        (service, port, timeout),
+    ) {
+        run_roxy::<String>(req).map(|_| ())
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Stops the service, and checks that it is stopped by waiting until the unit
+/// is inactive for up to `timeout` seconds.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the service fails to stop or is still active after `timeout`
+///   seconds, then an error with `ErrorCode::Fail` and the last journal
+///   lines of the service is returned.
+pub fn stop_service_checked(service: String, timeout: u64) -> Result<()> {
+    if let Ok(req) =
+        NodeRequest::new::<(String, u64)>(Node::Service(SubCommand::Stop), (service, timeout))
+    {
+        run_roxy::<String>(req).map(|_| ())
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
 /// Returns a hostname.
 #[must_use]
 pub fn hostname() -> String {
     gethostname::gethostname().to_string_lossy().into_owned()
 }
 
-/// Sets a version for OS.
+/// Returns the current hostname read by roxy, e.g. to show the hostname
+/// right after `set_hostname`. Use `hostname` to read it without roxy.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+pub fn hostname_remote() -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Hostname(SubCommand::Get), None) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the static host entries in `/etc/hosts`, as IP addresses and
+/// their hostnames.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to open `/etc/hosts`, then an error is returned.
+pub fn hosts() -> Result<Vec<(String, Vec<String>)>> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Hosts(SubCommand::Get), None) {
+        run_roxy::<Vec<(String, Vec<String>)>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets the hostnames of the IP address in `/etc/hosts`. The existing entry
+/// of the address is replaced, so setting the same entry again changes
+/// nothing.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the IP address or a hostname is invalid, or `names` is empty, then
+///   an error is returned.
+/// * If it fails to write `/etc/hosts`, then an error is returned.
+pub fn set_host_entry(ip: String, names: Vec<String>) -> Result<String> {
+    if let Ok(req) =
+        NodeRequest::new::<(String, Vec<String>)>(Node::Hosts(SubCommand::Set), (ip, names))
+    {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Removes the entry of the IP address, or the hostname from every entry, in
+/// `/etc/hosts`. Nothing is changed if neither is found.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to write `/etc/hosts`, then an error is returned.
+pub fn remove_host_entry(ip_or_name: String) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Hosts(SubCommand::Delete), ip_or_name) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets a version for OS.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If reading or writing of an OS version file, [`common::VERSION_PATH`],
+///   fails, then an error is returned.
+pub fn set_os_version(ver: String) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Version(SubCommand::SetOsVersion), ver) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets a version for product.
+///
+/// # Errors
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If reading or writing of a product version file,
+///   [`common::VERSION_PATH`], fails, then an error is returned.
+pub fn set_product_version(ver: String) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Version(SubCommand::SetProductVersion), ver) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets a hostname.
+///
+/// # Errors
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If `hostname::set` fails, then an error is returned.
+pub fn set_hostname(host: String) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Hostname(SubCommand::Set), host) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets the pretty hostname of systemd, a free-form name for display. The
+/// static hostname set by `set_hostname` is not changed. An empty name
+/// removes the pretty hostname.
+///
+/// # Errors
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the name has a control character, or `hostnamectl` fails, then an
+///   error is returned.
+pub fn set_pretty_hostname(name: String) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Hostname(SubCommand::SetPretty), name) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the sshd port number.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to open `/etc/ssh/sshd_config`, then an error is returned.
+pub fn sshd_port() -> Result<u16> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Sshd(SubCommand::Get), None) {
+        run_roxy::<u16>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets the sshd port number.
+///
+/// If `update_firewall` is true, the new port is allowed and the rule for the
+/// old port is deleted in the firewall before restarting sshd, so that the
+/// port change does not lock out remote access.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the port is invalid, then an error is returned.
+/// * If it fails to update the firewall rules, then an error is returned and
+///   the port is not changed.
+/// * If it fails to open or write `/etc/ssh/sshd_config`, then an error is
+///   returned.
+/// * If it fails to restart sshd service, then an error is returned.
+pub fn set_sshd_port(port: String, update_firewall: bool) -> Result<String> {
+    if let Ok(req) =
+        NodeRequest::new::<(String, bool)>(Node::Sshd(SubCommand::Set), (port, update_firewall))
+    {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the disk usage of the journal in bytes.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to execute `journalctl`, then an error is returned.
+pub fn journal_usage() -> Result<u64> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Journal(SubCommand::Get), None) {
+        run_roxy::<u64>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Removes archived journal files until the disk usage of the journal is
+/// below `size_mb` megabytes.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If `size_mb` is zero, then an error is returned.
+/// * If it fails to execute `journalctl`, then an error is returned.
+pub fn journal_vacuum(size_mb: u64) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<JournalVacuum>(
+        Node::Journal(SubCommand::Delete),
+        JournalVacuum::Size(size_mb),
+    ) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Removes archived journal files older than `days` days.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If `days` is zero, then an error is returned.
+/// * If it fails to execute `journalctl`, then an error is returned.
+pub fn journal_vacuum_time(days: u32) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<JournalVacuum>(
+        Node::Journal(SubCommand::Delete),
+        JournalVacuum::Time(days),
+    ) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the logrotate policy of `/var/log/aice/<service>.log`, or `None` if
+/// it is not set.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If `service` is invalid, then an error is returned.
+/// * If `/etc/logrotate.d/<service>` is not written by `set_log_rotation`,
+///   then an error is returned.
+pub fn log_rotation(service: String) -> Result<Option<LogRotation>> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Logrotate(SubCommand::Get), service) {
+        run_roxy::<Option<LogRotation>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Rotates `/var/log/aice/<service>.log` when it grows bigger than
+/// `max_size_mb` megabytes, keeping `keep` rotated files. The policy is
+/// written to `/etc/logrotate.d/<service>`.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If `service` is invalid, or `max_size_mb` or `keep` is zero or too big,
+///   then an error is returned.
+/// * If `/etc/logrotate.d/<service>` exists but is not written by roxy, then
+///   an error is returned.
+pub fn set_log_rotation(service: String, max_size_mb: u32, keep: u32) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<(String, LogRotation)>(
+        Node::Logrotate(SubCommand::Set),
+        (service, LogRotation { max_size_mb, keep }),
+    ) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the value of a kernel parameter, e.g. `net.core.rmem_max`.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the key is invalid or unknown, then an error is returned.
+pub fn sysctl(key: String) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Sysctl(SubCommand::Get), key) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets a kernel parameter persistently. The parameter is written to
+/// `/etc/sysctl.d/99-roxy.conf` and loaded immediately.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the key is invalid or unknown, then an error is returned.
+/// * If it fails to write `/etc/sysctl.d/99-roxy.conf`, then an error is
+///   returned.
+/// * If it fails to execute `sysctl`, then an error is returned.
+pub fn set_sysctl(key: String, value: String) -> Result<String> {
+    if let Ok(req) =
+        NodeRequest::new::<(String, String)>(Node::Sysctl(SubCommand::Set), (key, value))
+    {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the login banner sshd shows before authentication. `None` if no
+/// banner is set.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to open `/etc/ssh/sshd_config` or `/etc/issue.net`, then an
+///   error is returned.
+pub fn login_banner() -> Result<Option<String>> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Banner(SubCommand::Get), None) {
+        run_roxy::<Option<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets the login banner sshd shows before authentication. The text is
+/// written to `/etc/issue.net`, and sshd is configured to show it.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the text contains a NUL character, then an error is returned.
+/// * If it fails to write `/etc/issue.net` or `/etc/ssh/sshd_config`, then
+///   an error is returned.
+/// * If it fails to restart sshd service, then an error is returned.
+pub fn set_login_banner(text: String) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Banner(SubCommand::Set), text) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the kernel modules loaded at boot, in the order they are loaded.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to read `/etc/modules-load.d/roxy.conf`, then an error is
+///   returned.
+pub fn kernel_modules() -> Result<Vec<String>> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Modules(SubCommand::Get), None) {
+        run_roxy::<Vec<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets the kernel modules loaded at boot, replacing the existing ones. The
+/// modules are written to `/etc/modules-load.d/roxy.conf` and loaded
+/// immediately.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If a module name is invalid or the module does not exist, then an
+///   error is returned and nothing is changed.
+/// * If it fails to write `/etc/modules-load.d/roxy.conf`, then an error is
+///   returned.
+/// * If it fails to load a module with `modprobe`, then an error is
+///   returned.
+pub fn set_kernel_modules(modules: Vec<String>) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<Vec<String>>(Node::Modules(SubCommand::Set), modules) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns NTP server addresses.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to open `/etc/ntp.conf`, then an error is returned.
+pub fn ntp_servers() -> Result<Option<Vec<String>>> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Ntp(SubCommand::Get), None) {
+        run_roxy::<Option<Vec<String>>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the peers of the NTP daemon with their stratum, reachability,
+/// delay, offset, and jitter.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to execute `ntpq` or its output is not recognized, then an
+///   error is returned.
+pub fn ntp_peers() -> Result<Vec<NtpPeer>> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Ntp(SubCommand::List), None) {
+        run_roxy::<Vec<NtpPeer>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets NTP server addresses.
+///
+/// If `check` is true, each server is checked whether it responds to an NTP
+/// request before it's written. Unreachable servers are not an error; they
+/// are returned as warnings, so the caller can decide whether to keep them.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to open or write `/etc/ntp.conf`, then an error is returned.
+/// * If it fails to restart ntp service, then an error is returned.
+pub fn set_ntp_servers(servers: Vec<String>, check: bool) -> Result<Vec<String>> {
+    if let Ok(req) =
+        NodeRequest::new::<(Vec<String>, bool)>(Node::Ntp(SubCommand::Set), (servers, check))
+    {
+        run_roxy::<Vec<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets whether the NTP daemon serves time to the clients in
+/// `allow_networks`, e.g. "192.168.10.0/24", such as the downstream devices
+/// of an isolated site. The other hosts are denied time service either way.
+/// `allow_networks` is ignored if `enabled` is false. This does not change
+/// the NTP servers this system syncs with.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If a network is not a valid IP network, or `enabled` is true with no
+///   network, then an error is returned.
+/// * If it fails to open or write `/etc/ntp.conf`, then an error is returned.
+/// * If it fails to restart ntp service, then an error is returned.
+pub fn set_ntp_serve(enabled: bool, allow_networks: Vec<String>) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<(bool, Vec<String>)>(
+        Node::Ntp(SubCommand::SetServe),
+        (enabled, allow_networks),
+    ) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns remote syslog servers. `None` if no server is set.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to open `/etc/rsyslog.d/50-default.conf`, then an error
+///   is returned.
+pub fn syslog_servers() -> Result<Option<Vec<SyslogServer>>> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Syslog(SubCommand::Get), None) {
+        run_roxy::<Option<Vec<SyslogServer>>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets remote syslog servers, replacing the existing ones.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to open or write `/etc/rsyslog.d/50-default.conf`, then
+///   an error is returned.
+/// * If rsyslogd rejects the new configuration, then the previous one is
+///   restored and an error is returned.
+/// * If it fails to restart rsyslogd service, then an error is returned.
+pub fn set_syslog_servers(servers: Vec<SyslogServer>) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<Vec<SyslogServer>>(Node::Syslog(SubCommand::Set), servers) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Restarts rsyslogd after checking its configuration with `rsyslogd -N1`.
+/// Use this after editing the configuration out-of-band. rsyslogd is not
+/// restarted if the configuration is invalid.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If rsyslogd rejects the configuration, then an error is returned.
+/// * If it fails to restart rsyslogd service, then an error is returned.
+pub fn syslog_reload() -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Syslog(SubCommand::Update), None) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Initiates syslog servers, i.e. removes the servers `syslog_servers`
+/// returns. Forwarding rules it cannot parse, e.g. with a hostname, are kept.
+/// Use `clear_syslog_servers` to remove them as well.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to open or write `/etc/rsyslog.d/50-default.conf`, then
+///   an error is returned.
+/// * If rsyslogd rejects the new configuration, then the previous one is
+///   restored and an error is returned.
+/// * If it fails to restart rsyslogd service, then an error is returned.
+pub fn init_syslog_servers() -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Syslog(SubCommand::Init), None) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Removes all remote syslog servers, so that rsyslogd only logs locally.
+/// Unlike `init_syslog_servers`, this also removes the forwarding rules
+/// `syslog_servers` cannot parse, e.g. with a hostname or an `omfwd` action.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to open or write `/etc/rsyslog.d/50-default.conf`, then
+///   an error is returned.
+/// * If rsyslogd rejects the new configuration, then the previous one is
+///   restored and an error is returned.
+/// * If it fails to restart rsyslogd service, then an error is returned.
+pub fn clear_syslog_servers() -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Syslog(SubCommand::Clear), None) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Flushes the DNS cache of systemd-resolved, so that name resolution uses
+/// the nameservers set by `set_interface` immediately. systemd-resolved is
+/// restarted if its cache cannot be flushed with `resolvectl`.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to restart systemd-resolved, then an error is returned.
+pub fn flush_dns() -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Dns(SubCommand::Flush), None) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the global DNS servers of systemd-resolved, which are used
+/// regardless of interfaces.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to read `/etc/systemd/resolved.conf`, then an error is
+///   returned.
+pub fn global_dns_servers() -> Result<Vec<String>> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Dns(SubCommand::Get), None) {
+        run_roxy::<Vec<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the DNS servers and domains systemd-resolved uses for the
+/// interface now, which may differ from netplan yaml until it is applied.
+///
+/// # Return
+///
+/// * (Vec<String>, Vec<String>): DNS servers and domains. Empty if not set.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the interface does not exist or `resolvectl` fails, then an error
+///   is returned.
+pub fn link_dns(dev: String) -> Result<(Vec<String>, Vec<String>)> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Dns(SubCommand::GetLink), dev) {
+        run_roxy::<(Vec<String>, Vec<String>)>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets the DNS servers and domains of the interface in systemd-resolved
+/// immediately, for split DNS. Domains starting with "~" are routing-only,
+/// e.g. "~corp.example.com" sends only the queries for it to these servers.
+/// Empty `servers` or `domains` removes them.
+///
+/// The setting is lost when systemd-resolved restarts or netplan is applied.
+/// Use `set_interface` to keep it.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If a server address or domain is invalid, then an error is returned.
+/// * If the interface does not exist or `resolvectl` fails, then an error
+///   is returned.
+pub fn set_link_dns(dev: String, servers: Vec<String>, domains: Vec<String>) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<(String, Vec<String>, Vec<String>)>(
+        Node::Dns(SubCommand::SetLink),
+        (dev, servers, domains),
+    ) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets the global DNS servers of systemd-resolved, and restarts it. Unlike
+/// the nameservers of `set_interface`, these are not tied to an interface.
+/// Empty `servers` removes them.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If a server address is invalid, then an error is returned.
+/// * If it fails to write `/etc/systemd/resolved.conf` or restart
+///   systemd-resolved, then an error is returned.
+pub fn set_global_dns_servers(servers: Vec<String>) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<Vec<String>>(Node::Dns(SubCommand::Set), servers) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets both the global DNS servers and search domains of systemd-resolved at
+/// once, and restarts it, so that a failure does not leave only one of them
+/// changed. Invalid servers and domains are skipped, and the valid ones are
+/// set. Empty `servers` or `search` removes them.
+///
+/// # Return
+///
+/// * the rejected servers and domains. Empty if all are valid.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If every server, or every domain, is invalid, then an error is returned
+///   and nothing is changed.
+/// * If it fails to write `/etc/systemd/resolved.conf` or restart
+///   systemd-resolved, then an error is returned.
+pub fn set_global_dns(servers: Vec<String>, search: Vec<String>) -> Result<Vec<String>> {
+    if let Ok(req) = NodeRequest::new::<(Vec<String>, Vec<String>)>(
+        Node::Dns(SubCommand::SetWithSearch),
+        (servers, search),
+    ) {
+        run_roxy::<Vec<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the DNS servers and search domains systemd-resolved uses at
+/// runtime, globally and on each link. Unlike the getters of the settings,
+/// this shows the result of netplan, DHCP, and `resolved.conf` together.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to execute `resolvectl`, e.g. systemd-resolved is not
+///   running, then an error is returned.
+pub fn effective_dns() -> Result<EffectiveDns> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Dns(SubCommand::Status), None) {
+        run_roxy::<EffectiveDns>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the global search domains of systemd-resolved, which are used
+/// regardless of interfaces.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to read `/etc/systemd/resolved.conf`, then an error is
+///   returned.
+pub fn global_search_domains() -> Result<Vec<String>> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Dns(SubCommand::GetSearch), None) {
+        run_roxy::<Vec<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets the global search domains of systemd-resolved, and restarts it.
+/// Unlike the search domains in netplan, these are not tied to an interface.
+/// Empty `domains` removes them.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If a domain is not a valid DNS name, then an error is returned.
+/// * If it fails to write `/etc/systemd/resolved.conf` or restart
+///   systemd-resolved, then an error is returned.
+pub fn set_global_search_domains(domains: Vec<String>) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<Vec<String>>(Node::Dns(SubCommand::SetSearch), domains) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the list of interface names.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+pub fn list_of_interfaces(prefix: Option<String>) -> Result<Vec<String>> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::List), prefix) {
+        run_roxy::<Vec<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the settings of interface. All interfafces if None for device name
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If no netplan yaml file is parsed, then an error with
+///   `ErrorCode::NetplanUnreadable`, `ErrorCode::NoNetplanYaml`, or
+///   `ErrorCode::NetplanInvalid` is returned.
+///
+/// Netplan yaml files failing to be parsed are skipped. Use
+/// `interfaces_with_errors` to get them.
+pub fn interfaces(dev: Option<String>) -> Result<Option<Vec<(String, NicOutput)>>> {
+    interfaces_with_errors(dev).map(|(nics, _)| nics)
+}
+
+/// Same as `interfaces`, but also returns the netplan yaml files skipped
+/// because they fail to be parsed, with their error messages.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If no netplan yaml file is parsed, then an error with
+///   `ErrorCode::NetplanUnreadable`, `ErrorCode::NoNetplanYaml`, or
+///   `ErrorCode::NetplanInvalid` is returned.
+#[allow(clippy::type_complexity)]
+pub fn interfaces_with_errors(
+    dev: Option<String>,
+) -> Result<(Option<Vec<(String, NicOutput)>>, Vec<(String, String)>)> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::Get), dev) {
+        run_roxy::<(Option<Vec<(String, NicOutput)>>, Vec<(String, String)>)>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns each netplan yaml file with its parsed contents, without merging
+/// them. This helps to find which file introduced a bad setting.
+///
+/// Each item is a file name and either the parsed configuration in yaml or
+/// the error message if the file fails to be parsed.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to read the netplan directory, then an error is returned.
+pub fn netplan_configs() -> Result<Vec<(String, std::result::Result<String, String>)>> {
+    if let Ok(req) =
+        NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::ListConfigs), None)
+    {
+        run_roxy_streaming::<Vec<(String, std::result::Result<String, String>)>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the whole network configuration, including ethernets, bridges,
+/// vlans, and their routes. Use `import_network` to restore it.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to load netplan yaml files, then an error is returned.
+pub fn export_network() -> Result<NetworkSnapshot> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::Export), None) {
+        let json = run_roxy_streaming::<String>(req)?;
+        Ok(serde_json::from_str::<NetworkSnapshot>(&json)?)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Writes the minimal netplan yaml with version 2 and the renderer,
+/// "networkd" or "NetworkManager", if no netplan yaml file exists. On such a
+/// system, `set_interface` starts from this, or from "networkd" without it.
+/// Nothing is applied.
+///
+/// # Return
+///
+/// * true if the yaml file is written, false if a netplan yaml file already
+///   exists.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the renderer is invalid, then an error is returned.
+/// * If the netplan directory is unreadable or has only invalid yaml files,
+///   then an error is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn bootstrap_netplan(renderer: String) -> Result<bool> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Interface(SubCommand::Bootstrap), renderer) {
+        run_roxy::<bool>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Compares the network configuration with the snapshot, without changing
+/// it. This shows what `import_network` of the snapshot will change: the
+/// snapshot is the new configuration in the result.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to load netplan yaml files, then an error is returned.
+pub fn diff_network(snapshot: &NetworkSnapshot) -> Result<NetplanDiff> {
+    let json = serde_json::to_string(snapshot)?;
+    if let Ok(req) = NodeRequest::new::<String>(Node::Interface(SubCommand::Diff), json) {
+        run_roxy::<NetplanDiff>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Compares the netplan yaml files with the running interfaces, to show the
+/// changes which are written but not applied yet. Netplan saves a setting
+/// without applying it to an unplugged link, for example. The running
+/// interfaces are the old configuration in the result, and the yaml files
+/// are the new one. Only the existence and the addresses of interfaces are
+/// compared.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to load netplan yaml files, then an error is returned.
+pub fn pending_network_changes() -> Result<NetplanDiff> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::Pending), None)
+    {
+        run_roxy::<NetplanDiff>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Replaces the whole network configuration with the snapshot, and applies
+/// it.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If an interface address or gateway address is invalid, then an error
+///   is returned.
+/// * If the parameters of a bridge are invalid, then an error is returned.
+/// * If it fails to write or apply netplan yaml files, then an error is
+///   returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn import_network(snapshot: &NetworkSnapshot) -> Result<String> {
+    let json = serde_json::to_string(snapshot)?;
+    if let Ok(req) = NodeRequest::new::<String>(Node::Interface(SubCommand::Import), json) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Saves the netplan yaml files into a gzipped tarball at `dest`, which should
+/// be an absolute path ending with ".tar.gz".
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If `dest` is invalid, then an error is returned.
+/// * If it fails to read netplan yaml files or write the tarball, then an
+///   error is returned.
+pub fn backup_netplan(dest: String) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Interface(SubCommand::Backup), dest) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Replaces the netplan yaml files with the ones in the tarball made by
+/// `backup_netplan`, and applies them. The tarball is rejected if it contains
+/// anything other than yaml files.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If `src` is invalid or the tarball contains a file other than a yaml
+///   file, then an error is returned.
+/// * If it fails to write netplan yaml files, then an error is returned.
+/// * If it fails to execute `netplan apply`, then an error is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn restore_netplan(src: String) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Interface(SubCommand::Restore), src) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Restores a config file roxy writes, e.g. `/etc/ntp.conf`, from the snapshot
+/// taken before the last write, and returns "Ok". Roxy keeps the last 10
+/// snapshots of each file under `/var/lib/roxy/backup`. The service reading
+/// the file is not restarted.
+///
+/// The files accepted are `/etc/hosts`, `/etc/issue.net`, `/etc/ntp.conf`,
+/// `/etc/ssh/sshd_config`, `/etc/systemd/resolved.conf`, `/etc/version`,
+/// `/etc/modules-load.d/roxy.conf`, `/etc/sysctl.d/99-roxy.conf`,
+/// `/etc/rsyslog.d/50-default.conf`, and `/etc/logrotate.d/<service>`. Use
+/// `restore_netplan` for the netplan yaml files.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If `path` is not one of the files above, then an error is returned.
+/// * If the file has no snapshot or fails to be overwritten, then an error is
+///   returned.
+pub fn restore_config(path: String) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Backup(SubCommand::Restore), path) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the name of the netplan yaml file which defines the interface, or
+/// `None` if no file defines it.
+///
+/// Files are merged in the order of their names, and a later file overrides
+/// an earlier one. If several files define the interface, the last one is
+/// returned because its definition takes effect.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to read the netplan directory, then an error is returned.
+pub fn interface_source_file(dev: String) -> Result<Option<String>> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Interface(SubCommand::SourceFile), dev) {
+        run_roxy::<Option<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the ring buffer sizes of the network adapter, with the maximums
+/// the hardware supports.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the interface does not exist or `ethtool -g` fails, then an error is
+///   returned.
+pub fn ring_size(dev: String) -> Result<RingSize> {
+    if let Ok(req) = NodeRequest::new::<String>(Node::Interface(SubCommand::GetRing), dev) {
+        run_roxy::<RingSize>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets the ring buffer sizes of the network adapter, e.g. to capture at high
+/// packet rates. The sizes are lost on reboot.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If `rx` or `tx` is zero or larger than the maximum of the hardware, then
+///   an error is returned.
+/// * If the interface does not exist or `ethtool -G` fails, then an error is
+///   returned.
+pub fn set_ring_size(dev: String, rx: u32, tx: u32) -> Result<String> {
+    if let Ok(req) =
+        NodeRequest::new::<(String, u32, u32)>(Node::Interface(SubCommand::SetRing), (dev, rx, tx))
+    {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the netplan yaml merged from all netplan yaml files, as netplan
+/// sees it, e.g. to attach to a bug report. Wifi passwords are masked.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If no netplan yaml file is loaded, or a netplan yaml file is invalid,
+///   then an error is returned.
+pub fn merged_netplan_yaml() -> Result<String> {
+    if let Ok(req) =
+        NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::GetMerged), None)
+    {
+        run_roxy_streaming::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the effective renderer of netplan, "networkd" or
+/// "NetworkManager". It is `renderer` of the merged netplan yaml files, or
+/// "networkd" if no file sets it. The renderer decides how the interface
+/// settings take effect.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If no netplan yaml file is loaded, then an error is returned.
+pub fn active_renderer() -> Result<String> {
+    if let Ok(req) =
+        NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::GetRenderer), None)
+    {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Checks the netplan yaml files by generating the backend configuration
+/// with `netplan generate`, without applying it. The running interfaces are
+/// not changed.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If `netplan generate` fails, e.g. for an invalid yaml file, then an
+///   error is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn generate_netplan() -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::Generate), None)
+    {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns the interfaces whose running addresses differ from netplan yaml,
+/// e.g. an address added by `ip addr add` manually.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to load netplan yaml files, then an error is returned.
+pub fn interface_drift() -> Result<Vec<(String, AddressDrift)>> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::Sync), None) {
+        run_roxy::<Vec<(String, AddressDrift)>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Compares the addresses and the gateway of an interface setting with the
+/// running interface, to confirm that `set_interface` took effect. Netplan
+/// saves the setting without applying it to an unplugged link, so the items
+/// are returned as unmatched then.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the interface is not found, then an error is returned.
+/// * If an address of the setting is invalid, then an error is returned.
+/// * If it fails to execute `ip route`, then an error is returned.
+///
+/// # Example
+///
+/// ```ignore
+/// let ret = roxy::verify_interface("eno3".to_string(), nic)?;
+/// if !ret.is_ok() {
+///     println!("not applied: {}", ret.unmatched.join(", "));
+/// }
+/// ```
+pub fn verify_interface(dev: String, nic: NicOutput) -> Result<Verification> {
+    if let Ok(req) =
+        NodeRequest::new::<(String, NicOutput)>(Node::Interface(SubCommand::Verify), (dev, nic))
+    {
+        run_roxy::<Verification>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets an interface setting.
+///
+/// If `force` is false, the interface must exist in the system. Set `force`
+/// to write the setting for an interface that is not detected yet, e.g. one
+/// that appears only after cabling.
+///
+/// If `optional` is true, booting does not wait for the interface to be
+/// online. This avoids a boot delay by a disconnected interface.
+///
+/// Nameservers are saved in the given order, which is the order the resolver
+/// tries them. Duplicated nameservers are removed, keeping the first one.
+/// Returns warnings if the setting is applied but may not work as expected,
+/// e.g. more than [`common::MAX_NAMESERVERS`] nameservers are set.
+///
+/// If `check_gateway` is true, `gateway4` is pinged after the setting is
+/// applied, and a warning is returned if it does not respond, e.g. because
+/// the gateway address is wrong. The setting is kept either way, so that it
+/// can be written for a link that is not up yet.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to read or write a netplan yaml conf file, then an error
+///   is returned.
+/// * If dhcp4 and static ip address or nameserver address is set in the same
+///   interface, then an error is returned.
+/// * If a user tries to set a new gateway address when another interface has
+///   the same, then an error is returned, unless both gateways have distinct
+///   metrics.
+/// * If the interface is not found and `force` is false, then an error is
+///   returned.
+/// * If `gateway4` is not an IPv4 address, then an error is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+///
+/// Both IPv4 and IPv6 addresses are accepted in `addresses`. Use
+/// `validate_address_family` beforehand to enforce a family policy.
+///
+/// If `gateway_metric` is set, `gateway4` is saved as a default route with the
+/// metric, so that another interface can have a backup gateway with a higher
+/// metric.
+#[allow(clippy::too_many_arguments)]
+pub fn set_interface(
+    dev: String,
+    addresses: Option<Vec<String>>,
+    dhcp4: Option<bool>,
+    gateway4: Option<String>,
+    gateway_metric: Option<u32>,
+    nameservers: Option<Vec<String>>,
+    optional: Option<bool>,
+    force: bool,
+    check_gateway: bool,
+) -> Result<Vec<String>> {
+    let nic = NicOutput::new(
+        addresses,
+        dhcp4,
+        gateway4,
+        nameservers,
+        optional,
+        gateway_metric,
+    );
+    if let Ok(req) = NodeRequest::new::<(String, NicOutput, bool, bool)>(
+        Node::Interface(SubCommand::Set),
+        (dev, nic, force, check_gateway),
+    ) {
+        run_roxy::<Vec<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Adds nameservers to an interface, keeping the nameservers already set in
+/// it, e.g. by another controller. The nameservers already in it are skipped.
+/// Returns warnings like `set_interface`.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If a nameserver is not an IP address, then an error is returned.
+/// * If the interface is not found in netplan, or uses dhcp4, then an error
+///   is returned.
+/// * If it fails to read or write a netplan yaml conf file, then an error
+///   is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn add_nameservers(dev: String, nameservers: Vec<String>) -> Result<Vec<String>> {
+    if let Ok(req) = NodeRequest::new::<(String, Vec<String>)>(
+        Node::Interface(SubCommand::AddNameservers),
+        (dev, nameservers),
+    ) {
+        run_roxy::<Vec<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Adds search domains to an interface, keeping the domains already set in
+/// it. The domains already in it are skipped.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If a domain is not a valid DNS name, then an error is returned.
+/// * If the interface is not found in netplan, then an error is returned.
+/// * If it fails to read or write a netplan yaml conf file, then an error
+///   is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn add_search_domains(dev: String, domains: Vec<String>) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<(String, Vec<String>)>(
+        Node::Interface(SubCommand::AddSearch),
+        (dev, domains),
+    ) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets `accept-ra` of an interface. `false` stops the interface from
+/// configuring itself from IPv6 router advertisements. `set_interface` keeps
+/// this setting.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the interface is not found in netplan, then an error is returned.
+/// * If it fails to read or write a netplan yaml conf file, then an error
+///   is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn set_accept_ra(dev: String, accept: bool) -> Result<String> {
+    if let Ok(req) =
+        NodeRequest::new::<(String, bool)>(Node::Interface(SubCommand::SetAcceptRa), (dev, accept))
+    {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Marks every ethernet interface in netplan except `except`, e.g. the
+/// management interface, as `optional: true` and applies it, so that booting
+/// does not wait for them to come up.
+///
+/// # Return
+///
+/// * the names of the interfaces marked as optional
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If an interface in `except` is not found in netplan, then an error is
+///   returned.
+/// * If it fails to read or write a netplan yaml conf file, then an error
+///   is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn set_all_optional(except: Vec<String>) -> Result<Vec<String>> {
+    if let Ok(req) =
+        NodeRequest::new::<Vec<String>>(Node::Interface(SubCommand::SetAllOptional), except)
+    {
+        run_roxy::<Vec<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets `dhcp4-overrides` and `dhcp-identifier` of a DHCP interface, e.g.
+/// `use_routes: Some(false)` to ignore the routes pushed by the DHCP server.
+/// `identifier` is "mac" or "duid". `None` removes them. `set_interface`
+/// keeps them while the interface uses DHCP.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the interface is not found in netplan, or dhcp4 is not set in it,
+///   then an error is returned.
+/// * If `identifier` is neither "mac" nor "duid", then an error is returned.
+/// * If it fails to read or write a netplan yaml conf file, then an error
+///   is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn set_dhcp_overrides(
+    dev: String,
+    overrides: Option<Dhcp4Overrides>,
+    identifier: Option<String>,
+) -> Result<String> {
+    let Ok(overrides) = overrides.map(|o| serde_json::to_string(&o)).transpose() else {
+        return Err(anyhow!(FAIL_REQUEST));
+    };
+    if let Ok(req) = NodeRequest::new::<(String, Option<String>, Option<String>)>(
+        Node::Interface(SubCommand::SetDhcpOverrides),
+        (dev, overrides, identifier),
+    ) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets the interface having the MAC address, e.g. "00:11:22:33:44:55", and
+/// names it `name`. The setting is saved with `match: macaddress` and
+/// `set-name` in netplan, so it follows the NIC instead of the kernel name.
+/// The MAC address does not need to exist in the system yet. Returns
+/// warnings like `set_interface`.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the MAC address or the name is invalid, then an error is returned.
+/// * If another interface already has the name, or another netplan entry
+///   already matches the MAC address, then an error is returned.
+/// * If the setting fails the checks of `set_interface`, then an error is
+///   returned.
+/// * If it fails to read or write a netplan yaml conf file, then an error
+///   is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn set_interface_by_mac(mac: String, name: String, nic: NicOutput) -> Result<Vec<String>> {
+    if let Ok(req) = NodeRequest::new::<(String, String, NicOutput)>(
+        Node::Interface(SubCommand::SetByMac),
+        (mac, name, nic),
+    ) {
+        run_roxy::<Vec<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets multiple interfaces at once. The settings are checked with the same
+/// rules as `set_interface`, and netplan is applied only once. If any setting
+/// is invalid, none of them is saved.
+///
+/// The gateways are checked after all settings are merged, so that a gateway
+/// can be moved from one interface to another in one call. Returns warnings
+/// prefixed with the interface name.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the same interface is set more than once, then an error is returned.
+/// * If any setting fails the checks of `set_interface`, then an error is
+///   returned.
+/// * If it fails to read or write a netplan yaml conf file, then an error
+///   is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn set_interfaces(interfaces: Vec<(String, NicOutput)>, force: bool) -> Result<Vec<String>> {
+    if let Ok(req) = NodeRequest::new::<(Vec<(String, NicOutput)>, bool)>(
+        Node::Interface(SubCommand::SetMany),
+        (interfaces, force),
+    ) {
+        run_roxy::<Vec<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets addresses, gateway, and nameservers to the running interface
+/// immediately, without netplan. This works on a link that netplan ignores,
+/// e.g. an unplugged one, but the change is lost after reboot. Use
+/// `set_interface` to save it.
+///
+/// Addresses are added to the existing ones. The gateway replaces the default
+/// route.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the interface is not found, then an error is returned.
+/// * If an address is invalid or `gateway4` is not an IPv4 address, then an
+///   error is returned.
+/// * If it fails to execute `ip` or `resolvectl`, then an error is returned.
+pub fn apply_interface_runtime(
+    dev: String,
+    addresses: Option<Vec<String>>,
+    gateway4: Option<String>,
+    gateway_metric: Option<u32>,
+    nameservers: Option<Vec<String>>,
+) -> Result<String> {
+    let nic = NicOutput::new(addresses, None, gateway4, nameservers, None, gateway_metric);
+    if let Ok(req) =
+        NodeRequest::new::<(String, NicOutput)>(Node::Interface(SubCommand::Apply), (dev, nic))
+    {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Returns wifi interfaces in netplan. Passwords are replaced with
+/// `PASSWORD_MASK` unless `show_password` is true.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If it fails to read netplan yaml files, then an error is returned.
+pub fn wifis(show_password: bool) -> Result<Vec<(String, Wifi)>> {
+    if let Ok(req) = NodeRequest::new::<bool>(Node::Wifi(SubCommand::Get), show_password) {
+        let json = run_roxy::<String>(req)?;
+        Ok(serde_json::from_str(&json)?)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets a wifi interface to connect to the access point `ssid`. `password` is
+/// `None` for an open network. This will OVERWRITE all existing setting of
+/// the wifi interface.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If `ssid` is empty or longer than 32 bytes, then an error is returned.
+/// * If `password` is not 8 to 63 characters, then an error is returned.
+/// * If dhcp4 and static ip address is set, then an error is returned.
+/// * If it fails to write or apply netplan yaml files, then an error is
+///   returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn set_wifi(
+    dev: String,
+    ssid: String,
+    password: Option<String>,
+    addresses: Option<Vec<String>>,
+    dhcp4: Option<bool>,
+    gateway4: Option<String>,
+    nameservers: Option<Vec<String>>,
+) -> Result<String> {
+    let nic = NicOutput::new(addresses, dhcp4, gateway4, nameservers, None, None);
+    if let Ok(req) = NodeRequest::new::<(String, String, Option<String>, NicOutput)>(
+        Node::Wifi(SubCommand::Set),
+        (dev, ssid, password, nic),
+    ) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Checks the interface setting with the same rules as `set_interface`,
+/// without saving it.
+///
+/// # Return
+///
+/// * `None` if the setting is valid, or the reason why it is invalid.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+pub fn validate_interface(
+    dev: String,
+    addresses: Option<Vec<String>>,
+    dhcp4: Option<bool>,
+    gateway4: Option<String>,
+    gateway_metric: Option<u32>,
+    nameservers: Option<Vec<String>>,
+) -> Result<Option<String>> {
+    let nic = NicOutput::new(
+        addresses,
+        dhcp4,
+        gateway4,
+        nameservers,
+        None,
+        gateway_metric,
+    );
+    if let Ok(req) =
+        NodeRequest::new::<(String, NicOutput)>(Node::Interface(SubCommand::Validate), (dev, nic))
+    {
+        run_roxy::<Option<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Checks the interface setting like `validate_interface`, and also with
+/// netplan itself. The netplan yaml merged with the setting is written to a
+/// temporary directory, and `netplan generate` runs against it. Neither the
+/// netplan yaml files nor the running interfaces are changed.
+///
+/// # Return
+///
+/// * `None` if the setting is valid, or the reason why it is invalid, e.g.
+///   the error of `netplan generate`.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+pub fn validate_interface_with_netplan(dev: String, nic: NicOutput) -> Result<Option<String>> {
+    if let Ok(req) = NodeRequest::new::<(String, NicOutput)>(
+        Node::Interface(SubCommand::ValidateGenerate),
+        (dev, nic),
+    ) {
+        run_roxy::<Option<String>>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Sets the description of an interface, e.g. "management" or "capture".
+/// `None` or an empty string removes it. The description is returned in
+/// `NicOutput` by `interfaces`.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If `description` is longer than 256 characters or has control
+///   characters, then an error is returned.
+/// * If it fails to save the description, then an error is returned.
+pub fn set_interface_description(dev: String, description: Option<String>) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<(String, Option<String>)>(
+        Node::Interface(SubCommand::SetDescription),
+        (dev, description),
+    ) {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Init the settings of an interface.
+///
+/// # Errors
+/// * If serialization of command arguments does not succeed, then an error
+///   is returned.
+/// * If spawning the roxy executable fails, then an error is returned.
+/// * If delivering a command to roxy fails, then an error is returned.
+/// * If a response message from roxy is invalid regarding JSON syntax or
+///   is not successfully base64-decoded, then an error is returned.
+/// * If the specified interface name is not found, then an error is returned.
+/// * If it failed to load /etc/netplan yaml files, then an error is returned.
+/// * If if failed to execute netplan apply command, then an error is returned.
+/// * If it failed to execute ifconfig command, then an error is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn init_interface(dev: String) -> Result<String> {
+    if let Ok(req) =
+        NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::Init), Some(dev))
+    {
+        run_roxy::<String>(req)
+    } else {
+        Err(anyhow!(FAIL_REQUEST))
+    }
+}
+
+/// Removes the whole setting of an interface from netplan, e.g. to
+/// decommission a NIC. Unlike `init_interface`, the interface is not left in
+/// the yaml files. Use `remove_interface` to remove only some addresses.
 ///
-/// # Errors
-///
-/// The following errors are possible:
+/// The interface carrying the active default route is not removed unless
+/// `force` is set.
 ///
+/// # Errors
 /// * If serialization of command arguments does not succeed, then an error
 ///   is returned.
 /// * If spawning the roxy executable fails, then an error is returned.
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-/// * If reading or writing of an OS version file fails, then an error
-///   is returned.
-pub fn set_os_version(ver: String) -> Result<String> {
-    if let Ok(req) = NodeRequest::new::<String>(Node::Version(SubCommand::SetOsVersion), ver) {
+/// * If the specified interface is not found in netplan, then an error is
+///   returned.
+/// * If the interface carries the active default route and `force` is not
+///   set, then an error is returned.
+/// * If it failed to load /etc/netplan yaml files, then an error is returned.
+/// * If if failed to execute netplan apply command, then an error is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn purge_interface(dev: String, force: bool) -> Result<String> {
+    if let Ok(req) =
+        NodeRequest::new::<(String, bool)>(Node::Interface(SubCommand::Remove), (dev, force))
+    {
         run_roxy::<String>(req)
     } else {
         Err(anyhow!(FAIL_REQUEST))
     }
 }
 
-/// Sets a version for product.
+/// Renames a running interface, e.g. "enp3s0" to "lan0", to standardize
+/// interface names across hardware. Netplan matches the MAC address of the
+/// interface and names it with `set-name`, so the name follows the NIC. Its
+/// setting, the bridges and vlans on it, and its description are kept under
+/// the new name.
+///
+/// The interface carrying the active default route is not renamed unless
+/// `force` is set.
 ///
 /// # Errors
 ///
+/// The following errors are possible:
+///
 /// * If serialization of command arguments does not succeed, then an error
 ///   is returned.
 /// * If spawning the roxy executable fails, then an error is returned.
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-/// * If reading or writing of a product version file fails, then an error
+/// * If `new` is not a valid interface name, then an error is returned.
+/// * If `old` is not running, or `new` is already running or defined in
+///   netplan, then an error is returned.
+/// * If `old` carries the active default route and `force` is not set, then
+///   an error is returned.
+/// * If it fails to read or write a netplan yaml conf file, then an error
 ///   is returned.
-pub fn set_product_version(ver: String) -> Result<String> {
-    if let Ok(req) = NodeRequest::new::<String>(Node::Version(SubCommand::SetProductVersion), ver) {
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn rename_interface(old: String, new: String, force: bool) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<(String, String, bool)>(
+        Node::Interface(SubCommand::Rename),
+        (old, new, force),
+    ) {
         run_roxy::<String>(req)
     } else {
         Err(anyhow!(FAIL_REQUEST))
     }
 }
 
-/// Sets a hostname.
+/// Removes interface/gateway/nameserver address or dhcp4 option of interface.
 ///
 /// # Errors
 ///
+/// The following errors are possible:
+///
 /// * If serialization of command arguments does not succeed, then an error
 ///   is returned.
 /// * If spawning the roxy executable fails, then an error is returned.
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-/// * If `hostname::set` fails, then an error is returned.
-pub fn set_hostname(host: String) -> Result<String> {
-    if let Ok(req) = NodeRequest::new::<String>(Node::Hostname(SubCommand::Set), host) {
+/// * If it fails to read or write a netplan yaml conf file, then an error
+///   is returned.
+/// * If another netplan edit is in progress for a while, then an error with
+///   `ErrorCode::Busy` is returned.
+pub fn remove_interface(
+    dev: String,
+    addresses: Option<Vec<String>>,
+    dhcp4: Option<bool>,
+    gateway4: Option<String>,
+    nameservers: Option<Vec<String>>,
+) -> Result<String> {
+    let nic = NicOutput::new(addresses, dhcp4, gateway4, nameservers, None, None);
+    if let Ok(req) =
+        NodeRequest::new::<(String, NicOutput)>(Node::Interface(SubCommand::Delete), (dev, nic))
+    {
         run_roxy::<String>(req)
     } else {
         Err(anyhow!(FAIL_REQUEST))
     }
 }
 
-/// Returns tuples of (facilitiy, proto, addr) of syslog servers.
+/// Adds a firewall rule to allow the port.
 ///
 /// # Errors
 ///
@@ -105,17 +2262,19 @@ pub fn set_hostname(host: String) -> Result<String> {
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-/// * If it fails to open `/etc/rsyslog.d/50-default.conf`, then an error
-///   is returned.
-pub fn syslog_servers() -> Result<Option<Vec<(String, String, String)>>> {
-    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Syslog(SubCommand::Get), None) {
-        run_roxy::<Option<Vec<(String, String, String)>>>(req)
+/// * If it fails to execute `ufw`, then an error is returned.
+pub fn firewall_allow(port: u16, proto: FirewallProtocol) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<(FirewallAction, u16, FirewallProtocol)>(
+        Node::Ufw(SubCommand::Add),
+        (FirewallAction::Allow, port, proto),
+    ) {
+        run_roxy::<String>(req)
     } else {
         Err(anyhow!(FAIL_REQUEST))
     }
 }
 
-/// Sets syslog servers.
+/// Adds a firewall rule to deny the port.
 ///
 /// # Errors
 ///
@@ -127,18 +2286,19 @@ pub fn syslog_servers() -> Result<Option<Vec<(String, String, String)>>> {
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-/// * If it fails to open or write `/etc/rsyslog.d/50-default.conf`, then
-///   an error is returned.
-/// * If it fails to restart rsyslogd service, then an error is returned.
-pub fn set_syslog_servers(servers: Vec<String>) -> Result<String> {
-    if let Ok(req) = NodeRequest::new::<Vec<String>>(Node::Syslog(SubCommand::Set), servers) {
+/// * If it fails to execute `ufw`, then an error is returned.
+pub fn firewall_deny(port: u16, proto: FirewallProtocol) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<(FirewallAction, u16, FirewallProtocol)>(
+        Node::Ufw(SubCommand::Add),
+        (FirewallAction::Deny, port, proto),
+    ) {
         run_roxy::<String>(req)
     } else {
         Err(anyhow!(FAIL_REQUEST))
     }
 }
 
-/// Initiates syslog servers.
+/// Deletes a firewall rule.
 ///
 /// # Errors
 ///
@@ -150,18 +2310,23 @@ pub fn set_syslog_servers(servers: Vec<String>) -> Result<String> {
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-/// * If it fails to open or write `/etc/rsyslog.d/50-default.conf`, then
-///   an error is returned.
-/// * If it fails to restart rsyslogd service, then an error is returned.
-pub fn init_syslog_servers() -> Result<String> {
-    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Syslog(SubCommand::Init), None) {
+/// * If it fails to execute `ufw`, then an error is returned.
+pub fn firewall_delete(
+    action: FirewallAction,
+    port: u16,
+    proto: FirewallProtocol,
+) -> Result<String> {
+    if let Ok(req) = NodeRequest::new::<(FirewallAction, u16, FirewallProtocol)>(
+        Node::Ufw(SubCommand::Delete),
+        (action, port, proto),
+    ) {
         run_roxy::<String>(req)
     } else {
         Err(anyhow!(FAIL_REQUEST))
     }
 }
 
-/// Returns the list of interface names.
+/// Returns the firewall rules. Empty if the firewall is inactive.
 ///
 /// # Errors
 ///
@@ -173,15 +2338,18 @@ pub fn init_syslog_servers() -> Result<String> {
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-pub fn list_of_interfaces(prefix: Option<String>) -> Result<Vec<String>> {
-    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::List), prefix) {
-        run_roxy::<Vec<String>>(req)
+/// * If it fails to execute `ufw`, then an error is returned.
+pub fn firewall_status() -> Result<Vec<FirewallRule>> {
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Ufw(SubCommand::Get), None) {
+        run_roxy::<Vec<FirewallRule>>(req)
     } else {
         Err(anyhow!(FAIL_REQUEST))
     }
 }
 
-/// Returns the settings of interface. All interfafces if None for device name
+/// Returns the version and the protocol revision of the installed roxy
+/// helper. Use `RoxyInfo::is_compatible` to check whether it can serve this
+/// library.
 ///
 /// # Errors
 ///
@@ -189,19 +2357,28 @@ pub fn list_of_interfaces(prefix: Option<String>) -> Result<Vec<String>> {
 ///
 /// * If serialization of command arguments does not succeed, then an error
 ///   is returned.
-/// * If spawning the roxy executable fails, then an error is returned.
+/// * If spawning the roxy executable fails, e.g. it is not installed or not
+///   executable, then an error is returned.
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-pub fn interfaces(dev: Option<String>) -> Result<Option<Vec<(String, NicOutput)>>> {
-    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::Get), dev) {
-        run_roxy::<Option<Vec<(String, NicOutput)>>>(req)
+/// * If roxy is older than the protocol revision, then an error with
+///   `ErrorCode::ProtocolMismatch` is returned.
+pub fn ping_roxy() -> Result<RoxyInfo> {
+    // `RoxyInfo` is decoded even if the protocol revision differs.
+    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Ping, None) {
+        run_roxy_any_protocol::<RoxyInfo>(req)
     } else {
         Err(anyhow!(FAIL_REQUEST))
     }
 }
 
-/// Sets an interface setting.
+/// Checks whether the installed roxy helper implements the commands of this
+/// library, and returns a warning for each command it does not. An empty
+/// vector means every command is available.
+///
+/// A roxy older than the capabilities command cannot report its commands,
+/// and a warning for that is returned instead.
 ///
 /// # Errors
 ///
@@ -213,53 +2390,79 @@ pub fn interfaces(dev: Option<String>) -> Result<Option<Vec<(String, NicOutput)>
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-/// * If it fails to read or write a netplan yaml conf file, then an error
-///   is returned.
-/// * If dhcp4 and static ip address or nameserver address is set in the same
-///   interface, then an error is returned.
-/// * If a user tries to set a new gateway address when another interface has
-///   the same, then an error is returned.
-pub fn set_interface(
-    dev: String,
-    addresses: Option<Vec<String>>,
-    dhcp4: Option<bool>,
-    gateway4: Option<String>,
-    nameservers: Option<Vec<String>>,
-) -> Result<String> {
-    let nic = NicOutput::new(addresses, dhcp4, gateway4, nameservers);
-    if let Ok(req) =
-        NodeRequest::new::<(String, NicOutput)>(Node::Interface(SubCommand::Set), (dev, nic))
-    {
-        run_roxy::<String>(req)
-    } else {
-        Err(anyhow!(FAIL_REQUEST))
+///
+/// # Example
+///
+/// ```ignore
+/// for warning in roxy::compat_check()? {
+///     log::warn!("{warning}");
+/// }
+/// ```
+pub fn compat_check() -> Result<Vec<String>> {
+    let info = ping_roxy()?;
+    let mut warnings = Vec::new();
+    if !info.is_compatible() {
+        warnings.push(format!(
+            "roxy {} speaks protocol {}, but this library speaks {}",
+            info.version, info.protocol, PROTOCOL_VERSION
+        ));
     }
+
+    let Ok(req) = NodeRequest::new::<Option<String>>(Node::Capabilities, None) else {
+        return Err(anyhow!(FAIL_REQUEST));
+    };
+    // The commands are decoded even if the protocol revision differs.
+    let supported = match run_roxy_any_protocol::<Vec<String>>(req) {
+        Ok(supported) => supported,
+        Err(e)
+            if e.downcast_ref::<RemoteError>()
+                .is_some_and(|e| e.code == ErrorCode::InvalidCommand) =>
+        {
+            warnings.push(format!(
+                "roxy {} does not report its commands",
+                info.version
+            ));
+            return Ok(warnings);
+        }
+        Err(e) => return Err(e),
+    };
+    for spec in commands() {
+        let name = serde_json::to_string(&spec.node)?;
+        if !supported.contains(&name) {
+            warnings.push(format!(
+                "roxy {} does not support {:?}",
+                info.version, spec.node
+            ));
+        }
+    }
+    Ok(warnings)
 }
 
-/// Init the settings of an interface.
+/// Reboots the system. Same as `reboot_in(0)`.
+///
+/// This returns once roxy schedules the reboot, so the system is still
+/// running when it returns. The reboot starts about a second later.
 ///
 /// # Errors
+///
+/// The following errors are possible:
+///
 /// * If serialization of command arguments does not succeed, then an error
 ///   is returned.
 /// * If spawning the roxy executable fails, then an error is returned.
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-/// * If the specified interface name is not found, then an error is returned.
-/// * If it failed to load /etc/netplan yaml files, then an error is returned.
-/// * If if failed to execute netplan apply command, then an error is returned.
-/// * If it failed to execute ifconfig command, then an error is returned.
-pub fn init_interface(dev: String) -> Result<String> {
-    if let Ok(req) =
-        NodeRequest::new::<Option<String>>(Node::Interface(SubCommand::Init), Some(dev))
-    {
-        run_roxy::<String>(req)
-    } else {
-        Err(anyhow!(FAIL_REQUEST))
-    }
+/// * If roxy fails to schedule the reboot, then an error is returned.
+pub fn reboot() -> Result<ShutdownAck> {
+    reboot_in(0)
 }
 
-/// Removes interface/gateway/nameserver address or dhcp4 option of interface.
+/// Reboots the system after `delay_secs` seconds.
+///
+/// This returns once roxy schedules the reboot, with the time it starts.
+/// A failure of the reboot itself is not returned, because it happens after
+/// the response.
 ///
 /// # Errors
 ///
@@ -271,26 +2474,19 @@ pub fn init_interface(dev: String) -> Result<String> {
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-/// * If it fails to read or write a netplan yaml conf file, then an error
-///   is returned.
-pub fn remove_interface(
-    dev: String,
-    addresses: Option<Vec<String>>,
-    dhcp4: Option<bool>,
-    gateway4: Option<String>,
-    nameservers: Option<Vec<String>>,
-) -> Result<String> {
-    let nic = NicOutput::new(addresses, dhcp4, gateway4, nameservers);
-    if let Ok(req) =
-        NodeRequest::new::<(String, NicOutput)>(Node::Interface(SubCommand::Delete), (dev, nic))
-    {
-        run_roxy::<String>(req)
+/// * If roxy fails to schedule the reboot, then an error is returned.
+pub fn reboot_in(delay_secs: u64) -> Result<ShutdownAck> {
+    if let Ok(req) = NodeRequest::new::<u64>(Node::Reboot, delay_secs) {
+        run_roxy::<ShutdownAck>(req)
     } else {
         Err(anyhow!(FAIL_REQUEST))
     }
 }
 
-/// Reboots the system.
+/// Turns the system off. Same as `power_off_in(0)`.
+///
+/// This returns once roxy schedules the power off, so the system is still
+/// running when it returns.
 ///
 /// # Errors
 ///
@@ -302,16 +2498,14 @@ pub fn remove_interface(
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-/// * If `nix::sys::reboot::reboot` fails, then an error is returned.
-pub fn reboot() -> Result<String> {
-    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::Reboot, None) {
-        run_roxy::<String>(req)
-    } else {
-        Err(anyhow!(FAIL_REQUEST))
-    }
+/// * If roxy fails to schedule the power off, then an error is returned.
+pub fn power_off() -> Result<ShutdownAck> {
+    power_off_in(0)
 }
 
-/// Turns the system off.
+/// Turns the system off after `delay_secs` seconds.
+///
+/// This returns once roxy schedules the power off, with the time it starts.
 ///
 /// # Errors
 ///
@@ -323,15 +2517,60 @@ pub fn reboot() -> Result<String> {
 /// * If delivering a command to roxy fails, then an error is returned.
 /// * If a response message from roxy is invalid regarding JSON syntax or
 ///   is not successfully base64-decoded, then an error is returned.
-/// * If `nix::sys::reboot::reboot` fails, then an error is returned.
-pub fn power_off() -> Result<String> {
-    if let Ok(req) = NodeRequest::new::<Option<String>>(Node::PowerOff, None) {
-        run_roxy::<String>(req)
+/// * If roxy fails to schedule the power off, then an error is returned.
+pub fn power_off_in(delay_secs: u64) -> Result<ShutdownAck> {
+    if let Ok(req) = NodeRequest::new::<u64>(Node::PowerOff, delay_secs) {
+        run_roxy::<ShutdownAck>(req)
     } else {
         Err(anyhow!(FAIL_REQUEST))
     }
 }
 
+/// Stops the services, then reboots the system. The services are stopped one
+/// by one in the given order, so list dependents before their dependencies,
+/// e.g. `["zeek", "kafka", "postgresql"]`. Each service has up to
+/// `stop_timeout` seconds to become inactive.
+///
+/// The system is not rebooted if a service fails to stop. The services
+/// stopped before it stay stopped, and the caller may `reboot` anyway.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If a service fails to stop or is still active after `stop_timeout`
+///   seconds, then an error with `ErrorCode::Fail` and the last journal
+///   lines of the service is returned.
+/// * Any error of `reboot`.
+pub fn graceful_reboot(services: &[String], stop_timeout: u64) -> Result<ShutdownAck> {
+    stop_services(services, stop_timeout)?;
+    reboot()
+}
+
+/// Stops the services, then turns the system off. The services are stopped
+/// the same way as `graceful_reboot`.
+///
+/// # Errors
+///
+/// The following errors are possible:
+///
+/// * If a service fails to stop or is still active after `stop_timeout`
+///   seconds, then an error with `ErrorCode::Fail` and the last journal
+///   lines of the service is returned.
+/// * Any error of `power_off`.
+pub fn graceful_power_off(services: &[String], stop_timeout: u64) -> Result<ShutdownAck> {
+    stop_services(services, stop_timeout)?;
+    power_off()
+}
+
+fn stop_services(services: &[String], stop_timeout: u64) -> Result<()> {
+    for service in services {
+        stop_service_checked(service.clone(), stop_timeout)
+            .map_err(|e| anyhow!("fail to stop {} before shutdown: {}", service, e))?;
+    }
+    Ok(())
+}
+
 /// Response message from Roxy to caller
 #[derive(Deserialize, Debug)]
 pub enum TaskResult {
@@ -339,6 +2578,37 @@ pub enum TaskResult {
     Err(String),
 }
 
+/// A callback invoked with the command and its outcome after each command to
+/// roxy, including the ones of `ping_roxy` and `compat_check`, e.g. to keep
+/// an audit trail of the commands changing the system.
+pub type AuditHook = Box<dyn Fn(&Node, std::result::Result<(), &anyhow::Error>) + Send + Sync>;
+
+static AUDIT_HOOK: RwLock<Option<AuditHook>> = RwLock::new(None);
+
+/// Sets the hook invoked after each command to roxy, for all threads. `None`
+/// removes it. No hook is set by default.
+///
+/// The hook runs on the thread calling roxy, so it should return quickly.
+///
+/// # Example
+///
+/// ```ignore
+/// roxy::set_audit_hook(Some(Box::new(|kind, ret| match ret {
+///     Ok(()) => log::info!("{kind:?}: Ok"),
+///     Err(e) => log::warn!("{kind:?}: {e}"),
+/// })));
+/// ```
+pub fn set_audit_hook(hook: Option<AuditHook>) {
+    *AUDIT_HOOK.write().unwrap_or_else(PoisonError::into_inner) = hook;
+}
+
+fn audit<T>(kind: &Node, ret: &Result<T>) {
+    let hook = AUDIT_HOOK.read().unwrap_or_else(PoisonError::into_inner);
+    if let Some(hook) = hook.as_ref() {
+        hook(kind, ret.as_ref().map(|_| ()));
+    }
+}
+
 // TODO: fix the exact path to "roxy"
 //
 /// # Errors
@@ -347,11 +2617,115 @@ pub enum TaskResult {
 /// * Failure to write command to roxy
 /// * Invalid json syntax in response message
 /// * base64 decode error for reponse message
-/// * Received execution error from roxy
+/// * Received execution error from roxy. The error is [`RemoteError`].
+/// * roxy speaks another protocol revision. The error is [`RemoteError`] with
+///   `ErrorCode::ProtocolMismatch`.
+/// * The decoded response is larger than [`common::MAX_RESPONSE_SIZE`]. The
+///   error is [`ResponseTooLarge`].
 pub fn run_roxy<T>(req: NodeRequest) -> Result<T>
 where
     T: serde::de::DeserializeOwned,
 {
+    let kind = req.kind.clone();
+    let ret = call_roxy(req).and_then(decode_response);
+    audit(&kind, &ret);
+    ret
+}
+
+// Same as `run_roxy`, but decodes the response even if roxy speaks another
+// protocol revision, for `Node::Ping` and `Node::Capabilities` which roxy
+// answers regardless of it.
+fn run_roxy_any_protocol<T>(req: NodeRequest) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let kind = req.kind.clone();
+    let ret = call_roxy(req).and_then(|resp| decode_result(resp.result));
+    audit(&kind, &ret);
+    ret
+}
+
+fn decode_response<T>(resp: NodeResponse) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    if resp.protocol_version != PROTOCOL_VERSION {
+        return Err(protocol_mismatch(&resp.protocol_version.to_string()).into());
+    }
+    decode_result(resp.result)
+}
+
+/// Same as `run_roxy`, but decodes the response as it is read from roxy,
+/// instead of buffering the whole output. Roxy is asked to write the base64
+/// payload after a [`NodeResponse`] line, and the payload is decoded a chunk
+/// at a time into the result, so the memory used is the result itself and a
+/// chunk. Use this for commands which may return a large response, e.g.
+/// `netplan_configs` and `export_network`.
+///
+/// # Errors
+///
+/// * Failure to spawn roxy
+/// * Failure to write command to roxy
+/// * Invalid json syntax in response message
+/// * base64 decode error for reponse message
+/// * Received execution error from roxy. The error is [`RemoteError`].
+/// * roxy speaks another protocol revision. The error is [`RemoteError`] with
+///   `ErrorCode::ProtocolMismatch`.
+/// * The decoded response is larger than [`common::MAX_RESPONSE_SIZE`]. The
+///   error is [`ResponseTooLarge`].
+pub fn run_roxy_streaming<T>(mut req: NodeRequest) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let kind = req.kind.clone();
+    req.stream = true;
+    let ret = read_streaming(req);
+    audit(&kind, &ret);
+    ret
+}
+
+fn read_streaming<T>(req: NodeRequest) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let mut child = spawn_roxy(req)?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("failed to execute roxy"))?;
+    let ret = decode_stream(BufReader::new(stdout));
+    child.wait()?;
+    ret
+}
+
+// Decodes the response written for `NodeRequest::stream`. A response which is
+// not in that form, e.g. the pretty JSON of an older roxy or of an error
+// before roxy reads the request, is read to the end and decoded as a whole.
+fn decode_stream<T, R>(mut reader: R) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+    R: BufRead,
+{
+    let mut header = String::new();
+    reader.read_line(&mut header)?;
+    let Ok(resp) = serde_json::from_str::<NodeResponse>(&header) else {
+        let mut output = header.into_bytes();
+        reader.read_to_end(&mut output)?;
+        return parse_response(&output).and_then(decode_response);
+    };
+    if resp.protocol_version != PROTOCOL_VERSION {
+        return Err(protocol_mismatch(&resp.protocol_version.to_string()).into());
+    }
+    match resp.result {
+        Ok(_) => bincode_options()
+            .deserialize_from(Base64Reader::new(reader))
+            .map_err(bincode_error),
+        Err(x) => Err(decode_error(&x).into()),
+    }
+}
+
+// Spawns roxy and sends the request to it.
+fn spawn_roxy(req: NodeRequest) -> Result<Child> {
     let mut child = Command::new("roxy")
         .env(
             "PATH",
@@ -368,16 +2742,267 @@ where
     } else {
         return Err(anyhow!("failed to execute roxy"));
     }
+    Ok(child)
+}
+
+// Sends the request to roxy and returns the response without checking the
+// protocol revision.
+fn call_roxy(req: NodeRequest) -> Result<NodeResponse> {
+    let output = spawn_roxy(req)?.wait_with_output()?;
+    parse_response(&output.stdout)
+}
+
+fn parse_response(output: &[u8]) -> Result<NodeResponse> {
+    match serde_json::from_slice::<NodeResponse>(output) {
+        Ok(resp) => Ok(resp),
+        Err(e) => {
+            // roxy older than the protocol revision responds without it.
+            if serde_json::from_slice::<TaskResult>(output).is_ok() {
+                Err(protocol_mismatch("none").into())
+            } else {
+                Err(anyhow!("fail to parse response. {}", e))
+            }
+        }
+    }
+}
+
+// Decodes base64 incrementally, a chunk at a time.
+struct Base64Reader<R> {
+    input: R,
+    decoded: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> Base64Reader<R> {
+    // The number of base64 characters decoded at once. It should be a multiple
+    // of 4 so that padding appears only in the last chunk.
+    const CHUNK_LEN: usize = 4096;
+
+    fn new(input: R) -> Self {
+        Self {
+            input,
+            decoded: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for Base64Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos == self.decoded.len() {
+            let mut chunk = Vec::with_capacity(Self::CHUNK_LEN);
+            (&mut self.input)
+                .take(Self::CHUNK_LEN as u64)
+                .read_to_end(&mut chunk)?;
+            self.decoded = BASE64.decode(chunk.trim_ascii_end()).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, "fail to decode response.")
+            })?;
+            self.pos = 0;
+        }
+        let len = buf.len().min(self.decoded.len() - self.pos);
+        buf[..len].copy_from_slice(&self.decoded[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
 
-    let output = child.wait_with_output()?;
-    match serde_json::from_reader::<&[u8], TaskResult>(&output.stdout) {
-        Ok(TaskResult::Ok(x)) => {
+fn decode_result<T>(result: std::result::Result<String, String>) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    match result {
+        Ok(x) => {
+            // Rejects a too large response before decoding base64.
+            let len = BASE64
+                .decode_len(x.len())
+                .map_err(|_| anyhow!("fail to decode response."))?;
+            if len as u64 > MAX_RESPONSE_SIZE {
+                return Err(ResponseTooLarge {
+                    limit: MAX_RESPONSE_SIZE,
+                }
+                .into());
+            }
             let decoded = BASE64
                 .decode(x.as_bytes())
                 .map_err(|_| anyhow!("fail to decode response."))?;
-            Ok(bincode::deserialize::<T>(&decoded)?)
+            bincode_options()
+                .deserialize::<T>(&decoded)
+                .map_err(bincode_error)
+        }
+        Err(x) => Err(decode_error(&x).into()),
+    }
+}
+
+// The bincode options to decode a response. They are compatible with
+// `bincode::serialize` in roxy, but limit the size to `MAX_RESPONSE_SIZE`, so
+// that a corrupt length in the response does not cause a huge allocation.
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(MAX_RESPONSE_SIZE)
+}
+
+fn bincode_error(e: bincode::Error) -> anyhow::Error {
+    if matches!(*e, bincode::ErrorKind::SizeLimit) {
+        ResponseTooLarge {
+            limit: MAX_RESPONSE_SIZE,
         }
-        Ok(TaskResult::Err(x)) => Err(anyhow!("{}", x)),
-        Err(e) => Err(anyhow!("fail to parse response. {}", e)),
+        .into()
+    } else {
+        e.into()
+    }
+}
+
+fn protocol_mismatch(actual: &str) -> RemoteError {
+    RemoteError {
+        code: ErrorCode::ProtocolMismatch,
+        message: format!(
+            "protocol mismatch: expected {PROTOCOL_VERSION}, but roxy speaks {actual}"
+        ),
+    }
+}
+
+// Decodes the error message from roxy. The message is base64-encoded bincode of
+// `RemoteError`, or a plain string from an older roxy.
+fn decode_error(x: &str) -> RemoteError {
+    BASE64
+        .decode(x.as_bytes())
+        .ok()
+        .and_then(|decoded| bincode_options().deserialize::<RemoteError>(&decoded).ok())
+        .unwrap_or_else(|| RemoteError {
+            code: ErrorCode::Unknown,
+            message: x.to_string(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_stream_decodes_payload_after_header() {
+        let value = vec!["eno1".to_string(); 2000];
+        let header = serde_json::to_string(&NodeResponse {
+            protocol_version: PROTOCOL_VERSION,
+            result: Ok(String::new()),
+        })
+        .unwrap();
+        let payload = BASE64.encode(&bincode::serialize(&value).unwrap());
+        let output = format!("{header}\n{payload}");
+        assert_eq!(
+            decode_stream::<Vec<String>, _>(output.as_bytes()).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn decode_stream_decodes_whole_response() {
+        // an error written before roxy reads the request is pretty JSON
+        let remote = RemoteError {
+            code: ErrorCode::InvalidCommand,
+            message: "invalid command".to_string(),
+        };
+        let output = serde_json::to_string_pretty(&NodeResponse {
+            protocol_version: PROTOCOL_VERSION,
+            result: Err(BASE64.encode(&bincode::serialize(&remote).unwrap())),
+        })
+        .unwrap();
+        let e = decode_stream::<String, _>(output.as_bytes()).unwrap_err();
+        assert_eq!(e.downcast_ref::<RemoteError>(), Some(&remote));
+
+        // roxy older than the protocol revision responds without it
+        let e = decode_stream::<String, _>(r#"{"Ok":"AAAA"}"#.as_bytes()).unwrap_err();
+        let remote = e.downcast_ref::<RemoteError>().unwrap();
+        assert_eq!(remote.code, ErrorCode::ProtocolMismatch);
+    }
+
+    #[test]
+    fn decode_stream_rejects_invalid_payload() {
+        let header = serde_json::to_string(&NodeResponse {
+            protocol_version: PROTOCOL_VERSION,
+            result: Ok(String::new()),
+        })
+        .unwrap();
+        let output = format!("{header}\n!!!!");
+        assert!(decode_stream::<String, _>(output.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn netplan_errors_are_distinct_codes() {
+        for code in [
+            ErrorCode::NetplanInvalid,
+            ErrorCode::NetplanUnreadable,
+            ErrorCode::NoNetplanYaml,
+        ] {
+            let remote = RemoteError {
+                code,
+                message: "no netplan yaml file in /etc/netplan".to_string(),
+            };
+            let encoded = BASE64.encode(&bincode::serialize(&remote).unwrap());
+            let e = decode_result::<String>(Err(encoded)).unwrap_err();
+            match e.downcast_ref::<RemoteError>() {
+                Some(RemoteError { code: got, .. }) => assert_eq!(*got, code),
+                None => panic!("not a RemoteError: {e}"),
+            }
+        }
+    }
+
+    #[test]
+    fn decode_result_rejects_truncated_base64() {
+        let e = decode_result::<String>(Ok("AAAAA".to_string())).unwrap_err();
+        assert!(e.downcast_ref::<ResponseTooLarge>().is_none());
+    }
+
+    #[test]
+    fn decode_result_is_compatible_with_bincode_serialize() {
+        let value = vec!["eno1".to_string(), "eno2".to_string()];
+        let encoded = BASE64.encode(&bincode::serialize(&value).unwrap());
+        assert_eq!(decode_result::<Vec<String>>(Ok(encoded)).unwrap(), value);
+    }
+
+    #[test]
+    fn commands_are_distinct_in_json() {
+        let names = commands()
+            .iter()
+            .map(|spec| serde_json::to_string(&spec.node).unwrap())
+            .collect::<Vec<_>>();
+        for (i, name) in names.iter().enumerate() {
+            assert!(!names[..i].contains(name), "{name} is listed twice");
+        }
+    }
+
+    #[test]
+    fn audit_hook_gets_command() {
+        use std::sync::{Arc, Mutex};
+
+        let audited = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&audited);
+        set_audit_hook(Some(Box::new(move |kind, _| {
+            sink.lock().unwrap().push(kind.clone());
+        })));
+        let kind = Node::Hostname(SubCommand::Get);
+        audit(&kind, &Ok(()));
+        audit::<()>(&kind, &Err(anyhow!("fail")));
+        set_audit_hook(None);
+        audit(&kind, &Ok(()));
+
+        assert_eq!(*audited.lock().unwrap(), vec![kind.clone(), kind]);
+    }
+
+    #[test]
+    fn streaming_decode_rejects_huge_length() {
+        // A string claiming `u64::MAX` bytes. The streaming decoder would
+        // allocate the buffer for it without the limit.
+        let encoded = BASE64.encode(&u64::MAX.to_le_bytes());
+        let err = bincode_options()
+            .deserialize_from::<_, String>(Base64Reader::new(encoded.as_bytes()))
+            .map_err(bincode_error)
+            .unwrap_err();
+        assert!(err.is::<ResponseTooLarge>());
+
+        // The buffered decoder reads from the decoded bytes, so it fails
+        // without allocating.
+        assert!(decode_result::<String>(Ok(encoded)).is_err());
     }
 }
@@ -1,10 +1,10 @@
 mod root;
 
 use data_encoding::BASE64;
-use root::task::{ExecResult, Task, ERR_INVALID_COMMAND};
-use roxy::common::{self, Node, NodeRequest};
+use root::task::{encode_error, Task, ERR_INVALID_COMMAND, ERR_PROTOCOL_MISMATCH};
+use roxy::common::{self, Node, NodeRequest, NodeResponse, PROTOCOL_VERSION};
 use std::{
-    io::{stdin, stdout},
+    io::{self, stdin, stdout, BufWriter, Write},
     process,
 };
 
@@ -13,32 +13,89 @@ fn main() {
         Ok(nr) => nr,
         Err(err) => {
             log::error!("Command Error: {}", err);
-            if let Err(err) =
-                serde_json::to_writer_pretty(stdout(), &ExecResult::Err(ERR_INVALID_COMMAND))
-            {
+            if let Err(err) = respond(Err(encode_error(ERR_INVALID_COMMAND.into()))) {
                 log::error!("Serialize Error: {}", err);
             }
             process::exit(1);
         }
     };
 
+    // `Ping` and `Capabilities` are answered regardless of the protocol
+    // revision, so that the caller can find which revision and commands roxy
+    // speaks.
+    if nr.protocol_version != PROTOCOL_VERSION
+        && !matches!(nr.kind, Node::Ping | Node::Capabilities)
+    {
+        log::error!(
+            "Protocol mismatch: expected {}, got {}",
+            PROTOCOL_VERSION,
+            nr.protocol_version
+        );
+        if let Err(err) = respond(Err(encode_error(ERR_PROTOCOL_MISMATCH.into()))) {
+            log::error!("Serialize Error: {}", err);
+        }
+        process::exit(1);
+    }
+
     let arg = BASE64.encode(&nr.arg);
     let task = match nr.kind {
+        Node::Backup(cmd) => Task::Backup { cmd, arg },
+        Node::Banner(cmd) => Task::Banner { cmd, arg },
+        Node::Capabilities => Task::Capabilities(arg),
+        Node::Dns(cmd) => Task::Dns { cmd, arg },
         Node::Hostname(cmd) => Task::Hostname { cmd, arg },
+        Node::Hosts(cmd) => Task::Hosts { cmd, arg },
         Node::Interface(cmd) => Task::Interface { cmd, arg },
+        Node::Journal(cmd) => Task::Journal { cmd, arg },
+        Node::Logrotate(cmd) => Task::Logrotate { cmd, arg },
+        Node::Modules(cmd) => Task::Modules { cmd, arg },
         Node::Ntp(cmd) => Task::Ntp { cmd, arg },
+        Node::Ping => Task::Ping(arg),
         Node::PowerOff => Task::PowerOff(arg),
         Node::Reboot => Task::Reboot(arg),
         Node::Service(cmd) => Task::Service { cmd, arg },
         Node::Sshd(cmd) => Task::Sshd { cmd, arg },
+        Node::Sysctl(cmd) => Task::Sysctl { cmd, arg },
         Node::Syslog(cmd) => Task::Syslog { cmd, arg },
         Node::Ufw(cmd) => Task::Ufw { cmd, arg },
         Node::Version(cmd) => Task::Version { cmd, arg },
+        Node::Wifi(cmd) => Task::Wifi { cmd, arg },
     };
 
-    let ret = task.execute();
-    if let Err(err) = serde_json::to_writer_pretty(stdout(), &ret) {
+    let ret = task.execute().map_err(encode_error);
+    let written = if nr.stream {
+        respond_stream(ret)
+    } else {
+        respond(ret).map_err(Into::into)
+    };
+    if let Err(err) = written {
         log::error!("Stdout Error: {}", err);
         process::exit(1);
     }
 }
+
+fn respond(result: Result<String, String>) -> serde_json::Result<()> {
+    let resp = NodeResponse {
+        protocol_version: PROTOCOL_VERSION,
+        result,
+    };
+    serde_json::to_writer_pretty(stdout(), &resp)
+}
+
+// Writes the response for `NodeRequest::stream`: a `NodeResponse` line, with
+// an empty string as the successful result, and then the base64 payload.
+fn respond_stream(result: Result<String, String>) -> io::Result<()> {
+    let mut out = BufWriter::new(stdout().lock());
+    let (result, payload) = match result {
+        Ok(payload) => (Ok(String::new()), payload),
+        Err(e) => (Err(e), String::new()),
+    };
+    let resp = NodeResponse {
+        protocol_version: PROTOCOL_VERSION,
+        result,
+    };
+    serde_json::to_writer(&mut out, &resp)?;
+    out.write_all(b"\n")?;
+    out.write_all(payload.as_bytes())?;
+    out.flush()
+}
@@ -1,9 +1,330 @@
+mod backup;
+mod banner;
+mod dns;
+mod hosts;
 mod hwinfo;
 mod ifconfig;
+mod logs;
+mod modules;
 mod ntp;
+#[cfg(target_os = "linux")]
+mod power;
 mod services;
 mod sshd;
+mod sysctl;
 mod syslog;
 pub(crate) mod task;
+mod ufw;
 
-use super::common::{Nic, NicOutput, SubCommand};
+use super::common::{
+    AccessPoint, AddressDrift, Bridge, Dhcp4Overrides, DnsScope, EffectiveDns, FieldChange,
+    FirewallAction, FirewallDirection, FirewallProtocol, FirewallRule, InterfaceChange,
+    JournalVacuum, LogRotation, NetplanDiff, NetworkSnapshot, Nic, NicMatch, NicOutput, NtpPeer,
+    RingSize, Route, SubCommand, SyslogServer, Verification, Vlan, Wifi, MAX_NAMESERVERS,
+    PASSWORD_MASK,
+};
+use anyhow::{anyhow, Result};
+use roxy::common::{DEFAULT_PATH_ENV, VERSION_PATH};
+use std::{
+    fs::{self, OpenOptions},
+    io::{Read, Write},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+// The timeout of `systemctl restart`. systemd waits 90 seconds by default for
+// a unit to stop, so this is a bit longer than that.
+const SYSTEMCTL_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Error returned when a command does not finish within its timeout. The
+// command is killed.
+#[derive(Debug, Error)]
+#[error("{cmd} timed out after {} seconds", timeout.as_secs())]
+pub(crate) struct CommandTimeout {
+    cmd: String,
+    timeout: Duration,
+}
+
+// Error returned when a command exits with failure, with what it printed to
+// stderr.
+#[derive(Debug, Error)]
+#[error("{cmd} failed: {stderr}")]
+pub(crate) struct CommandFailed {
+    cmd: String,
+    stderr: String,
+}
+
+// Error returned when a written config file does not read back as written,
+// e.g. because the disk is full and the write is truncated.
+#[derive(Debug, Error)]
+#[error("{path} does not contain the written contents")]
+pub(crate) struct WriteVerifyFailed {
+    path: String,
+}
+
+// Overwrites the existing file with the contents, and reads it back to check
+// that the contents landed. Call this before restarting the service reading
+// the file, so that a truncated config does not take the service down.
+//
+// The previous contents are saved to a snapshot under /var/lib/roxy/backup
+// first, and restored if the file does not read back as written.
+//
+// # Errors
+//
+// * fail to open, write, or read the file
+// * fail to back up the file. The file is not written.
+// * `WriteVerifyFailed` if the file does not read back as written
+pub(crate) fn write_verified(path: &str, contents: &str) -> Result<()> {
+    backup::snapshot(path)?;
+    let mut file = OpenOptions::new().write(true).truncate(true).open(path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    if fs::read_to_string(path)? != contents {
+        // Best effort. The disk may be as full as it was for the write.
+        let _r = backup::restore(path);
+        return Err(WriteVerifyFailed {
+            path: path.to_string(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+// Same as `write_verified`, but creates the file first if it does not exist,
+// e.g. a drop-in file roxy writes for the first time.
+pub(crate) fn create_verified(path: &str, contents: &str) -> Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    write_verified(path, contents)
+}
+
+// Restores a config file roxy writes from its latest snapshot, e.g. to roll
+// back a change. The service reading the file is not restarted.
+//
+// # Errors
+//
+// * the file is not one roxy writes through `write_verified`. Netplan yaml
+//   files are restored by `ifconfig::restore_configs` instead.
+// * no snapshot of the file
+// * fail to overwrite the file
+pub(crate) fn restore_config(path: &str) -> Result<()> {
+    let managed = [
+        banner::BANNER_PATH,
+        dns::RESOLVED_CONF,
+        hosts::HOSTS_PATH,
+        modules::MODULES_CONF,
+        ntp::NTP_CONF,
+        sshd::SSHD_CONFIG,
+        sysctl::SYSCTL_CONF,
+        syslog::RSYSLOG_CONF,
+        VERSION_PATH,
+    ];
+    if !managed.contains(&path) && !logs::is_rotation_path(path) {
+        return Err(anyhow!("{} is not managed by roxy", path));
+    }
+    backup::restore(path)
+}
+
+pub(crate) fn run_command(cmd: &str, args: &[&str]) -> Result<bool> {
+    let status = Command::new(cmd)
+        .env("PATH", DEFAULT_PATH_ENV)
+        .args(args)
+        .status()?;
+    Ok(status.success())
+}
+
+// Runs the command and returns its stdout.
+//
+// # Errors
+//
+// * fail to execute the command
+// * `CommandFailed` if the command exits with failure
+pub(crate) fn run_command_output(cmd: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(cmd)
+        .env("PATH", DEFAULT_PATH_ENV)
+        .args(args)
+        .output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(CommandFailed {
+            cmd: format!("{} {}", cmd, args.join(" ")),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }
+        .into())
+    }
+}
+
+// Same as `run_command_output`, but kills the command if it does not finish
+// within `timeout`.
+//
+// # Errors
+//
+// * fail to execute the command
+// * `CommandFailed` if the command exits with failure
+// * `CommandTimeout` if the command does not finish within `timeout`
+pub(crate) fn run_command_output_timeout(
+    cmd: &str,
+    args: &[&str],
+    timeout: Duration,
+) -> Result<String> {
+    let mut child = Command::new(cmd)
+        .env("PATH", DEFAULT_PATH_ENV)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Drains the pipes while waiting, so that the command does not block on
+    // a full pipe.
+    let stdout = child.stdout.take().map(drain);
+    let stderr = child.stderr.take().map(drain);
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() >= timeout {
+            // The command may exit right before being killed.
+            let _r = child.kill();
+            child.wait()?;
+            return Err(CommandTimeout {
+                cmd: format!("{} {}", cmd, args.join(" ")),
+                timeout,
+            }
+            .into());
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+
+    let stdout = stdout.and_then(|h| h.join().ok()).unwrap_or_default();
+    let stderr = stderr.and_then(|h| h.join().ok()).unwrap_or_default();
+    if status.success() {
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    } else {
+        Err(CommandFailed {
+            cmd: format!("{} {}", cmd, args.join(" ")),
+            stderr: String::from_utf8_lossy(&stderr).trim().to_string(),
+        }
+        .into())
+    }
+}
+
+fn drain(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _r = pipe.read_to_end(&mut buf);
+        buf
+    })
+}
+
+// Restarts the systemd unit, waiting up to `SYSTEMCTL_TIMEOUT`.
+//
+// # Return
+//
+// * true if the unit is restarted
+// * false if systemctl exits with failure without telling why
+//
+// # Errors
+//
+// * fail to execute systemctl
+// * `CommandFailed` with the stderr of systemctl, e.g. the unit is not found
+//   or fails to start
+// * `CommandTimeout` if the restart does not finish in time
+pub(crate) fn restart_service(unit: &str) -> Result<bool> {
+    match run_command_output_timeout("systemctl", &["restart", unit], SYSTEMCTL_TIMEOUT) {
+        Ok(_) => Ok(true),
+        Err(e)
+            if e.downcast_ref::<CommandFailed>()
+                .is_some_and(|failed| failed.stderr.is_empty()) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_command_output_timeout_kills_hung_command() {
+        let start = Instant::now();
+        let err =
+            run_command_output_timeout("sleep", &["10"], Duration::from_millis(300)).unwrap_err();
+        assert!(err.is::<CommandTimeout>());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn write_verified_overwrites_existing_file() {
+        let path = std::env::temp_dir().join(format!("roxy-verify-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert!(write_verified(path, "Port 22\n").is_err());
+
+        fs::write(path, "Port 22\nPermitRootLogin no\n").unwrap();
+        write_verified(path, "Port 10022\n").unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "Port 10022\n");
+        let backups = backup::snapshots(path).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(
+            fs::read_to_string(&backups[0]).unwrap(),
+            "Port 22\nPermitRootLogin no\n"
+        );
+        fs::remove_file(path).unwrap();
+        fs::remove_file(&backups[0]).unwrap();
+    }
+
+    #[test]
+    fn create_verified_creates_missing_file() {
+        let path = std::env::temp_dir().join(format!("roxy-create-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        create_verified(path, "tcp_bbr\n").unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "tcp_bbr\n");
+        let backups = backup::snapshots(path).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "");
+        fs::remove_file(path).unwrap();
+        fs::remove_file(&backups[0]).unwrap();
+    }
+
+    #[test]
+    fn restore_config_rejects_unmanaged_path() {
+        assert!(restore_config("/etc/shadow").is_err());
+        assert!(restore_config("/etc/logrotate.d/../shadow").is_err());
+        assert!(restore_config("/etc/netplan/01-netcfg.yaml").is_err());
+    }
+
+    #[test]
+    fn run_command_output_timeout_fails_with_stderr() {
+        let err = run_command_output_timeout(
+            "sh",
+            &["-c", "echo 'Unit foo.service not found.' >&2; exit 5"],
+            Duration::from_secs(5),
+        )
+        .unwrap_err();
+        let failed = err.downcast_ref::<CommandFailed>().unwrap();
+        assert_eq!(failed.stderr, "Unit foo.service not found.");
+
+        let err = run_command_output_timeout("sh", &["-c", "exit 1"], Duration::from_secs(5))
+            .unwrap_err();
+        assert!(err
+            .downcast_ref::<CommandFailed>()
+            .unwrap()
+            .stderr
+            .is_empty());
+    }
+
+    #[test]
+    fn run_command_output_timeout_returns_stdout() {
+        let out = run_command_output_timeout("echo", &["hello"], Duration::from_secs(5)).unwrap();
+        assert_eq!(out, "hello\n");
+    }
+}
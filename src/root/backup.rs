@@ -0,0 +1,181 @@
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDateTime, Utc};
+use std::{
+    fs::{self, DirBuilder},
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+};
+
+// Snapshots are kept out of /etc, because a service reads every file in its
+// include directory, e.g. /etc/logrotate.d, and would take a snapshot for
+// config.
+const BACKUP_DIR: &str = "/var/lib/roxy/backup";
+
+// The number of snapshots kept for each config file. The oldest ones are
+// removed.
+const MAX_SNAPSHOTS: usize = 10;
+
+// The suffix of a snapshot, e.g. `20261014T093000.123456789Z`. It sorts in the
+// order the snapshots are taken.
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.9fZ";
+
+// The directory the snapshots are saved in. Tests use a temporary directory,
+// so that they do not write to /var/lib.
+fn backup_dir() -> PathBuf {
+    if cfg!(test) {
+        std::env::temp_dir().join(format!("roxy-backup-{}", std::process::id()))
+    } else {
+        PathBuf::from(BACKUP_DIR)
+    }
+}
+
+// Returns the directory of the snapshots of the config file, which mirrors
+// the path of the file, and the file name, e.g. `/var/lib/roxy/backup/etc`
+// and `ntp.conf` for `/etc/ntp.conf`.
+fn snapshot_dir(path: &str) -> Result<(PathBuf, String)> {
+    let path = Path::new(path);
+    let relative = path
+        .strip_prefix("/")
+        .map_err(|_| anyhow!("not an absolute path: {}", path.display()))?;
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("no file name: {}", path.display()))?;
+    let mut dir = backup_dir().join(relative);
+    dir.pop();
+    Ok((dir, name.to_string_lossy().into_owned()))
+}
+
+// Copies the config file to `/var/lib/roxy/backup/<path>.<timestamp>`, e.g.
+// `/var/lib/roxy/backup/etc/ntp.conf.20261014T093000.123456789Z`. The
+// permissions of the file are copied as well. Only the last `MAX_SNAPSHOTS`
+// snapshots of the file are kept.
+//
+// # Return
+//
+// * String: the path of the snapshot
+//
+// # Errors
+//
+// * fail to read the file, e.g. it does not exist
+// * fail to write the snapshot
+pub(crate) fn snapshot(path: &str) -> Result<String> {
+    let (dir, name) = snapshot_dir(path)?;
+    DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    let backup = dir.join(format!("{name}.{}", Utc::now().format(TIMESTAMP_FORMAT)));
+    fs::copy(path, &backup)?;
+
+    let all = snapshots(path)?;
+    for old in &all[..all.len().saturating_sub(MAX_SNAPSHOTS)] {
+        fs::remove_file(old)?;
+    }
+    Ok(backup.to_string_lossy().into_owned())
+}
+
+// Returns the snapshots of the config file, the oldest first.
+//
+// # Errors
+//
+// * fail to read the directory of the snapshots
+pub(crate) fn snapshots(path: &str) -> Result<Vec<PathBuf>> {
+    let (dir, name) = snapshot_dir(path)?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let prefix = format!("{name}.");
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(timestamp) = file_name.to_str().and_then(|n| n.strip_prefix(&prefix)) else {
+            continue;
+        };
+        // Skips the snapshots of another file with the same prefix, e.g.
+        // `ntp.conf.d.<timestamp>` of `ntp.conf.d` for `ntp.conf`.
+        if NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).is_ok() {
+            snapshots.push(entry.path());
+        }
+    }
+    snapshots.sort();
+    Ok(snapshots)
+}
+
+// Restores the config file from the latest snapshot taken by `snapshot`. The
+// snapshot is kept, so that the file can be restored again.
+//
+// # Errors
+//
+// * no snapshot of the file
+// * fail to overwrite the file
+pub(crate) fn restore(path: &str) -> Result<()> {
+    let Some(latest) = snapshots(path)?.pop() else {
+        return Err(anyhow!("no backup of {}", path));
+    };
+    fs::copy(latest, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_and_restore() {
+        let path = std::env::temp_dir().join(format!("roxy-backup-conf-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert!(snapshot(path).is_err());
+        assert!(restore(path).is_err());
+
+        fs::write(path, "server time.bora.net iburst\n").unwrap();
+        let first = snapshot(path).unwrap();
+        assert!(first.starts_with(backup_dir().to_str().unwrap()));
+        fs::write(path, "server 10.0.0.1 iburst\n").unwrap();
+        let second = snapshot(path).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(
+            snapshots(path).unwrap(),
+            vec![PathBuf::from(&first), PathBuf::from(&second)]
+        );
+
+        fs::write(path, "server 10.0.0.2 iburst\n").unwrap();
+        restore(path).unwrap();
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "server 10.0.0.1 iburst\n"
+        );
+        fs::remove_file(path).unwrap();
+        fs::remove_file(first).unwrap();
+        fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn snapshot_keeps_the_latest_ones() {
+        let path = std::env::temp_dir().join(format!("roxy-backup-keep-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut taken = Vec::new();
+        for i in 0..=MAX_SNAPSHOTS {
+            fs::write(path, format!("{i}\n")).unwrap();
+            taken.push(PathBuf::from(snapshot(path).unwrap()));
+        }
+        assert_eq!(snapshots(path).unwrap(), taken[1..]);
+        for backup in &taken[1..] {
+            fs::remove_file(backup).unwrap();
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn snapshots_of_another_file_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("roxy-backup-dir-{}", std::process::id()));
+        let path = dir.join("ntp.conf");
+        let other = dir.join("ntp.conf.d");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "a\n").unwrap();
+        fs::write(&other, "b\n").unwrap();
+        let other_backup = snapshot(other.to_str().unwrap()).unwrap();
+        assert!(snapshots(path.to_str().unwrap()).unwrap().is_empty());
+        fs::remove_file(other_backup).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,58 @@
+use super::{create_verified, restart_service, sshd::SshdConfig};
+use anyhow::{anyhow, Result};
+use std::{fs, io::ErrorKind};
+
+pub(crate) const BANNER_PATH: &str = "/etc/issue.net";
+
+// Gets the login banner sshd shows before authentication. `None` if
+// /etc/issue.net does not exist or sshd_config does not point to it.
+//
+// # Errors
+//
+// * fail to open ``/etc/ssh/sshd_config`` or /etc/issue.net
+pub(crate) fn get() -> Result<Option<String>> {
+    let config = SshdConfig::load()?;
+    if config.get("Banner") != Some(BANNER_PATH) {
+        return Ok(None);
+    }
+    match fs::read_to_string(BANNER_PATH) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Sets the login banner. The text is written to /etc/issue.net, and the
+// `Banner` directive of sshd_config is set to it. sshd is restarted only if
+// the directive changes, since sshd reads the banner on each connection.
+//
+// # Example
+//
+// let ret = banner::set("Authorized access only.\n")?;
+//
+// # Errors
+//
+// * the text contains a NUL character
+// * fail to write /etc/issue.net, or `WriteVerifyFailed` if it does not read
+//   back as written
+// * fail to open or write ``/etc/ssh/sshd_config``
+// * fail to restart sshd service
+pub(crate) fn set(text: &str) -> Result<bool> {
+    if text.contains('\0') {
+        return Err(anyhow!("banner contains a NUL character"));
+    }
+    let mut text = text.to_string();
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+
+    create_verified(BANNER_PATH, &text)?;
+
+    let mut config = SshdConfig::load()?;
+    if config.get("Banner") == Some(BANNER_PATH) {
+        return Ok(true);
+    }
+    config.set("Banner", BANNER_PATH);
+    config.save()?;
+    restart_service("sshd")
+}
@@ -0,0 +1,549 @@
+use super::{backup, restart_service, run_command_output, DnsScope, EffectiveDns};
+use anyhow::{anyhow, Result};
+use std::{fs, io::ErrorKind, net::IpAddr, path::Path};
+
+const RESOLVED_SERVICE: &str = "systemd-resolved";
+pub(crate) const RESOLVED_CONF: &str = "/etc/systemd/resolved.conf";
+const RESOLVE_SECTION: &str = "[Resolve]";
+const DNS_KEY: &str = "DNS";
+const DOMAINS_KEY: &str = "Domains";
+
+// Flushes the DNS cache of systemd-resolved, so that name resolution uses new
+// nameservers immediately. If `resolvectl flush-caches` fails, e.g. resolvectl
+// is not installed, systemd-resolved is restarted instead.
+//
+// Nothing is done if systemd-resolved is not running, because there is no
+// cache to flush.
+//
+// # Errors
+//
+// * fail to get the status of systemd-resolved
+// * fail to restart systemd-resolved
+pub(crate) fn flush() -> Result<()> {
+    if !systemctl::is_active(RESOLVED_SERVICE)? {
+        return Ok(());
+    }
+    if run_command_output("resolvectl", &["flush-caches"]).is_ok() {
+        return Ok(());
+    }
+    if restart_service(RESOLVED_SERVICE)? {
+        Ok(())
+    } else {
+        Err(anyhow!("fail to restart {}", RESOLVED_SERVICE))
+    }
+}
+
+// Sets the global DNS servers of systemd-resolved, which are used regardless
+// of interfaces, and restarts systemd-resolved. Empty `servers` removes them.
+//
+// Only the `DNS=` setting in /etc/systemd/resolved.conf is changed. Other
+// settings and comments are kept.
+//
+// # Errors
+//
+// * invalid server address
+// * fail to read or write /etc/systemd/resolved.conf
+// * fail to restart systemd-resolved
+pub(crate) fn set_global(servers: &[String]) -> Result<()> {
+    for server in servers {
+        server
+            .parse::<IpAddr>()
+            .map_err(|e| anyhow!("invalid DNS server: {}. {:?}", server, e))?;
+    }
+
+    set_resolve_values(DNS_KEY, servers)
+}
+
+// Gets the global DNS servers in /etc/systemd/resolved.conf.
+//
+// # Return
+//
+// * Vec<String>: DNS servers. Empty if not set.
+//
+// # Errors
+//
+// * fail to read /etc/systemd/resolved.conf
+pub(crate) fn get_global() -> Result<Vec<String>> {
+    get_resolve_values(DNS_KEY)
+}
+
+// Sets the global search domains of systemd-resolved, which are used
+// regardless of interfaces, and restarts systemd-resolved. Empty `domains`
+// removes them.
+//
+// # Errors
+//
+// * invalid domain name
+// * fail to read or write /etc/systemd/resolved.conf
+// * fail to restart systemd-resolved
+pub(crate) fn set_search(domains: &[String]) -> Result<()> {
+    for domain in domains {
+        if !is_valid_domain(domain) {
+            return Err(anyhow!("invalid search domain: {}", domain));
+        }
+    }
+    set_resolve_values(DOMAINS_KEY, domains)
+}
+
+// Gets the global search domains in /etc/systemd/resolved.conf.
+//
+// # Return
+//
+// * Vec<String>: search domains. Empty if not set.
+//
+// # Errors
+//
+// * fail to read /etc/systemd/resolved.conf
+pub(crate) fn get_search() -> Result<Vec<String>> {
+    get_resolve_values(DOMAINS_KEY)
+}
+
+// Sets both the global DNS servers and search domains of systemd-resolved in
+// one write of /etc/systemd/resolved.conf, and restarts systemd-resolved
+// once, so that a failure does not leave only one of them changed. Invalid
+// servers and domains are skipped and returned, and the valid ones are set.
+//
+// # Return
+//
+// * Vec<String>: the rejected servers and domains. Empty if all are valid.
+//
+// # Errors
+//
+// * every server, or every domain, is rejected. Nothing is written.
+// * fail to read or write /etc/systemd/resolved.conf
+// * fail to restart systemd-resolved
+pub(crate) fn set_global_with_search(servers: &[String], search: &[String]) -> Result<Vec<String>> {
+    let (valid_servers, mut rejected): (Vec<_>, Vec<_>) = servers
+        .iter()
+        .cloned()
+        .partition(|server| server.parse::<IpAddr>().is_ok());
+    let (valid_search, rejected_search): (Vec<_>, Vec<_>) = search
+        .iter()
+        .cloned()
+        .partition(|domain| is_valid_domain(domain));
+    if valid_servers.is_empty() && !servers.is_empty() {
+        return Err(anyhow!("no valid DNS server: {}", servers.join(" ")));
+    }
+    if valid_search.is_empty() && !search.is_empty() {
+        return Err(anyhow!("no valid search domain: {}", search.join(" ")));
+    }
+    rejected.extend(rejected_search);
+
+    let contents = read_resolved_conf()?;
+    let contents = replace_values(&contents, DNS_KEY, &valid_servers);
+    let contents = replace_values(&contents, DOMAINS_KEY, &valid_search);
+    write_resolved_conf(&contents)?;
+    restart_resolved()?;
+    Ok(rejected)
+}
+
+// Sets the DNS servers and domains of an interface in systemd-resolved at
+// runtime with `resolvectl`, for split DNS. Domains starting with "~" are
+// routing-only, e.g. "~corp.example.com" sends queries for it to the servers
+// of this interface. Empty `servers` or `domains` removes them.
+//
+// The setting is not persistent: it is lost when systemd-resolved restarts or
+// netplan is applied. Use `set_interface` to keep it.
+//
+// # Errors
+//
+// * invalid interface name, server address, or domain name
+// * fail to execute resolvectl, e.g. the interface does not exist
+pub(crate) fn set_link_dns(ifname: &str, servers: &[String], domains: &[String]) -> Result<()> {
+    validate_ifname(ifname)?;
+    for server in servers {
+        server
+            .parse::<IpAddr>()
+            .map_err(|e| anyhow!("invalid DNS server: {}. {:?}", server, e))?;
+    }
+    for domain in domains {
+        let name = domain.strip_prefix('~').unwrap_or(domain);
+        if name != "." && !is_valid_domain(name) {
+            return Err(anyhow!("invalid domain: {}", domain));
+        }
+    }
+
+    run_resolvectl_link("dns", ifname, servers)?;
+    run_resolvectl_link("domain", ifname, domains)
+}
+
+// Gets the DNS servers and domains of an interface in systemd-resolved.
+//
+// # Return
+//
+// * (Vec<String>, Vec<String>): DNS servers and domains. Empty if not set.
+//
+// # Errors
+//
+// * invalid interface name
+// * fail to execute resolvectl, e.g. the interface does not exist
+pub(crate) fn get_link_dns(ifname: &str) -> Result<(Vec<String>, Vec<String>)> {
+    validate_ifname(ifname)?;
+    let servers = run_command_output("resolvectl", &["dns", "--", ifname])?;
+    let domains = run_command_output("resolvectl", &["domain", "--", ifname])?;
+    Ok((link_values(&servers), link_values(&domains)))
+}
+
+// Gets the DNS servers and domains systemd-resolved uses at runtime,
+// globally and on each link, from `resolvectl status`. This shows the result
+// of netplan, DHCP, and /etc/systemd/resolved.conf together.
+//
+// # Errors
+//
+// * fail to execute resolvectl, e.g. systemd-resolved is not running
+pub(crate) fn effective() -> Result<EffectiveDns> {
+    let output = run_command_output("resolvectl", &["status", "--no-pager"])?;
+    Ok(parse_status(&output))
+}
+
+// Parses the output of `resolvectl status` like:
+//
+// Global
+//        Protocols: -LLMNR -mDNS -DNSOverTLS DNSSEC=no/unsupported
+// Current DNS Server: 8.8.8.8
+//        DNS Servers: 8.8.8.8 1.1.1.1
+//         DNS Domain: corp.example.com
+//
+// Link 2 (eth0)
+// Current DNS Server: 192.168.0.1
+//        DNS Servers: 192.168.0.1
+//                     2001:db8::1
+//
+// Older versions of systemd put each value on its own line. Links without
+// any DNS setting have an empty scope.
+fn parse_status(output: &str) -> EffectiveDns {
+    let mut effective = EffectiveDns::default();
+    let mut in_global = false;
+    let mut key = "";
+    for line in output.lines() {
+        let line = line.trim();
+        if line == "Global" {
+            in_global = true;
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix("Link ")
+            .and_then(|rest| rest.split_once('('))
+            .and_then(|(_, name)| name.strip_suffix(')'))
+        {
+            in_global = false;
+            effective
+                .links
+                .push((name.to_string(), DnsScope::default()));
+            continue;
+        }
+        let scope = if in_global {
+            &mut effective.global
+        } else if let Some((_, scope)) = effective.links.last_mut() {
+            scope
+        } else {
+            continue;
+        };
+        // A line without a key continues the values of the previous key.
+        let value = if let Some((k, value)) = line.split_once(": ") {
+            key = k;
+            value
+        } else if let Some(k) = line.strip_suffix(':') {
+            key = k;
+            ""
+        } else {
+            line
+        };
+        let mut values = value.split_whitespace().map(ToString::to_string);
+        match key {
+            "Current DNS Server" => scope.current_server = values.next(),
+            "DNS Servers" => scope.servers.extend(values),
+            "DNS Domain" => scope.domains.extend(values),
+            _ => {}
+        }
+    }
+    effective
+}
+
+fn validate_ifname(ifname: &str) -> Result<()> {
+    let valid = !ifname.is_empty()
+        && ifname
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!("invalid interface name: {}", ifname))
+    }
+}
+
+// Runs `resolvectl <cmd> <ifname> <values>`. An empty string clears the values.
+fn run_resolvectl_link(cmd: &str, ifname: &str, values: &[String]) -> Result<()> {
+    let mut args = vec![cmd, "--", ifname];
+    if values.is_empty() {
+        args.push("");
+    } else {
+        args.extend(values.iter().map(String::as_str));
+    }
+    run_command_output("resolvectl", &args)?;
+    Ok(())
+}
+
+// Parses the output of `resolvectl dns|domain <ifname>`, e.g.
+// "Link 2 (eth0): 8.8.8.8 1.1.1.1".
+fn link_values(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once("): "))
+        .flat_map(|(_, values)| values.split_whitespace())
+        .map(ToString::to_string)
+        .collect()
+}
+
+// True if the domain is a valid DNS name, e.g. "example.com" or "corp.". Each
+// label has letters, digits, and hyphens, and does not start or end with a
+// hyphen.
+pub(crate) fn is_valid_domain(domain: &str) -> bool {
+    let name = domain.strip_suffix('.').unwrap_or(domain);
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+// Writes `key=values` in the [Resolve] section of
+// /etc/systemd/resolved.conf, and restarts systemd-resolved.
+fn set_resolve_values(key: &str, values: &[String]) -> Result<()> {
+    let contents = read_resolved_conf()?;
+    write_resolved_conf(&replace_values(&contents, key, values))?;
+    restart_resolved()
+}
+
+// Writes /etc/systemd/resolved.conf through a temporary file renamed over it,
+// so that systemd-resolved never reads it half written. The previous file is
+// backed up to a snapshot first, if it exists.
+fn write_resolved_conf(contents: &str) -> Result<()> {
+    if Path::new(RESOLVED_CONF).exists() {
+        backup::snapshot(RESOLVED_CONF)?;
+    }
+    let tmp = format!("{RESOLVED_CONF}.tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, RESOLVED_CONF)?;
+    Ok(())
+}
+
+// Reads /etc/systemd/resolved.conf. An empty string if it does not exist.
+fn read_resolved_conf() -> Result<String> {
+    match fs::read_to_string(RESOLVED_CONF) {
+        Ok(contents) => Ok(contents),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn restart_resolved() -> Result<()> {
+    if restart_service(RESOLVED_SERVICE)? {
+        Ok(())
+    } else {
+        Err(anyhow!("fail to restart {}", RESOLVED_SERVICE))
+    }
+}
+
+// Reads the values of `key=` in the [Resolve] section of
+// /etc/systemd/resolved.conf.
+fn get_resolve_values(key: &str) -> Result<Vec<String>> {
+    let contents = match fs::read_to_string(RESOLVED_CONF) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(resolve_values(&contents, key))
+}
+
+// Parses the values of `key=` in the [Resolve] section of resolved.conf.
+fn resolve_values(contents: &str, key: &str) -> Vec<String> {
+    let mut in_resolve = false;
+    let mut values = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_resolve = line == RESOLVE_SECTION;
+        } else if in_resolve {
+            if let Some(value) = key_value(line, key) {
+                // A later line overrides the earlier ones.
+                values = value.split_whitespace().map(ToString::to_string).collect();
+            }
+        }
+    }
+    values
+}
+
+// Returns the value if the line is `<key>=...`.
+fn key_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let (k, value) = line.split_once('=')?;
+    (k.trim() == key).then_some(value)
+}
+
+// Replaces `<key>=` lines in the [Resolve] section with `values`. The new
+// line is put right after the section header, which is added if not found.
+fn replace_values(contents: &str, key: &str, values: &[String]) -> String {
+    let new_line = (!values.is_empty()).then(|| format!("{key}={}", values.join(" ")));
+    let mut lines = Vec::new();
+    let mut in_resolve = false;
+    let mut found_section = false;
+    let mut inserted = false;
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            in_resolve = trimmed == RESOLVE_SECTION;
+            lines.push(line.to_string());
+            if in_resolve && !found_section {
+                found_section = true;
+                if let Some(new_line) = &new_line {
+                    lines.push(new_line.clone());
+                    inserted = true;
+                }
+            }
+        } else if in_resolve && key_value(trimmed, key).is_some() {
+            continue;
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+    if let (Some(new_line), false) = (new_line, inserted) {
+        lines.push(RESOLVE_SECTION.to_string());
+        lines.push(new_line);
+    }
+    let mut ret = lines.join("\n");
+    ret.push('\n');
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_status_global_and_links() {
+        let output = "\
+Global
+         Protocols: -LLMNR -mDNS -DNSOverTLS DNSSEC=no/unsupported
+  resolv.conf mode: stub
+Current DNS Server: 8.8.8.8
+       DNS Servers: 8.8.8.8 1.1.1.1
+        DNS Domain: corp.example.com
+
+Link 2 (eth0)
+    Current Scopes: DNS
+Current DNS Server: 192.168.0.1
+       DNS Servers: 192.168.0.1
+                    2001:db8::1
+        DNS Domain: ~.
+
+Link 3 (eth1)
+    Current Scopes: none
+";
+        let effective = parse_status(output);
+        assert_eq!(
+            effective.global,
+            DnsScope {
+                current_server: Some("8.8.8.8".to_string()),
+                servers: vec!["8.8.8.8".to_string(), "1.1.1.1".to_string()],
+                domains: vec!["corp.example.com".to_string()],
+            }
+        );
+        assert_eq!(
+            effective.links,
+            vec![
+                (
+                    "eth0".to_string(),
+                    DnsScope {
+                        current_server: Some("192.168.0.1".to_string()),
+                        servers: vec!["192.168.0.1".to_string(), "2001:db8::1".to_string()],
+                        domains: vec!["~.".to_string()],
+                    }
+                ),
+                ("eth1".to_string(), DnsScope::default()),
+            ]
+        );
+    }
+
+    const RESOLVED_CONF_SAMPLE: &str = "\
+#  This file is part of systemd.
+[Resolve]
+#DNS=
+DNS=8.8.8.8
+FallbackDNS=1.1.1.1
+Domains=corp.example.com
+DNS=8.8.4.4 9.9.9.9
+
+[Manager]
+DNS=10.0.0.1
+";
+
+    #[test]
+    fn resolve_values_of_resolve_section() {
+        assert_eq!(
+            resolve_values(RESOLVED_CONF_SAMPLE, DNS_KEY),
+            vec!["8.8.4.4".to_string(), "9.9.9.9".to_string()]
+        );
+        assert_eq!(
+            resolve_values(RESOLVED_CONF_SAMPLE, DOMAINS_KEY),
+            vec!["corp.example.com".to_string()]
+        );
+        assert!(resolve_values(RESOLVED_CONF_SAMPLE, "DNSSEC").is_empty());
+        assert!(resolve_values("DNS=8.8.8.8\n", DNS_KEY).is_empty());
+    }
+
+    #[test]
+    fn replace_values_in_resolve_section() {
+        let servers = vec!["1.1.1.1".to_string(), "1.0.0.1".to_string()];
+        assert_eq!(
+            replace_values(RESOLVED_CONF_SAMPLE, DNS_KEY, &servers),
+            "\
+#  This file is part of systemd.
+[Resolve]
+DNS=1.1.1.1 1.0.0.1
+#DNS=
+FallbackDNS=1.1.1.1
+Domains=corp.example.com
+
+[Manager]
+DNS=10.0.0.1
+"
+        );
+
+        let replaced = replace_values(RESOLVED_CONF_SAMPLE, DNS_KEY, &[]);
+        assert!(resolve_values(&replaced, DNS_KEY).is_empty());
+        assert!(replaced.contains("[Manager]\nDNS=10.0.0.1\n"));
+    }
+
+    #[test]
+    fn replace_values_adds_resolve_section() {
+        let domains = vec!["example.com".to_string()];
+        assert_eq!(
+            replace_values("", DOMAINS_KEY, &domains),
+            "[Resolve]\nDomains=example.com\n"
+        );
+        assert_eq!(
+            replace_values("[Manager]\n", DOMAINS_KEY, &domains),
+            "[Manager]\n[Resolve]\nDomains=example.com\n"
+        );
+    }
+
+    #[test]
+    fn valid_domains() {
+        assert!(is_valid_domain("example.com"));
+        assert!(is_valid_domain("example.com."));
+        assert!(is_valid_domain("my-host.corp.example.com"));
+        assert!(is_valid_domain("localdomain"));
+        assert!(!is_valid_domain(""));
+        assert!(!is_valid_domain("."));
+        assert!(!is_valid_domain("-example.com"));
+        assert!(!is_valid_domain("example-.com"));
+        assert!(!is_valid_domain("example..com"));
+        assert!(!is_valid_domain("exa_mple.com"));
+        assert!(!is_valid_domain(&format!("{}.com", "a".repeat(64))));
+        assert!(!is_valid_domain(&["a"; 128].join(".")));
+    }
+}
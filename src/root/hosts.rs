@@ -0,0 +1,172 @@
+use super::{dns::is_valid_domain, write_verified};
+use anyhow::{anyhow, Result};
+use std::{fs, net::IpAddr};
+
+pub(crate) const HOSTS_PATH: &str = "/etc/hosts";
+
+// Gets the static host entries in /etc/hosts.
+//
+// # Return
+//
+// * Vec<(String, Vec<String>)>: IP address and its hostnames, in the order of
+//   the file
+//
+// # Errors
+//
+// * fail to open /etc/hosts
+pub(crate) fn list() -> Result<Vec<(String, Vec<String>)>> {
+    let contents = fs::read_to_string(HOSTS_PATH)?;
+    Ok(contents.lines().filter_map(parse_line).collect())
+}
+
+// Sets the hostnames of the IP address in /etc/hosts. The first line of the
+// address is replaced and the other lines of it are removed, so setting the
+// same entry again changes nothing. If the address has no line, a new line is
+// added at the end. Comments and other lines are kept.
+//
+// # Example
+//
+// hosts::set("192.168.0.10", &["peer1".to_string(), "peer1.corp".to_string()])?;
+//
+// # Errors
+//
+// * invalid IP address or hostname, or no hostname
+// * fail to open or write /etc/hosts, or `WriteVerifyFailed` if it does not
+//   read back as written
+pub(crate) fn set(ip: &str, names: &[String]) -> Result<()> {
+    let addr = ip
+        .parse::<IpAddr>()
+        .map_err(|e| anyhow!("invalid IP address: {}. {:?}", ip, e))?;
+    if names.is_empty() {
+        return Err(anyhow!("no hostname for {}", ip));
+    }
+    if let Some(name) = names.iter().find(|name| !is_valid_domain(name)) {
+        return Err(anyhow!("invalid hostname: {}", name));
+    }
+
+    let contents = fs::read_to_string(HOSTS_PATH)?;
+    write_verified(HOSTS_PATH, &set_entry(&contents, addr, names))
+}
+
+// Removes the entry of the IP address, or the hostname from every entry, in
+// /etc/hosts. An entry without hostnames left is removed. Nothing is changed if
+// neither is found.
+//
+// # Errors
+//
+// * fail to open or write /etc/hosts, or `WriteVerifyFailed` if it does not
+//   read back as written
+pub(crate) fn remove(ip_or_name: &str) -> Result<()> {
+    let contents = fs::read_to_string(HOSTS_PATH)?;
+    let new_contents = remove_entry(&contents, ip_or_name);
+    if new_contents == contents {
+        return Ok(());
+    }
+    write_verified(HOSTS_PATH, &new_contents)
+}
+
+// Parses a line like "192.168.0.10 peer1 peer1.corp # comment". `None` for a
+// comment, an empty line, or a line without a valid address.
+fn parse_line(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.split_once('#').map_or(line, |(entry, _)| entry);
+    let mut words = line.split_whitespace();
+    let ip = words.next()?;
+    ip.parse::<IpAddr>().ok()?;
+    Some((ip.to_string(), words.map(ToString::to_string).collect()))
+}
+
+fn line_addr(line: &str) -> Option<IpAddr> {
+    parse_line(line).and_then(|(ip, _)| ip.parse::<IpAddr>().ok())
+}
+
+fn set_entry(contents: &str, addr: IpAddr, names: &[String]) -> String {
+    let new_line = format!("{addr}\t{}", names.join(" "));
+    let mut lines = Vec::new();
+    let mut replaced = false;
+    for line in contents.lines() {
+        if line_addr(line) != Some(addr) {
+            lines.push(line.to_string());
+        } else if !replaced {
+            lines.push(new_line.clone());
+            replaced = true;
+        }
+    }
+    if !replaced {
+        lines.push(new_line);
+    }
+    join_lines(&lines)
+}
+
+fn remove_entry(contents: &str, ip_or_name: &str) -> String {
+    let addr = ip_or_name.parse::<IpAddr>().ok();
+    let mut lines = Vec::new();
+    for line in contents.lines() {
+        match parse_line(line) {
+            Some((ip, _)) if addr.is_some() && ip.parse::<IpAddr>().ok() == addr => {}
+            Some((ip, names)) if addr.is_none() && names.iter().any(|name| name == ip_or_name) => {
+                let names = names
+                    .into_iter()
+                    .filter(|name| name != ip_or_name)
+                    .collect::<Vec<_>>();
+                if !names.is_empty() {
+                    lines.push(format!("{ip}\t{}", names.join(" ")));
+                }
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+    join_lines(&lines)
+}
+
+fn join_lines(lines: &[String]) -> String {
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTS: &str = "\
+127.0.0.1\tlocalhost
+# peers
+192.168.0.10\tpeer1
+::1\tip6-localhost ip6-loopback
+192.168.0.10\tpeer1-old
+";
+
+    #[test]
+    fn set_entry_replaces_address() {
+        let names = vec!["peer1".to_string(), "peer1.corp".to_string()];
+        let addr = "192.168.0.10".parse().unwrap();
+        let new_contents = set_entry(HOSTS, addr, &names);
+        assert_eq!(
+            new_contents,
+            "\
+127.0.0.1\tlocalhost
+# peers
+192.168.0.10\tpeer1 peer1.corp
+::1\tip6-localhost ip6-loopback
+"
+        );
+        assert_eq!(set_entry(&new_contents, addr, &names), new_contents);
+    }
+
+    #[test]
+    fn remove_entry_by_address_or_name() {
+        assert_eq!(
+            remove_entry(HOSTS, "192.168.0.10"),
+            "127.0.0.1\tlocalhost\n# peers\n::1\tip6-localhost ip6-loopback\n"
+        );
+        assert_eq!(
+            remove_entry(HOSTS, "ip6-loopback"),
+            "\
+127.0.0.1\tlocalhost
+# peers
+192.168.0.10\tpeer1
+::1\tip6-localhost
+192.168.0.10\tpeer1-old
+"
+        );
+        assert_eq!(remove_entry(HOSTS, "unknown"), HOSTS);
+    }
+}
@@ -1,15 +1,21 @@
-use super::SubCommand;
+use super::{run_command_output, write_verified, SubCommand};
 use anyhow::{anyhow, Result};
-use std::{
-    fs::{self, OpenOptions},
-    io::Write as IoWrite,
-};
+use roxy::common::VERSION_PATH;
+use std::fs;
 
-// TODO: should change this path to /usr/local/aice/conf/version?
-const DEFAULT_VERSION_PATH: &str = "/etc/version";
+// Gets the current hostname as systemd-hostnamed reports it, i.e. the
+// hostname of the kernel, which `hostname::set` changes. If hostnamectl fails,
+// e.g. systemd-hostnamed is not available, the kernel is asked directly.
+pub(crate) fn current_hostname() -> String {
+    run_command_output("hostnamectl", &["--transient"])
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(roxy::hostname)
+}
 
 pub(crate) fn set_version(kind: SubCommand, arg: &str) -> Result<()> {
-    let contents = fs::read_to_string(DEFAULT_VERSION_PATH)?;
+    let contents = fs::read_to_string(VERSION_PATH)?;
     let lines = contents.lines();
     let mut new_contents = String::new();
     for line in lines {
@@ -40,11 +46,5 @@ pub(crate) fn set_version(kind: SubCommand, arg: &str) -> Result<()> {
     new_contents.push_str(&new_version);
     new_contents.push('\n');
 
-    let mut file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(DEFAULT_VERSION_PATH)?;
-
-    file.write_all(new_contents.as_bytes())?;
-    Ok(())
+    write_verified(VERSION_PATH, &new_contents)
 }
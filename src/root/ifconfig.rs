@@ -1,40 +1,64 @@
-use super::{Nic, NicOutput};
+use super::{
+    dns::is_valid_domain, run_command, run_command_output, run_command_output_timeout, AccessPoint,
+    AddressDrift, Bridge, Dhcp4Overrides, FieldChange, InterfaceChange, NetplanDiff,
+    NetworkSnapshot, Nic, NicMatch, NicOutput, RingSize, Route, Verification, Vlan, Wifi,
+    MAX_NAMESERVERS, PASSWORD_MASK,
+};
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Local};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use ipnet::IpNet;
-use pnet::datalink::interfaces;
-use roxy::common::DEFAULT_PATH_ENV;
+use nix::{
+    errno::Errno,
+    fcntl::{flock, FlockArg},
+};
+use pnet::{datalink::interfaces, util::MacAddr};
 use serde_derive::{Deserialize, Serialize};
 use serde_with::serde_as;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
     fmt,
-    fs::{self, File, OpenOptions},
+    fs::{self, DirBuilder, File, OpenOptions},
     io::{Read, Write},
-    net::IpAddr,
-    process::Command,
+    net::{IpAddr, Ipv4Addr},
+    os::unix::{
+        fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+        io::AsRawFd,
+    },
+    path::PathBuf,
+    process, thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use thiserror::Error;
 
-const NETPLAN_PATH: &str = "/etc/netplan";
-const DEFAULT_NETPLAN_YAML: &str = "01-netcfg.yaml";
+// Fields of a netplan setting in JSON, by name.
+type Fields = BTreeMap<String, serde_json::Value>;
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Address {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    search: Option<Vec<String>>,
-    addresses: Option<Vec<String>>,
-}
+// Interface settings, and the netplan yaml files skipped with their errors.
+type InterfacesOutput = (Option<Vec<(String, NicOutput)>>, Vec<(String, String)>);
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Bridge {
-    interfaces: Vec<String>,
-    addresses: Vec<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    gateway4: Option<String>,
-    nameservers: Address,
-}
+const NETPLAN_PATH: &str = "/etc/netplan";
+const DEFAULT_NETPLAN_YAML: &str = "01-netcfg.yaml";
+// Netplan yaml files may contain wifi passwords, so only root can read them.
+const NETPLAN_FILE_MODE: u32 = 0o600;
+// Hidden files in /etc/netplan, e.g. `METADATA_PATH`, are not netplan yaml.
+const HIDDEN_FILES: &[&str] = &["."];
+const METADATA_PATH: &str = "/etc/netplan/.roxy-meta.json";
+// The tarball of the netplan yaml files taken before each netplan edit. It is
+// hidden, so that it is not read as a yaml file, and `restore_configs` can
+// restore it.
+const NETPLAN_BACKUP: &str = ".roxy.bak.tar.gz";
+const MAX_DESCRIPTION_LEN: usize = 256;
+// The maximum length of an interface name in Linux, without the trailing nul.
+const MAX_IFNAME_LEN: usize = 15;
+const NETPLAN_LOCK_PATH: &str = "/run/roxy-netplan.lock";
+const NETPLAN_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+// `netplan apply` may hang on a stuck interface.
+const NETPLAN_APPLY_TIMEOUT: Duration = Duration::from_secs(120);
+// The number of echo requests `set` sends to the gateway.
+const GATEWAY_PING_COUNT: u32 = 3;
 
-// only support ethernets, bridges. No wifis support.
+// only support ethernets, bridges, vlans, wifis.
 #[serde_as]
 #[derive(Debug, Deserialize, Serialize)]
 struct Network {
@@ -46,6 +70,10 @@ struct Network {
     ethernets: Vec<(String, Nic)>,
     #[serde(skip_serializing_if = "Option::is_none")]
     bridges: Option<HashMap<String, Bridge>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vlans: Option<HashMap<String, Vlan>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wifis: Option<HashMap<String, Wifi>>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -75,6 +103,20 @@ impl NetplanYaml {
         }
     }
 
+    // The minimal conf for a system without netplan yaml files.
+    fn bootstrap(renderer: &str) -> Self {
+        NetplanYaml {
+            network: Network {
+                version: Some(2),
+                renderer: Some(renderer.to_string()),
+                ethernets: Vec::new(),
+                bridges: None,
+                vlans: None,
+                wifis: None,
+            },
+        }
+    }
+
     // Merges two yaml conf into one. The merged conf will applied to system when save() is called.
     fn merge(&mut self, newyml: Self) {
         if newyml.network.version.is_some() {
@@ -83,7 +125,8 @@ impl NetplanYaml {
         if newyml.network.renderer.is_some() {
             self.network.renderer = newyml.network.renderer;
         }
-        for (ifname, ifcfg) in newyml.network.ethernets {
+        for (ifname, mut ifcfg) in newyml.network.ethernets {
+            ifcfg.dedup_nameservers();
             if let Some(item) = self.network.ethernets.iter_mut().find(|x| x.0 == ifname) {
                 item.1 = ifcfg;
             } else {
@@ -101,8 +144,233 @@ impl NetplanYaml {
                         self_bridges.insert(ifname, bridgecfg);
                     }
                 }
+            } else {
+                self.network.bridges = Some(new_bridges);
+            }
+        }
+
+        if let Some(new_vlans) = newyml.network.vlans {
+            self.network
+                .vlans
+                .get_or_insert_with(HashMap::new)
+                .extend(new_vlans);
+        }
+
+        if let Some(new_wifis) = newyml.network.wifis {
+            self.network
+                .wifis
+                .get_or_insert_with(HashMap::new)
+                .extend(new_wifis);
+        }
+    }
+
+    // Converts into the snapshot. Bridges, vlans, and wifis are sorted by name.
+    fn to_snapshot(&self) -> NetworkSnapshot {
+        let mut bridges = self
+            .network
+            .bridges
+            .iter()
+            .flatten()
+            .map(|(name, bridge)| (name.clone(), bridge.clone()))
+            .collect::<Vec<_>>();
+        bridges.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut vlans = self
+            .network
+            .vlans
+            .iter()
+            .flatten()
+            .map(|(name, vlan)| (name.clone(), vlan.clone()))
+            .collect::<Vec<_>>();
+        vlans.sort_by(|a, b| a.0.cmp(&b.0));
+        let wifis = self.wifis();
+
+        NetworkSnapshot {
+            version: self.network.version,
+            renderer: self.network.renderer.clone(),
+            ethernets: self.network.ethernets.clone(),
+            bridges,
+            vlans,
+            wifis,
+        }
+    }
+
+    fn from_snapshot(snapshot: NetworkSnapshot) -> Self {
+        let mut ethernets = snapshot.ethernets;
+        ethernets.sort_by(|a, b| a.0.cmp(&b.0));
+        let bridges = if snapshot.bridges.is_empty() {
+            None
+        } else {
+            Some(snapshot.bridges.into_iter().collect())
+        };
+        let vlans = if snapshot.vlans.is_empty() {
+            None
+        } else {
+            Some(snapshot.vlans.into_iter().collect())
+        };
+        let wifis = if snapshot.wifis.is_empty() {
+            None
+        } else {
+            Some(snapshot.wifis.into_iter().collect())
+        };
+
+        NetplanYaml {
+            network: Network {
+                version: snapshot.version,
+                renderer: snapshot.renderer,
+                ethernets,
+                bridges,
+                vlans,
+                wifis,
+            },
+        }
+    }
+
+    // Compares with `other`, the configuration after a change. Interfaces are
+    // compared by name, and each field of them is compared. Since wifi
+    // passwords are masked, a changed password is reported as a change of
+    // `access-points` whose values look the same.
+    fn diff(&self, other: &Self) -> NetplanDiff {
+        let old = self.interface_fields();
+        let new = other.interface_fields();
+        let mut diff = NetplanDiff {
+            network: diff_fields(&self.network_fields(), &other.network_fields()),
+            ..NetplanDiff::default()
+        };
+        diff.removed = old
+            .keys()
+            .filter(|name| !new.contains_key(*name))
+            .cloned()
+            .collect();
+        for (name, (new_fields, new_raw)) in &new {
+            let Some((old_fields, old_raw)) = old.get(name) else {
+                diff.added.push(name.clone());
+                continue;
+            };
+            let mut fields = diff_fields(old_fields, new_fields);
+            if fields.is_empty() && old_raw != new_raw {
+                let value = new_fields.get("access-points").map(ToString::to_string);
+                fields.push(FieldChange {
+                    field: "access-points".to_string(),
+                    old: value.clone(),
+                    new: value,
+                });
+            }
+            if !fields.is_empty() {
+                diff.changed.push(InterfaceChange {
+                    name: name.clone(),
+                    fields,
+                });
             }
         }
+        diff
+    }
+
+    // Builds the configuration of the running interfaces, with the addresses
+    // of each interface in `running`, to compare with this one. Interfaces
+    // which are not running are left out. Other settings cannot be read from
+    // the running interfaces, so they are copied as they are.
+    fn live(&self, running: &HashMap<String, Vec<IpNet>>) -> Self {
+        let mut snapshot = self.to_snapshot();
+        snapshot.ethernets.retain_mut(|(name, nic)| {
+            let Some(live) = running.get(name) else {
+                return false;
+            };
+            nic.addresses = live_addresses(nic.addresses.as_deref(), live, nic.dhcp4 == Some(true));
+            true
+        });
+        snapshot.bridges.retain_mut(|(name, bridge)| {
+            let Some(live) = running.get(name) else {
+                return false;
+            };
+            bridge.addresses =
+                live_addresses(Some(&bridge.addresses), live, false).unwrap_or_default();
+            true
+        });
+        snapshot.vlans.retain_mut(|(name, vlan)| {
+            let Some(live) = running.get(name) else {
+                return false;
+            };
+            vlan.addresses = live_addresses(vlan.addresses.as_deref(), live, false);
+            true
+        });
+        snapshot.wifis.retain_mut(|(name, wifi)| {
+            let Some(live) = running.get(name) else {
+                return false;
+            };
+            wifi.addresses =
+                live_addresses(wifi.addresses.as_deref(), live, wifi.dhcp4 == Some(true));
+            true
+        });
+        Self::from_snapshot(snapshot)
+    }
+
+    fn network_fields(&self) -> Fields {
+        [
+            ("version", self.network.version.map(serde_json::Value::from)),
+            (
+                "renderer",
+                self.network.renderer.clone().map(serde_json::Value::from),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(field, value)| Some((field.to_string(), value?)))
+        .collect()
+    }
+
+    // Gets the fields of ethernets, bridges, vlans, and wifis by name, with
+    // passwords masked. The unmasked fields are also kept, to find changed
+    // passwords.
+    fn interface_fields(&self) -> BTreeMap<String, (Fields, Fields)> {
+        let mut ret = BTreeMap::new();
+        for (name, nic) in &self.network.ethernets {
+            let nic = fields(nic);
+            ret.insert(name.clone(), (nic.clone(), nic));
+        }
+        for (name, bridge) in self.network.bridges.iter().flatten() {
+            let bridge = fields(bridge);
+            ret.insert(name.clone(), (bridge.clone(), bridge));
+        }
+        for (name, vlan) in self.network.vlans.iter().flatten() {
+            let vlan = fields(vlan);
+            ret.insert(name.clone(), (vlan.clone(), vlan));
+        }
+        for (name, mut wifi) in self.wifis() {
+            let raw = fields(&wifi);
+            wifi.mask_passwords();
+            ret.insert(name, (fields(&wifi), raw));
+        }
+        ret
+    }
+
+    // True if an ethernet, bridge, vlan, or wifi is named `ifname`.
+    fn defines(&self, ifname: &str) -> bool {
+        let network = &self.network;
+        network.ethernets.iter().any(|(name, _)| name == ifname)
+            || network
+                .bridges
+                .as_ref()
+                .is_some_and(|b| b.contains_key(ifname))
+            || network
+                .vlans
+                .as_ref()
+                .is_some_and(|v| v.contains_key(ifname))
+            || network
+                .wifis
+                .as_ref()
+                .is_some_and(|w| w.contains_key(ifname))
+    }
+
+    // Returns wifis sorted by name.
+    fn wifis(&self) -> Vec<(String, Wifi)> {
+        let mut wifis = self
+            .network
+            .wifis
+            .iter()
+            .flatten()
+            .map(|(name, wifi)| (name.clone(), wifi.clone()))
+            .collect::<Vec<_>>();
+        wifis.sort_by(|a, b| a.0.cmp(&b.0));
+        wifis
     }
 
     // apply() should be run to apply this change.
@@ -115,12 +383,184 @@ impl NetplanYaml {
         }
     }
 
+    // Sets the ethernet matched by the MAC address with the name. Fails if
+    // another ethernet already matches the MAC address or sets the name.
+    // apply() should be run to apply this change.
+    fn set_interface_by_mac(&mut self, mac: &str, ifname: &str, mut new_if: Nic) -> Result<()> {
+        for (name, nic) in &self.network.ethernets {
+            if name == ifname {
+                continue;
+            }
+            if nic.set_name.as_deref() == Some(ifname) {
+                return Err(anyhow!(
+                    "\"{}\" is already set as the name of {}",
+                    ifname,
+                    name
+                ));
+            }
+            let matched = nic
+                .matching
+                .as_ref()
+                .and_then(|m| m.macaddress.as_deref())
+                .and_then(|m| parse_mac(m).ok());
+            if matched.is_some_and(|m| m.to_string() == mac) {
+                return Err(anyhow!(
+                    "MAC address {} is already matched by {}",
+                    mac,
+                    name
+                ));
+            }
+        }
+        new_if.matching = Some(NicMatch {
+            macaddress: Some(mac.to_string()),
+        });
+        new_if.set_name = Some(ifname.to_string());
+        self.set_interface(ifname, new_if);
+        Ok(())
+    }
+
+    // Renames the ethernet `old` to `new` by matching its MAC address and
+    // setting `set-name`, keeping its settings. Bridges and vlans on `old`
+    // are moved to `new`. apply() should be run to apply this change.
+    fn rename_interface(&mut self, old: &str, new: &str, mac: &str) -> Result<()> {
+        if self.defines(new) {
+            return Err(anyhow!("\"{}\" is already defined in netplan", new));
+        }
+        let nic = match self
+            .network
+            .ethernets
+            .iter()
+            .position(|(name, _)| name == old)
+        {
+            Some(i) => self.network.ethernets.remove(i).1,
+            None => Nic::new(None, None, None, None, None),
+        };
+        self.set_interface_by_mac(mac, new, nic)?;
+        for bridge in self
+            .network
+            .bridges
+            .iter_mut()
+            .flat_map(HashMap::values_mut)
+        {
+            for ifname in &mut bridge.interfaces {
+                if ifname == old {
+                    *ifname = new.to_string();
+                }
+            }
+        }
+        for vlan in self.network.vlans.iter_mut().flat_map(HashMap::values_mut) {
+            if vlan.link == old {
+                vlan.link = new.to_string();
+            }
+        }
+        Ok(())
+    }
+
     // apply() should be run to apply this change.
     fn init_interface(&mut self, ifname: &str) {
         let new_if = Nic::new(None, None, None, None, None);
         Self::set_interface(self, ifname, new_if);
     }
 
+    // Sets dhcp4-overrides and dhcp-identifier of the ethernet. `None` removes
+    // them. apply() should be run to apply this change.
+    fn set_dhcp_overrides(
+        &mut self,
+        ifname: &str,
+        overrides: Option<Dhcp4Overrides>,
+        identifier: Option<String>,
+    ) -> Result<()> {
+        let (_, nic) = self
+            .network
+            .ethernets
+            .iter_mut()
+            .find(|x| x.0 == *ifname)
+            .ok_or_else(|| anyhow!("Interface {} not found", ifname))?;
+        if (overrides.is_some() || identifier.is_some()) && nic.dhcp4 != Some(true) {
+            return Err(anyhow!("dhcp4 is not set in {}", ifname));
+        }
+        nic.dhcp4_overrides = overrides;
+        nic.dhcp_identifier = identifier;
+        Ok(())
+    }
+
+    // Sets accept-ra of the interface. apply() should be run to apply this change.
+    fn set_accept_ra(&mut self, ifname: &str, accept: bool) -> Result<()> {
+        let (_, nic) = self
+            .network
+            .ethernets
+            .iter_mut()
+            .find(|x| x.0 == *ifname)
+            .ok_or_else(|| anyhow!("Interface {} not found", ifname))?;
+        nic.accept_ra = Some(accept);
+        Ok(())
+    }
+
+    // Marks every ethernet except the given ones as `optional: true`, so that
+    // booting does not wait for them. The exceptions are left as they are.
+    // apply() should be run to apply this change.
+    //
+    // # Return
+    //
+    // * the names of the ethernets marked as optional
+    fn set_all_optional(&mut self, except: &[String]) -> Result<Vec<String>> {
+        if let Some(ifname) = except.iter().find(|ifname| {
+            !self
+                .network
+                .ethernets
+                .iter()
+                .any(|(name, _)| name == *ifname)
+        }) {
+            return Err(anyhow!("Interface {} not found", ifname));
+        }
+        let mut marked = Vec::new();
+        for (ifname, nic) in &mut self.network.ethernets {
+            if !except.contains(ifname) {
+                nic.optional = Some(true);
+                marked.push(ifname.clone());
+            }
+        }
+        Ok(marked)
+    }
+
+    // Appends the values to `nameservers.<key>` of the ethernet, i.e.
+    // "addresses" or "search", skipping the values already in it.
+    // apply() should be run to apply this change.
+    fn add_nameservers(&mut self, ifname: &str, key: &str, values: &[String]) -> Result<&Nic> {
+        let (_, nic) = self
+            .network
+            .ethernets
+            .iter_mut()
+            .find(|x| x.0 == *ifname)
+            .ok_or_else(|| anyhow!("Interface {} not found", ifname))?;
+        if key == "addresses" && nic.dhcp4 == Some(true) {
+            return Err(anyhow!(
+                "dhcp4 and static address cannot be set in the same interface"
+            ));
+        }
+        let current = nic
+            .nameservers
+            .get_or_insert_with(HashMap::new)
+            .entry(key.to_string())
+            .or_default();
+        for value in values {
+            if !current.contains(value) {
+                current.push(value.clone());
+            }
+        }
+        Ok(nic)
+    }
+
+    // Removes the whole ethernet entry. apply() should be run to apply this change.
+    fn remove_interface(&mut self, ifname: &str) -> Result<()> {
+        let len = self.network.ethernets.len();
+        self.network.ethernets.retain(|(name, _)| name != ifname);
+        if self.network.ethernets.len() == len {
+            return Err(anyhow!("Interface {} not found", ifname));
+        }
+        Ok(())
+    }
+
     // Removes interface address, gateway4, nameservers. apply() should be run to apply this change.
     // Use set() command instead of delete() if possible
     fn delete(&mut self, ifname: &str, nic_output: &NicOutput) -> Result<()> {
@@ -138,8 +578,16 @@ impl NetplanYaml {
             }
         }
 
-        if nic_output.gateway4.is_some() && ifs.gateway4 == nic_output.gateway4 {
-            ifs.gateway4 = None;
+        if let Some(gateway4) = &nic_output.gateway4 {
+            if ifs.gateway4.as_ref() == Some(gateway4) {
+                ifs.gateway4 = None;
+            }
+            if let Some(routes) = &mut ifs.routes {
+                routes.retain(|r| !(r.is_default() && r.via.as_ref() == Some(gateway4)));
+                if routes.is_empty() {
+                    ifs.routes = None;
+                }
+            }
         }
 
         if let Some(addrs) = &nic_output.nameservers {
@@ -157,58 +605,248 @@ impl NetplanYaml {
         Ok(())
     }
 
-    // TODO: synchronize /etc/netplan/--yaml vs nic running conf
-    // pub fn sync(&self, _dir: &str) -> usize {
-    //     0
-    // }
+    // Compares the addresses of each ethernet with the running interface. Only
+    // the interfaces with different addresses are returned.
+    //
+    // Addresses from DHCP and IPv6 link-local addresses are not configured in
+    // yaml, so they are not reported as missing in yaml.
+    fn sync(&self) -> Vec<(String, AddressDrift)> {
+        let running = interfaces();
+        let mut ret = Vec::new();
+        for (ifname, nic) in &self.network.ethernets {
+            let configured = nic
+                .addresses
+                .iter()
+                .flatten()
+                .filter_map(|addr| addr.parse::<IpNet>().ok())
+                .collect::<Vec<_>>();
+            let live = running
+                .iter()
+                .find(|iface| iface.name == *ifname)
+                .map(|iface| {
+                    iface
+                        .ips
+                        .iter()
+                        .filter_map(|ip| ip.to_string().parse::<IpNet>().ok())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+
+            let mut drift = AddressDrift::default();
+            for addr in &live {
+                if configured.contains(addr) || is_link_local(addr) {
+                    continue;
+                }
+                if nic.dhcp4 == Some(true) && matches!(addr, IpNet::V4(_)) {
+                    continue;
+                }
+                drift.missing_in_yaml.push(addr.to_string());
+            }
+            for addr in &configured {
+                if !live.contains(addr) {
+                    drift.missing_in_link.push(addr.to_string());
+                }
+            }
+
+            if !drift.is_empty() {
+                ret.push((ifname.clone(), drift));
+            }
+        }
+        ret
+    }
+
+    // Gets the metrics of the IPv4 default gateways of every interface, i.e.
+    // ethernets, bridges, vlans, and wifis. `None` is a gateway without metric.
+    fn ipv4_gateways(&self) -> Vec<(&str, Vec<Option<u32>>)> {
+        let network = &self.network;
+        let ethernets = network.ethernets.iter().map(|(name, nic)| {
+            let metrics = ipv4_gateway_metrics(nic.gateway4.as_deref(), nic.routes.as_deref());
+            (name.as_str(), metrics)
+        });
+        let bridges = network.bridges.iter().flatten().map(|(name, bridge)| {
+            let metrics =
+                ipv4_gateway_metrics(bridge.gateway4.as_deref(), bridge.routes.as_deref());
+            (name.as_str(), metrics)
+        });
+        let vlans = network.vlans.iter().flatten().map(|(name, vlan)| {
+            let metrics = ipv4_gateway_metrics(vlan.gateway4.as_deref(), vlan.routes.as_deref());
+            (name.as_str(), metrics)
+        });
+        let wifis = network.wifis.iter().flatten().map(|(name, wifi)| {
+            let metrics = ipv4_gateway_metrics(wifi.gateway4.as_deref(), wifi.routes.as_deref());
+            (name.as_str(), metrics)
+        });
+        ethernets.chain(bridges).chain(vlans).chain(wifis).collect()
+    }
 
     // Saves conf to netplan yaml file, and apply it to system. Merges all yaml files under /etc/netplan folder.
     //
     // The following errors are possible:
     //
     // * fail to get /etc/netplan yaml files
+    // * fail to back up /etc/netplan yaml files to `NETPLAN_BACKUP`
     // * fail to create or write temporary yaml file in /tmp
     // * fail to copy yaml file from /tmp to /etc/netplan
     // * fail to remove temporary file
     // * fail to remove /etc/netplan files except the first yaml file
-    // * fail to run netplan apply command
+    // * fail to run netplan apply command. The error contains its stderr.
+    // * `CommandTimeout` if netplan apply hangs
     fn apply(&self, dir: &str) -> Result<()> {
-        let files = match list_files(dir, None, false) {
-            Ok(r) => r,
-            Err(e) => return Err(e),
-        };
+        snapshot_netplan(dir)?;
+        self.apply_with(dir, &SystemFileSystem, &SystemCommandRunner)
+    }
+
+    // Same as `apply`, but uses the given file system and command runner.
+    fn apply_with(
+        &self,
+        dir: &str,
+        fs: &impl FileSystem,
+        runner: &impl CommandRunner,
+    ) -> Result<()> {
+        let files = fs.list_files(dir)?;
 
         let mut from = format!("/tmp/{DEFAULT_NETPLAN_YAML}");
         let mut to = format!("{dir}/{DEFAULT_NETPLAN_YAML}");
-        if let Some((_, _, first)) = files.first() {
+        if let Some(first) = files.first() {
             if first != DEFAULT_NETPLAN_YAML {
                 from = format!("/tmp/{first}");
                 to = format!("{dir}/{first}");
             }
         }
 
-        let mut tmp = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&from)?;
-        write!(tmp, "{self}")?;
-
-        fs::copy(&from, &to)?;
-        fs::remove_file(&from)?;
+        fs.write(&from, &self.to_string())?;
+        fs.copy(&from, &to)?;
+        fs.remove_file(&from)?;
 
-        for (_, _, file) in &files {
+        for file in &files {
             let path = format!("{dir}/{file}");
             if path != to {
-                fs::remove_file(&path)?;
+                fs.remove_file(&path)?;
             }
         }
 
-        run_command("netplan", &["apply"])?;
+        runner.run("netplan", &["apply"])?;
+        Ok(())
+    }
+}
+
+// File system operations used to save netplan yaml files.
+trait FileSystem {
+    // Gets the sorted file names in the specified folder.
+    fn list_files(&self, dir: &str) -> Result<Vec<String>>;
+    // Creates or truncates the file, and writes the contents. Only the owner
+    // can read or write the file.
+    fn write(&self, path: &str, contents: &str) -> Result<()>;
+    // Copies the file. Only the owner can read or write the copy.
+    fn copy(&self, from: &str, to: &str) -> Result<()>;
+    fn remove_file(&self, path: &str) -> Result<()>;
+}
+
+struct SystemFileSystem;
+
+impl FileSystem for SystemFileSystem {
+    fn list_files(&self, dir: &str) -> Result<Vec<String>> {
+        Ok(list_files(dir, Some(HIDDEN_FILES), false)?
+            .into_iter()
+            .map(|(_, _, file)| file)
+            .collect())
+    }
+
+    fn write(&self, path: &str, contents: &str) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(NETPLAN_FILE_MODE)
+            .open(path)?;
+        // `mode` is ignored if the file exists already
+        file.set_permissions(fs::Permissions::from_mode(NETPLAN_FILE_MODE))?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+
+    fn copy(&self, from: &str, to: &str) -> Result<()> {
+        fs::copy(from, to)?;
+        fs::set_permissions(to, fs::Permissions::from_mode(NETPLAN_FILE_MODE))?;
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &str) -> Result<()> {
+        fs::remove_file(path)?;
         Ok(())
     }
 }
 
+// Runs system commands.
+trait CommandRunner {
+    // Returns stdout of the command. If the command fails, the error contains
+    // its stderr. The command is killed if it runs longer than
+    // `NETPLAN_APPLY_TIMEOUT`.
+    fn run(&self, cmd: &str, args: &[&str]) -> Result<String>;
+}
+
+struct SystemCommandRunner;
+
+impl CommandRunner for SystemCommandRunner {
+    fn run(&self, cmd: &str, args: &[&str]) -> Result<String> {
+        run_command_output_timeout(cmd, args, NETPLAN_APPLY_TIMEOUT)
+    }
+}
+
+// Error returned when another netplan edit holds the lock for too long.
+#[derive(Debug, Error)]
+#[error("another netplan edit is in progress")]
+pub(crate) struct NetplanBusy;
+
+// Error returned when no netplan conf is loaded from the netplan yaml files.
+#[derive(Debug, Error)]
+pub(crate) enum NetplanLoadError {
+    // The directory does not exist, or is not readable.
+    #[error("fail to read {dir}: {reason}")]
+    Unreadable { dir: String, reason: String },
+    // The directory is readable, but has no yaml file, e.g. on a fresh image.
+    #[error("no netplan yaml file in {dir}")]
+    NoYaml { dir: String },
+    // Every yaml file in the directory fails to be parsed.
+    #[error("every netplan yaml file in {dir} fails to be parsed")]
+    AllInvalid { dir: String },
+}
+
+// Exclusive lock to serialize netplan edits from concurrent roxy processes.
+// The lock is released when this is dropped.
+struct NetplanLock {
+    _file: File,
+}
+
+impl NetplanLock {
+    // Acquires the lock, waiting up to `NETPLAN_LOCK_TIMEOUT`.
+    //
+    // # Errors
+    //
+    // * fail to open the lock file
+    // * `NetplanBusy` if the lock is not acquired within the timeout
+    fn acquire() -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(NETPLAN_LOCK_PATH)?;
+        let start = Instant::now();
+        loop {
+            match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+                Ok(()) => return Ok(Self { _file: file }),
+                Err(Errno::EWOULDBLOCK | Errno::EINTR) => {
+                    if start.elapsed() >= NETPLAN_LOCK_TIMEOUT {
+                        return Err(NetplanBusy.into());
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
 // Gets all interface settings. Gets all netplan yaml conf from /etc/netplan and merge it into one.
 //
 // The following errors are possible:
@@ -217,187 +855,1726 @@ impl NetplanYaml {
 // * fail to parse yaml file
 // * yaml file not found
 fn load_netplan_yaml(dir: &str) -> Result<NetplanYaml> {
-    let files = list_files(dir, None, false)?;
+    let (netplan, skipped) = load_netplan_yaml_tolerant(dir)?;
+    if let Some((file, e)) = skipped.into_iter().next() {
+        return Err(anyhow!("{}: {}", file, e));
+    }
+    Ok(netplan)
+}
+
+// Same as `load_netplan_yaml`, but starts from the minimal conf with
+// `DEFAULT_RENDERER` if `dir` has no yaml file, e.g. on a freshly imaged
+// system. Use `bootstrap` beforehand to start with another renderer.
+fn load_netplan_yaml_or_bootstrap(dir: &str) -> Result<NetplanYaml> {
+    match load_netplan_yaml(dir) {
+        Err(e)
+            if matches!(
+                e.downcast_ref::<NetplanLoadError>(),
+                Some(NetplanLoadError::NoYaml { .. })
+            ) =>
+        {
+            Ok(NetplanYaml::bootstrap(DEFAULT_RENDERER))
+        }
+        ret => ret,
+    }
+}
+
+// Same as `load_netplan_yaml`, but skips the files failing to be parsed, so
+// that one bad file does not hide the others. Use this only for read paths.
+//
+// # Return
+//
+// * the merged conf of the parsed files, and the names of the skipped files
+//   with their errors
+//
+// # Errors
+//
+// * `NetplanLoadError` if the dir is unreadable, has no yaml file, or no file
+//   is parsed
+fn load_netplan_yaml_tolerant(dir: &str) -> Result<(NetplanYaml, Vec<(String, String)>)> {
     let mut netplan: Option<NetplanYaml> = None;
-    for (_, _, file) in files {
-        let path = format!("{dir}/{file}");
-        let netplan_cfg = NetplanYaml::new(&path)?;
-        if let Some(n) = &mut netplan {
-            n.merge(netplan_cfg);
-        } else {
-            netplan = Some(netplan_cfg);
+    let mut skipped = Vec::new();
+    for (file, netplan_cfg) in list_configs_in(dir)? {
+        match netplan_cfg {
+            Ok(netplan_cfg) => {
+                if let Some(n) = &mut netplan {
+                    n.merge(netplan_cfg);
+                } else {
+                    netplan = Some(netplan_cfg);
+                }
+            }
+            Err(e) => skipped.push((file, e.to_string())),
         }
     }
-    if let Some(n) = netplan {
-        Ok(n)
-    } else {
-        Err(anyhow!("Netplan configuration not found!"))
+    match netplan {
+        Some(n) => Ok((n, skipped)),
+        None if skipped.is_empty() => Err(NetplanLoadError::NoYaml {
+            dir: dir.to_string(),
+        }
+        .into()),
+        None => Err(NetplanLoadError::AllInvalid {
+            dir: dir.to_string(),
+        }
+        .into()),
     }
 }
 
-fn validate_ipnetworks(ipnetwork: &str) -> Result<()> {
-    match ipnetwork.parse::<IpNet>() {
-        Ok(_) => Ok(()),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+// Gets all netplan yaml conf from /etc/netplan without merging them.
+//
+// # Return
+//
+// * Vec<(String, Result<NetplanYaml>)>: file name and its parsed conf, or the
+//   error if the file fails to be parsed.
+//
+// # Errors
+//
+// * `NetplanLoadError::Unreadable` if fail to read /etc/netplan
+pub(crate) fn list_configs() -> Result<Vec<(String, Result<NetplanYaml>)>> {
+    list_configs_in(NETPLAN_PATH)
 }
 
-fn validate_ipaddress(ipaddr: &str) -> Result<()> {
-    match ipaddr.parse::<IpAddr>() {
-        Ok(_) => Ok(()),
-        Err(e) => Err(anyhow!("{:?}", e)),
-    }
+// Same as `list_configs`, but uses the netplan yaml files in `dir`.
+pub(crate) fn list_configs_in(dir: &str) -> Result<Vec<(String, Result<NetplanYaml>)>> {
+    let files =
+        list_files(dir, Some(HIDDEN_FILES), false).map_err(|e| NetplanLoadError::Unreadable {
+            dir: dir.to_string(),
+            reason: e.to_string(),
+        })?;
+    Ok(files
+        .into_iter()
+        .map(|(_, _, file)| {
+            let netplan = NetplanYaml::new(&format!("{dir}/{file}"));
+            (file, netplan)
+        })
+        .collect())
 }
 
-// Initializes an interface.
+// Gets the name of the /etc/netplan yaml file which defines the interface.
 //
-// Be careful!. Netplan may remove address only in the yaml file.
-// The addresess cab be remained in the running interface after netplan apply.
-// To avoid this case, this function execute ifconfig system command internally.
+// Files are merged in the order of their names, and a later file overrides
+// the interface defined in an earlier one. So if several files define the
+// interface, the last one is returned because its definition takes effect.
+// Files failing to be parsed are ignored.
 //
-// Possible errors:
-// * interface name not found
-// * fail to load /etc/netplan yaml files
-// * fail to execute netplan apply
-// * fail to ifconfig command
-pub(crate) fn init(ifname: &str) -> Result<()> {
-    let mut netplan = load_netplan_yaml(NETPLAN_PATH)?;
-    let all_interfaces = interfaces();
-    for iface in all_interfaces {
-        if iface.name == *ifname {
-            netplan.init_interface(ifname);
-            netplan.apply(NETPLAN_PATH)?;
+// # Errors
+//
+// * fail to get yaml files from the /etc/netplan
+pub(crate) fn source_file(ifname: &str) -> Result<Option<String>> {
+    source_file_in(NETPLAN_PATH, ifname)
+}
 
-            // init running interface setting with ifconfig command
-            // because 'netplan apply' command would not init the running settings.
-            run_command("ifconfig", &[ifname, "0.0.0.0"])?;
-            run_command("ifconfig", &[ifname, "up"])?;
+// Same as `source_file`, but uses the netplan yaml files in `dir`.
+pub(crate) fn source_file_in(dir: &str, ifname: &str) -> Result<Option<String>> {
+    Ok(list_configs_in(dir)?
+        .into_iter()
+        .filter_map(|(file, netplan)| netplan.ok().map(|netplan| (file, netplan)))
+        .filter(|(_, netplan)| netplan.defines(ifname))
+        .map(|(file, _)| file)
+        .next_back())
+}
 
-            return Ok(());
+// Gets the whole network configuration merged from /etc/netplan yaml files.
+//
+// # Errors
+//
+// * fail to load /etc/netplan yaml files
+pub(crate) fn export() -> Result<NetworkSnapshot> {
+    let netplan = load_netplan_yaml(NETPLAN_PATH)?;
+    Ok(netplan.to_snapshot())
+}
+
+// Replaces all /etc/netplan yaml files with the snapshot, and apply it.
+//
+// # Errors
+//
+// * invalid interface address or gateway address in the snapshot
+// * invalid bridge parameters in the snapshot
+// * `NetplanBusy` if another netplan edit is in progress for a while
+// * fail to save or apply netplan yaml conf
+pub(crate) fn import(snapshot: NetworkSnapshot) -> Result<()> {
+    for (ifname, nic) in &snapshot.ethernets {
+        for ipnetwork in nic.addresses.iter().flatten() {
+            validate_ipnetworks(ipnetwork)
+                .map_err(|e| anyhow!("invalid address of {}: {}. {:?}", ifname, ipnetwork, e))?;
+        }
+        if let Some(ipaddr) = &nic.gateway4 {
+            validate_ipaddress(ipaddr)
+                .map_err(|e| anyhow!("invalid gateway4 of {}: {}. {:?}", ifname, ipaddr, e))?;
+        }
+    }
+    for (ifname, bridge) in &snapshot.bridges {
+        if let Some(params) = &bridge.parameters {
+            params
+                .validate()
+                .map_err(|e| anyhow!("invalid parameters of {}: {}", ifname, e))?;
         }
     }
 
-    Err(anyhow!("interface \"{}\" not found.", ifname))
+    let netplan = NetplanYaml::from_snapshot(snapshot);
+    let _lock = NetplanLock::acquire()?;
+    netplan.apply(NETPLAN_PATH)
 }
 
-// Sets interface ip address or gateway address or nameservers.
-// This command will OVERWRITE all existing setting in the interface if exist.
+// Saves /etc/netplan yaml files into a gzipped tarball.
 //
-// If the target interface is not running (cable connected), netplan does not
-// set the address to interface. Instead it will just saved it into conf file.
+// # Errors
+//
+// * `dest` is not an absolute path ending with ".tar.gz"
+// * fail to get yaml files from the /etc/netplan
+// * fail to create or write the tarball
+pub(crate) fn backup_configs(dest: &str) -> Result<()> {
+    validate_tarball_path(dest)?;
+    backup_configs_in(NETPLAN_PATH, dest)
+}
+
+// Same as `backup_configs`, but uses the netplan yaml files in `dir`. Only
+// the owner can read the tarball, since the yaml files may contain wifi
+// passwords.
+fn backup_configs_in(dir: &str, dest: &str) -> Result<()> {
+    let files = list_files(dir, None, false)?;
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(NETPLAN_FILE_MODE)
+        .open(dest)?;
+    file.set_permissions(fs::Permissions::from_mode(NETPLAN_FILE_MODE))?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    for (_, _, name) in files.iter().filter(|(_, _, name)| is_yaml(name)) {
+        tar.append_path_with_name(format!("{dir}/{name}"), name)?;
+    }
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+// Saves the yaml files in `dir` to `NETPLAN_BACKUP` before they are changed.
+// Nothing is saved if `dir` has no yaml file.
+fn snapshot_netplan(dir: &str) -> Result<()> {
+    let has_yaml = list_files(dir, Some(HIDDEN_FILES), false)?
+        .iter()
+        .any(|(_, _, name)| is_yaml(name));
+    if has_yaml {
+        backup_configs_in(dir, &format!("{dir}/{NETPLAN_BACKUP}"))?;
+    }
+    Ok(())
+}
+
+// Replaces /etc/netplan yaml files with the ones in the gzipped tarball made
+// by `backup_configs`, and apply them.
+//
+// The tarball is validated before /etc/netplan is changed. It should contain
+// only regular netplan yaml files, without directories. If the yaml files fail
+// to be written or applied, the previous yaml files are put back.
+//
+// # Errors
+//
+// * `src` is not an absolute path ending with ".tar.gz"
+// * fail to read the tarball
+// * the tarball contains a file other than a netplan yaml file, or no yaml
+//   file
+// * fail to read, remove or write yaml files in /etc/netplan
+// * fail to back up /etc/netplan yaml files to `NETPLAN_BACKUP`
+// * fail to run netplan apply command. The error contains its stderr.
+// * `CommandTimeout` if netplan apply hangs
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn restore_configs(src: &str) -> Result<()> {
+    validate_tarball_path(src)?;
+    let files = read_tarball(src)?;
+
+    let _lock = NetplanLock::acquire()?;
+    snapshot_netplan(NETPLAN_PATH)?;
+    replace_yaml_files(NETPLAN_PATH, &files, &SystemCommandRunner)
+}
+
+// Reads the yaml files in the tarball made by `backup_configs`.
+//
+// # Return
+//
+// * the names and contents of the yaml files
+fn read_tarball(src: &str) -> Result<Vec<(String, String)>> {
+    let mut files = Vec::new();
+    let mut tar = tar::Archive::new(GzDecoder::new(File::open(src)?));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let name = path
+            .to_str()
+            .filter(|name| !name.contains('/') && is_yaml(name))
+            .ok_or_else(|| anyhow!("invalid file in the tarball: {}", path.display()))?
+            .to_string();
+        if !entry.header().entry_type().is_file() {
+            return Err(anyhow!("invalid file in the tarball: {}", name));
+        }
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents)?;
+        if let Err(e) = serde_yaml::from_str::<NetplanYaml>(&contents) {
+            return Err(anyhow!(
+                "invalid netplan yaml in the tarball: {}: {}",
+                name,
+                e
+            ));
+        }
+        files.push((name, contents));
+    }
+    if files.is_empty() {
+        return Err(anyhow!("no yaml file in the tarball"));
+    }
+    Ok(files)
+}
+
+// Replaces the yaml files in `dir` with `files`, and applies them. If they
+// fail to be written or applied, the previous yaml files are written and
+// applied again.
+fn replace_yaml_files(
+    dir: &str,
+    files: &[(String, String)],
+    runner: &impl CommandRunner,
+) -> Result<()> {
+    let previous = read_yaml_files(dir)?;
+    let result = write_yaml_files(dir, files).and_then(|()| runner.run("netplan", &["apply"]));
+    if let Err(e) = result {
+        if write_yaml_files(dir, &previous).is_ok() {
+            let _r = runner.run("netplan", &["apply"]);
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+// Reads the yaml files in `dir`.
+//
+// # Return
+//
+// * the names and contents of the yaml files
+fn read_yaml_files(dir: &str) -> Result<Vec<(String, String)>> {
+    let mut files = Vec::new();
+    for (_, _, name) in list_files(dir, Some(HIDDEN_FILES), false)? {
+        if is_yaml(&name) {
+            let contents = fs::read_to_string(format!("{dir}/{name}"))?;
+            files.push((name, contents));
+        }
+    }
+    Ok(files)
+}
+
+// Makes `files` the only yaml files in `dir`. Each file is written to a hidden
+// file first and renamed, so that no yaml file is left half written.
+fn write_yaml_files(dir: &str, files: &[(String, String)]) -> Result<()> {
+    for (name, contents) in files {
+        let staged = format!("{dir}/.{name}.roxy-new");
+        let written = SystemFileSystem
+            .write(&staged, contents)
+            .and_then(|()| Ok(fs::rename(&staged, format!("{dir}/{name}"))?));
+        if written.is_err() {
+            let _r = fs::remove_file(&staged);
+            return written;
+        }
+    }
+    for (_, _, name) in list_files(dir, Some(HIDDEN_FILES), false)? {
+        if is_yaml(&name) && !files.iter().any(|(file, _)| *file == name) {
+            fs::remove_file(format!("{dir}/{name}"))?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_tarball_path(path: &str) -> Result<()> {
+    if path.starts_with('/') && path.ends_with(".tar.gz") && !path.contains("..") {
+        Ok(())
+    } else {
+        Err(anyhow!("invalid tarball path: {}", path))
+    }
+}
+
+fn is_yaml(name: &str) -> bool {
+    name.ends_with(".yaml")
+}
+
+// Gets the differences between the addresses in netplan yaml and the running
+// interfaces. This finds addresses added by `ip addr add` manually, which
+// netplan does not clean up.
+//
+// # Errors
+//
+// * fail to load /etc/netplan yaml files
+pub(crate) fn sync() -> Result<Vec<(String, AddressDrift)>> {
+    sync_in(NETPLAN_PATH)
+}
+
+// Same as `sync`, but uses the netplan yaml files in `dir`.
+pub(crate) fn sync_in(dir: &str) -> Result<Vec<(String, AddressDrift)>> {
+    let netplan = load_netplan_yaml(dir)?;
+    Ok(netplan.sync())
+}
+
+// Returns the metrics of IPv4 default gateways of the interface. `None` for a
+// gateway without metric, e.g. gateway4.
+fn ipv4_gateway_metrics(gateway4: Option<&str>, routes: Option<&[Route]>) -> Vec<Option<u32>> {
+    let mut metrics = Vec::new();
+    if gateway4.is_some() {
+        metrics.push(None);
+    }
+    for route in routes.into_iter().flatten() {
+        if route.is_default()
+            && route
+                .via
+                .as_ref()
+                .is_some_and(|via| via.parse::<Ipv4Addr>().is_ok())
+        {
+            metrics.push(route.metric);
+        }
+    }
+    metrics
+}
+
+fn is_link_local(addr: &IpNet) -> bool {
+    match addr {
+        IpNet::V4(_) => false,
+        IpNet::V6(v6) => (v6.addr().segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
+
+fn validate_ipnetworks(ipnetwork: &str) -> Result<()> {
+    match ipnetwork.parse::<IpNet>() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!("{:?}", e)),
+    }
+}
+
+fn validate_ipaddress(ipaddr: &str) -> Result<()> {
+    match ipaddr.parse::<IpAddr>() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!("{:?}", e)),
+    }
+}
+
+// Initializes an interface.
+//
+// Be careful!. Netplan may remove address only in the yaml file.
+// The addresess cab be remained in the running interface after netplan apply.
+// To avoid this case, this function execute ifconfig system command internally.
+//
+// Possible errors:
+// * interface name not found
+// * fail to load /etc/netplan yaml files
+// * fail to execute netplan apply
+// * fail to ifconfig command
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn init(ifname: &str) -> Result<()> {
+    let _lock = NetplanLock::acquire()?;
+    init_in(NETPLAN_PATH, ifname)
+}
+
+// Same as `init`, but uses the netplan yaml files in `dir`.
+pub(crate) fn init_in(dir: &str, ifname: &str) -> Result<()> {
+    let mut netplan = load_netplan_yaml(dir)?;
+    let all_interfaces = interfaces();
+    for iface in all_interfaces {
+        if iface.name == *ifname {
+            netplan.init_interface(ifname);
+            netplan.apply(dir)?;
+
+            // init running interface setting with ifconfig command
+            // because 'netplan apply' command would not init the running settings.
+            run_command("ifconfig", &[ifname, "0.0.0.0"])?;
+            run_command("ifconfig", &[ifname, "up"])?;
+
+            return Ok(());
+        }
+    }
+
+    Err(anyhow!("interface \"{}\" not found.", ifname))
+}
+
+// Checks the interface setting with the same rules as `set`, without saving
+// it. Whether the interface exists is not checked.
+//
+// # Errors
+//
+// * invalid interface address, gateway address, or nameserver address
+// * dhcp4 and static ip address or nameserver address is set in same interface
+// * other interface already have the gateway, unless both gateways have
+//   different metrics
+// * fail to load /etc/netplan yaml files
+pub(crate) fn validate(ifname: &str, nic_output: &NicOutput) -> Result<()> {
+    validate_in(NETPLAN_PATH, ifname, nic_output)
+}
+
+// Same as `validate`, but uses the netplan yaml files in `dir`.
+pub(crate) fn validate_in(dir: &str, ifname: &str, nic_output: &NicOutput) -> Result<()> {
+    prepare_set(dir, ifname, nic_output).map(|_| ())
+}
+
+// Checks the interface setting like `validate`, and also with netplan: the
+// merged yaml with the setting is written to a temporary root directory, and
+// `netplan generate --root-dir` runs against it. Neither /etc/netplan nor the
+// running interfaces are changed.
+//
+// # Errors
+//
+// * any error of `validate`
+// * netplan generate rejects the merged yaml. The error contains its stderr.
+// * fail to write the temporary root directory
+// * `CommandTimeout` if netplan generate hangs
+pub(crate) fn validate_generate(ifname: &str, nic_output: &NicOutput) -> Result<()> {
+    validate_generate_in(NETPLAN_PATH, ifname, nic_output)
+}
+
+// Same as `validate_generate`, but uses the netplan yaml files in `dir`.
+pub(crate) fn validate_generate_in(dir: &str, ifname: &str, nic_output: &NicOutput) -> Result<()> {
+    let (netplan, _) = prepare_set(dir, ifname, nic_output)?;
+
+    let root = TempRoot::create()?;
+    let netplan_dir = root.path.join("etc/netplan");
+    fs::create_dir_all(&netplan_dir)?;
+    fs::write(netplan_dir.join(DEFAULT_NETPLAN_YAML), netplan.to_string())?;
+    let root_dir = root
+        .path
+        .to_str()
+        .ok_or_else(|| anyhow!("invalid temporary directory"))?;
+    run_command_output_timeout(
+        "netplan",
+        &["generate", "--root-dir", root_dir],
+        NETPLAN_APPLY_TIMEOUT,
+    )?;
+    Ok(())
+}
+
+// Compares the network configuration with the snapshot, without changing
+// it. This shows what `import` of the snapshot will change.
+//
+// # Errors
+//
+// * fail to load /etc/netplan yaml files
+pub(crate) fn diff(snapshot: NetworkSnapshot) -> Result<NetplanDiff> {
+    let netplan = load_netplan_yaml(NETPLAN_PATH)?;
+    Ok(netplan.diff(&NetplanYaml::from_snapshot(snapshot)))
+}
+
+// Compares the netplan yaml files with the running interfaces, to show the
+// changes written but not applied yet, e.g. to an unplugged link. In the
+// result, the old configuration is the running one and the new is the yaml.
+// Interfaces are compared by their existence and addresses only.
+//
+// # Errors
+//
+// * fail to load /etc/netplan yaml files
+pub(crate) fn pending_changes() -> Result<NetplanDiff> {
+    let netplan = load_netplan_yaml(NETPLAN_PATH)?;
+    let running = interfaces()
+        .into_iter()
+        .map(|iface| {
+            let ips = iface
+                .ips
+                .iter()
+                .filter_map(|ip| ip.to_string().parse::<IpNet>().ok())
+                .collect();
+            (iface.name, ips)
+        })
+        .collect();
+    Ok(netplan.live(&running).diff(&netplan))
+}
+
+// Gets the addresses of a running interface as they would be in netplan
+// yaml. Link-local addresses are ignored, and so are IPv4 addresses from
+// DHCP. The configured addresses are returned as they are if the running
+// interface has the same ones, so that they are not reported as changed.
+fn live_addresses(
+    configured: Option<&[String]>,
+    live: &[IpNet],
+    dhcp4: bool,
+) -> Option<Vec<String>> {
+    let live = live
+        .iter()
+        .filter(|addr| !(is_link_local(addr) || (dhcp4 && matches!(addr, IpNet::V4(_)))))
+        .collect::<Vec<_>>();
+    let configured_set = configured
+        .iter()
+        .copied()
+        .flatten()
+        .filter_map(|addr| addr.parse::<IpNet>().ok())
+        .collect::<BTreeSet<_>>();
+    if configured_set == live.iter().copied().copied().collect() {
+        return configured.map(<[String]>::to_vec);
+    }
+    (!live.is_empty()).then(|| live.iter().map(ToString::to_string).collect())
+}
+
+fn fields<T: serde::Serialize>(value: &T) -> Fields {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::Object(map)) => map
+            .into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .collect(),
+        _ => Fields::new(),
+    }
+}
+
+fn diff_fields(old: &Fields, new: &Fields) -> Vec<FieldChange> {
+    old.keys()
+        .chain(new.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter_map(|field| {
+            let (old, new) = (old.get(field), new.get(field));
+            (old != new).then(|| FieldChange {
+                field: field.clone(),
+                old: old.map(ToString::to_string),
+                new: new.map(ToString::to_string),
+            })
+        })
+        .collect()
+}
+
+// A temporary directory only roxy can access, removed when this is dropped.
+struct TempRoot {
+    path: PathBuf,
+}
+
+impl TempRoot {
+    // Creates a new directory in the temporary directory of the system. This
+    // fails rather than reuses a directory which already exists.
+    fn create() -> Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        let path = std::env::temp_dir().join(format!("roxy-netplan-{}-{nanos}", process::id()));
+        DirBuilder::new().mode(0o700).create(&path)?;
+        Ok(TempRoot { path })
+    }
+}
+
+impl Drop for TempRoot {
+    fn drop(&mut self) {
+        let _r = fs::remove_dir_all(&self.path);
+    }
+}
+
+// Checks the addresses of the setting, and that dhcp4 is not set with static
+// addresses.
+fn validate_nic_output(nic_output: &NicOutput) -> Result<()> {
+    for ipnetwork in nic_output.addresses.iter().flatten() {
+        validate_ipnetworks(ipnetwork)
+            .map_err(|e| anyhow!("invalid interface address: {}. {:?}", ipnetwork, e))?;
+    }
+    if let Some(ipaddr) = &nic_output.gateway4 {
+        validate_ipaddress(ipaddr)
+            .map_err(|e| anyhow!("invalid gateway4 address: {}. {:?}", ipaddr, e))?;
+        if ipaddr.parse::<Ipv4Addr>().is_err() {
+            return Err(anyhow!("gateway4 should be an IPv4 address: {}", ipaddr));
+        }
+    }
+    for ipaddr in nic_output.nameservers.iter().flatten() {
+        validate_ipaddress(ipaddr)
+            .map_err(|e| anyhow!("invalid nameserver address: {}. {:?}", ipaddr, e))?;
+    }
+    if nic_output.dhcp4 == Some(true)
+        && (nic_output.addresses.is_some() || nic_output.nameservers.is_some())
+    {
+        return Err(anyhow!(
+            "dhcp4 and static address cannot be set in the same interface"
+        ));
+    }
+    Ok(())
+}
+
+// Checks that the gateway of the setting does not conflict with the gateways
+// of other ethernets, bridges, vlans, and wifis. Multiple gateways should have
+// distinct metrics.
+fn validate_gateway(netplan: &NetplanYaml, ifname: &str, nic_output: &NicOutput) -> Result<()> {
+    if nic_output.gateway4.is_none() {
+        return Ok(());
+    }
+    for (nic_name, metrics) in netplan.ipv4_gateways() {
+        if nic_name == ifname {
+            continue;
+        }
+        for metric in metrics {
+            match (metric, nic_output.gateway_metric) {
+                (Some(other), Some(new)) if other != new => {}
+                (Some(other), Some(_)) => {
+                    return Err(anyhow!(
+                        "gateway metric {} is already used by {}",
+                        other,
+                        nic_name
+                    ));
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "only one interface can have gateway without metric. {} has gateway",
+                        nic_name
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Sets interface ip address or gateway address or nameservers.
+// This command will OVERWRITE all existing setting in the interface if exist.
+//
+// If the target interface is not running (cable connected), netplan does not
+// set the address to interface. Instead it will just saved it into conf file.
 //
 // To replace(overwrite) ip address, gateway, nameservers of eno3 interface:
 // let nic_output = NicOutput::new(
 //     Some(vec!["192.168.0.205/24".to_string(), "192.168.4.7/24".to_string()]),
 //     None,
 //     Some("192.168.0.1".to_string()),
-//     Some(vec!["164.124.101.1".to_string(), "164.124.101.2".to_string()])
+//     Some(vec!["164.124.101.1".to_string(), "164.124.101.2".to_string()]),
+//     None,
+//     None,
 // );
-// ifconfig::set("eno3", &nic_output)?;
+// ifconfig::set("eno3", &nic_output, false, true)?;
+//
+// The interface name should exist in the system. Set `force` to write the
+// configuration for an interface that is not detected yet (e.g. it appears
+// only after cabling).
+//
+// Multiple interfaces can have gateways if every gateway has a distinct
+// `gateway_metric`. Then the gateway is saved as a default route with the
+// metric instead of gateway4.
+//
+// Duplicated nameservers are removed, keeping the first-seen order. If more
+// than `MAX_NAMESERVERS` nameservers remain, the setting is still applied but
+// a warning is returned because the resolver ignores the rest.
+//
+// If `check_gateway` is set, the gateway is pinged after the setting is
+// applied, and a warning is returned if it does not respond. The setting is
+// kept either way, because the link may not be up yet.
+//
+// # Return
+//
+// * Vec<String>: warnings. Empty if nothing to warn.
 //
 // Possible errors:
+// * interface name not found, unless `force` is set
 // * fail to get or save, apply netplan yaml conf
 // * dhcp4 and static ip address or nameserver address is set in same interface
-// * try to set new gateway address when other interface already have the gateway
-pub(crate) fn set(ifname: &str, nic_output: &NicOutput) -> Result<()> {
-    let mut netplan = load_netplan_yaml(NETPLAN_PATH)?;
+// * try to set new gateway address when other interface already have the
+//   gateway, unless both gateways have different metrics
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn set(
+    ifname: &str,
+    nic_output: &NicOutput,
+    force: bool,
+    check_gateway: bool,
+) -> Result<Vec<String>> {
+    let mut warnings = {
+        let _lock = NetplanLock::acquire()?;
+        set_in(NETPLAN_PATH, ifname, nic_output, force)?
+    };
+    // Does not hold the lock while waiting for the replies.
+    if let (true, Some(gateway)) = (check_gateway, &nic_output.gateway4) {
+        warnings.extend(gateway_warning(gateway));
+    }
+    Ok(warnings)
+}
+
+// Pings the gateway, and returns a warning if it does not respond.
+fn gateway_warning(gateway: &str) -> Option<String> {
+    match roxy::ping(gateway, GATEWAY_PING_COUNT) {
+        Ok(ret) if ret.is_reachable() => None,
+        Ok(_) => Some(format!("gateway {gateway} does not respond to ping")),
+        Err(e) => Some(format!("fail to ping gateway {gateway}: {e}")),
+    }
+}
+
+// Same as `set`, but uses the netplan yaml files in `dir`, and does not check
+// the gateway.
+pub(crate) fn set_in(
+    dir: &str,
+    ifname: &str,
+    nic_output: &NicOutput,
+    force: bool,
+) -> Result<Vec<String>> {
+    if !force {
+        check_interface_exists(ifname, &get_interface_names(&None))?;
+    }
+
+    let (netplan, warnings) = prepare_set(dir, ifname, nic_output)?;
+    netplan.apply(dir)?;
+    Ok(warnings)
+}
+
+// Loads the netplan yaml files in `dir`, or the minimal yaml if there is
+// none, checks the interface setting, and merges it into the yaml. Nothing is
+// saved. `set` and `validate` share this, so that both apply the same rules.
+//
+// # Return
+//
+// * the merged netplan yaml, and the warnings of the setting
+fn prepare_set(
+    dir: &str,
+    ifname: &str,
+    nic_output: &NicOutput,
+) -> Result<(NetplanYaml, Vec<String>)> {
+    validate_nic_output(nic_output)?;
+    let mut netplan = load_netplan_yaml_or_bootstrap(dir)?;
+    validate_gateway(&netplan, ifname, nic_output)?;
+
+    let mut new_if = nic_output.to();
+    let warnings = nameserver_warnings(&new_if);
+    keep_extra_settings(&netplan, ifname, &mut new_if);
+    netplan.set_interface(ifname, new_if);
+    Ok((netplan, warnings))
+}
+
+// Sets the interface having the MAC address, and names it `ifname` with
+// `set-name`. The setting follows the MAC address instead of the kernel name,
+// so it survives renaming of the interface, e.g. by moving the NIC to
+// another slot. The MAC address does not need to exist in the system yet.
+//
+// # Return
+//
+// * Vec<String>: warnings. Empty if nothing to warn.
+//
+// Possible errors:
+// * invalid MAC address or interface name
+// * another running interface already has the name
+// * another netplan entry already matches the MAC address or sets the name
+// * any error of `set`, except the existence check of the interface
+pub(crate) fn set_by_mac(mac: &str, ifname: &str, nic_output: &NicOutput) -> Result<Vec<String>> {
+    let mac = parse_mac(mac)?;
+    if let Some(running) = interfaces().into_iter().find(|x| x.name == ifname) {
+        if running.mac.is_some_and(|running_mac| running_mac != mac) {
+            return Err(anyhow!(
+                "interface \"{}\" already exists with another MAC address",
+                ifname
+            ));
+        }
+    }
+    let _lock = NetplanLock::acquire()?;
+    set_by_mac_in(NETPLAN_PATH, &mac.to_string(), ifname, nic_output)
+}
+
+// Same as `set_by_mac`, but uses the netplan yaml files in `dir`, and does
+// not check the running interfaces.
+pub(crate) fn set_by_mac_in(
+    dir: &str,
+    mac: &str,
+    ifname: &str,
+    nic_output: &NicOutput,
+) -> Result<Vec<String>> {
+    let mac = parse_mac(mac)?.to_string();
+    check_ifname(ifname)?;
+    validate_nic_output(nic_output)?;
+    let mut netplan = load_netplan_yaml_or_bootstrap(dir)?;
+    validate_gateway(&netplan, ifname, nic_output)?;
+
+    let mut new_if = nic_output.to();
+    let warnings = nameserver_warnings(&new_if);
+    keep_extra_settings(&netplan, ifname, &mut new_if);
+    netplan.set_interface_by_mac(&mac, ifname, new_if)?;
+    netplan.apply(dir)?;
+    Ok(warnings)
+}
+
+fn check_ifname(ifname: &str) -> Result<()> {
+    if ifname.is_empty()
+        || ifname.len() > MAX_IFNAME_LEN
+        || ifname.contains(|c: char| c == '/' || c == ':' || c.is_whitespace())
+    {
+        return Err(anyhow!("invalid interface name: {}", ifname));
+    }
+    Ok(())
+}
+
+// Renames the running interface `old` to `new`, e.g. "enp3s0" to "lan0", to
+// standardize names across hardware. Netplan matches the MAC address of
+// `old` and sets the name with `set-name`, so the name follows the NIC. The
+// settings and the description of `old` are kept under `new`.
+//
+// Renaming the interface with the default route cuts off the management
+// connection, so it is refused unless `force` is set.
+//
+// Possible errors:
+// * invalid interface name, or `old` is not running or has no MAC address
+// * `new` is already running or defined in netplan
+// * `old` has the default route, unless `force` is set
+// * fail to read or write netplan yaml files or `METADATA_PATH`
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn rename(old: &str, new: &str, force: bool) -> Result<()> {
+    check_ifname(new)?;
+    let running = interfaces();
+    if running.iter().any(|x| x.name == new) {
+        return Err(anyhow!("interface \"{}\" already exists", new));
+    }
+    let mac = running
+        .into_iter()
+        .find(|x| x.name == old)
+        .ok_or_else(|| anyhow!("interface \"{}\" not found", old))?
+        .mac
+        .ok_or_else(|| anyhow!("interface \"{}\" has no MAC address", old))?;
+    if !force {
+        if let Some((dev, gateway)) = roxy::default_route()? {
+            if dev == old {
+                return Err(anyhow!(
+                    "interface \"{}\" carries the default route via {}",
+                    old,
+                    gateway
+                ));
+            }
+        }
+    }
+
+    let _lock = NetplanLock::acquire()?;
+    rename_in(NETPLAN_PATH, old, new, &mac.to_string())?;
+    let mut descriptions = descriptions()?;
+    if let Some(d) = descriptions.remove(old) {
+        descriptions.insert(new.to_string(), d);
+        save_descriptions(&descriptions)?;
+    }
+    Ok(())
+}
+
+// Same as `rename`, but uses the netplan yaml files in `dir`, and does not
+// check the running interfaces.
+pub(crate) fn rename_in(dir: &str, old: &str, new: &str, mac: &str) -> Result<()> {
+    let mut netplan = load_netplan_yaml(dir)?;
+    netplan.rename_interface(old, new, mac)?;
+    netplan.apply(dir)
+}
+
+fn parse_mac(mac: &str) -> Result<MacAddr> {
+    mac.parse::<MacAddr>()
+        .map_err(|e| anyhow!("invalid MAC address: {}. {:?}", mac, e))
+}
+
+// Sets multiple interfaces at once, with the same rules as `set`. The
+// settings are merged into the netplan yaml and applied only once. If any
+// setting is invalid, nothing is saved.
+//
+// The gateways are checked after all settings are merged, so that a gateway
+// can be moved from one interface to another in one call.
+//
+// # Return
+//
+// * Vec<String>: warnings prefixed with the interface name. Empty if nothing
+//   to warn.
+//
+// Possible errors:
+// * the same interface is set more than once
+// * any error of `set`
+pub(crate) fn set_many(interfaces: &[(String, NicOutput)], force: bool) -> Result<Vec<String>> {
+    let _lock = NetplanLock::acquire()?;
+    set_many_in(NETPLAN_PATH, interfaces, force)
+}
+
+// Same as `set_many`, but uses the netplan yaml files in `dir`.
+pub(crate) fn set_many_in(
+    dir: &str,
+    interfaces: &[(String, NicOutput)],
+    force: bool,
+) -> Result<Vec<String>> {
+    let names = if force {
+        Vec::new()
+    } else {
+        get_interface_names(&None)
+    };
+    for (i, (ifname, nic_output)) in interfaces.iter().enumerate() {
+        if interfaces[..i].iter().any(|(name, _)| name == ifname) {
+            return Err(anyhow!("interface \"{}\" is set more than once", ifname));
+        }
+        if !force {
+            check_interface_exists(ifname, &names)?;
+        }
+        validate_nic_output(nic_output).map_err(|e| anyhow!("{}: {}", ifname, e))?;
+    }
+
+    let mut netplan = load_netplan_yaml_or_bootstrap(dir)?;
+    let mut warnings = Vec::new();
+    for (ifname, nic_output) in interfaces {
+        let mut new_if = nic_output.to();
+        keep_extra_settings(&netplan, ifname, &mut new_if);
+        warnings.extend(
+            nameserver_warnings(&new_if)
+                .into_iter()
+                .map(|warning| format!("{ifname}: {warning}")),
+        );
+        netplan.set_interface(ifname, new_if);
+    }
+    for (ifname, nic_output) in interfaces {
+        validate_gateway(&netplan, ifname, nic_output).map_err(|e| anyhow!("{}: {}", ifname, e))?;
+    }
+
+    netplan.apply(dir)?;
+    Ok(warnings)
+}
+
+// Keeps the settings of the interface that `NicOutput` does not have:
+// accept-ra, match and set-name, and dhcp4-overrides and dhcp-identifier if
+// the new setting still uses DHCP.
+fn keep_extra_settings(netplan: &NetplanYaml, ifname: &str, new_if: &mut Nic) {
+    let Some((_, nic)) = netplan.network.ethernets.iter().find(|x| x.0 == *ifname) else {
+        return;
+    };
+    new_if.accept_ra = nic.accept_ra;
+    new_if.matching.clone_from(&nic.matching);
+    new_if.set_name.clone_from(&nic.set_name);
+    if new_if.dhcp4 == Some(true) {
+        new_if.dhcp4_overrides.clone_from(&nic.dhcp4_overrides);
+        new_if.dhcp_identifier.clone_from(&nic.dhcp_identifier);
+    }
+
+    // `NicOutput` has no search domains, so the ones added by `add_search`
+    // are kept.
+    if let Some(search) = nic
+        .nameservers
+        .as_ref()
+        .and_then(|nm| nm.get("search"))
+        .filter(|search| !search.is_empty())
+    {
+        let nameservers = new_if.nameservers.get_or_insert_with(HashMap::new);
+        if nameservers.get("search").is_none_or(Vec::is_empty) {
+            nameservers.insert("search".to_string(), search.clone());
+        }
+    }
+
+    // `NicOutput` only sets the default route, so the static routes to other
+    // networks are kept.
+    let static_routes: Vec<_> = nic
+        .routes
+        .iter()
+        .flatten()
+        .filter(|route| !route.is_default())
+        .cloned()
+        .collect();
+    if !static_routes.is_empty() {
+        new_if
+            .routes
+            .get_or_insert_with(Vec::new)
+            .extend(static_routes);
+    }
+}
+
+fn check_interface_exists(ifname: &str, names: &[String]) -> Result<()> {
+    if names.iter().any(|name| name == ifname) {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "interface \"{}\" not found. available interfaces: {}",
+            ifname,
+            names.join(", ")
+        ))
+    }
+}
+
+// Returns a warning if more nameservers are set than the resolver uses.
+fn nameserver_warnings(nic: &Nic) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if let Some(addrs) = nic.nameservers.as_ref().and_then(|nm| nm.get("addresses")) {
+        if let Some(ignored) = addrs.get(MAX_NAMESERVERS..) {
+            if !ignored.is_empty() {
+                warnings.push(format!(
+                    "only the first {} nameservers are used by the resolver. ignored: {}",
+                    MAX_NAMESERVERS,
+                    ignored.join(", ")
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+// Gets interface configurations
+//
+// To get all interfaces:
+// let all_interfaces = ifconfig::get(&None)?;
+//
+// To get "eno1" interface:
+// let eno1_interface = ifconfig::get(&Some("eno1".to_string()))?;
+//
+// The files failing to be parsed are skipped, and returned with their errors.
+//
+// # Errors
+//
+// * fail to get yaml files from the /etc/netplan
+// * no yaml file is parsed
+pub(crate) fn get(ifname: &Option<String>) -> Result<InterfacesOutput> {
+    let (mut nics, skipped) = get_in(NETPLAN_PATH, ifname)?;
+    if let Some(nics) = &mut nics {
+        // The descriptions are optional. Do not fail for a broken metadata.
+        let descriptions = descriptions().unwrap_or_default();
+        for (name, nic_output) in nics {
+            nic_output.description = descriptions.get(name).cloned();
+        }
+    }
+    Ok((nics, skipped))
+}
+
+// Same as `get`, but uses the netplan yaml files in `dir`.
+pub(crate) fn get_in(dir: &str, ifname: &Option<String>) -> Result<InterfacesOutput> {
+    let (netplan, skipped) = load_netplan_yaml_tolerant(dir)?;
+    let nics = if let Some(name) = ifname {
+        netplan
+            .network
+            .ethernets
+            .iter()
+            .find(|(x, _)| *x == *name)
+            .map(|(_, nic)| vec![(name.to_string(), NicOutput::from(nic))])
+    } else {
+        Some(
+            netplan
+                .network
+                .ethernets
+                .iter()
+                .map(|(name, nic)| (name.to_string(), NicOutput::from(nic)))
+                .collect(),
+        )
+    };
+    Ok((nics, skipped))
+}
+
+// Removes interface or name server or gateway address from the specified interface.
+//
+// To delete interface address "192.168.3.7/24", nameserver "164.124.101.2":
+// let nic_output = NicOutput::new(
+//     Some(vec!["192.168.3.7/24".to_string()]),
+//     None,
+//     None,
+//     Some(vec!["164.124.101.2".to_string()]),
+//     None,
+//     None,
+// );
+//
+// ifconfig::delete("eno3", &nic_output)?;
+//
+// Possible errors:
+// * fail to load /etc/netplan yaml files
+// * fail to apply the change to system
+// * interface not found
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn delete(ifname: &str, nic_output: &NicOutput) -> Result<()> {
+    let _lock = NetplanLock::acquire()?;
+    delete_in(NETPLAN_PATH, ifname, nic_output)
+}
+
+// Same as `delete`, but uses the netplan yaml files in `dir`.
+pub(crate) fn delete_in(dir: &str, ifname: &str, nic_output: &NicOutput) -> Result<()> {
+    let mut netplan = load_netplan_yaml(dir)?;
+    netplan.delete(ifname, nic_output)?;
+    netplan.apply(dir)?;
+
+    if let Some(addrs) = &nic_output.addresses {
+        for addr in addrs {
+            // apply to running interface
+            // if the device does not have this ip address, then this command will return ERROR!!!!
+            run_command("ip", &["addr", "del", addr, "dev", ifname])?;
+        }
+    }
+    Ok(())
+}
+
+// Removes the whole ethernet entry of the interface from the netplan yaml
+// files, and applies it. The addresses of the running interface are flushed
+// because 'netplan apply' does not remove them.
+//
+// The interface carrying the active default route is not removed unless
+// `force` is set, so that the system does not lose its connectivity.
+//
+// Possible errors:
+// * interface not found in yaml
+// * the interface carries the active default route, and `force` is not set
+// * fail to load /etc/netplan yaml files
+// * fail to execute netplan apply or ip command
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn remove_interface(ifname: &str, force: bool) -> Result<()> {
+    let _lock = NetplanLock::acquire()?;
+    remove_interface_in(NETPLAN_PATH, ifname, force)
+}
+
+// Same as `remove_interface`, but uses the netplan yaml files in `dir`.
+pub(crate) fn remove_interface_in(dir: &str, ifname: &str, force: bool) -> Result<()> {
+    if !force {
+        if let Some((dev, gateway)) = roxy::default_route()? {
+            if dev == ifname {
+                return Err(anyhow!(
+                    "interface \"{}\" carries the default route via {}",
+                    ifname,
+                    gateway
+                ));
+            }
+        }
+    }
+
+    let mut netplan = load_netplan_yaml(dir)?;
+    netplan.remove_interface(ifname)?;
+    netplan.apply(dir)?;
+
+    if interfaces().iter().any(|iface| iface.name == *ifname) {
+        run_command("ip", &["addr", "flush", "dev", ifname])?;
+    }
+    Ok(())
+}
+
+// Sets dhcp4-overrides, e.g. `use-routes: false` to ignore the routes pushed
+// by the DHCP server, and dhcp-identifier of a DHCP interface. `None` removes
+// them.
+//
+// `set` keeps them while the interface uses DHCP.
+//
+// # Errors
+//
+// * interface not found in yaml
+// * dhcp4 is not set in the interface
+// * dhcp-identifier is neither "mac" nor "duid"
+// * fail to load /etc/netplan yaml files
+// * fail to apply the change to system
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn set_dhcp_overrides(
+    ifname: &str,
+    overrides: Option<Dhcp4Overrides>,
+    identifier: Option<String>,
+) -> Result<()> {
+    let _lock = NetplanLock::acquire()?;
+    set_dhcp_overrides_in(NETPLAN_PATH, ifname, overrides, identifier)
+}
+
+// Same as `set_dhcp_overrides`, but uses the netplan yaml files in `dir`.
+pub(crate) fn set_dhcp_overrides_in(
+    dir: &str,
+    ifname: &str,
+    overrides: Option<Dhcp4Overrides>,
+    identifier: Option<String>,
+) -> Result<()> {
+    if let Some(identifier) = &identifier {
+        if identifier != "mac" && identifier != "duid" {
+            return Err(anyhow!(
+                "dhcp-identifier should be \"mac\" or \"duid\": {}",
+                identifier
+            ));
+        }
+    }
+    let mut netplan = load_netplan_yaml(dir)?;
+    netplan.set_dhcp_overrides(ifname, overrides, identifier)?;
+    netplan.apply(dir)
+}
+
+// Adds nameservers to the interface, keeping the nameservers already set,
+// e.g. by another controller. The nameservers already in it are skipped.
+//
+// # Return
+//
+// * Vec<String>: warnings. Empty if nothing to warn.
+//
+// Possible errors:
+// * invalid nameserver address
+// * the interface is not found in netplan, or uses dhcp4
+// * fail to read or write netplan yaml files
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn add_nameservers(ifname: &str, nameservers: &[String]) -> Result<Vec<String>> {
+    let _lock = NetplanLock::acquire()?;
+    add_nameservers_in(NETPLAN_PATH, ifname, nameservers)
+}
+
+// Same as `add_nameservers`, but uses the netplan yaml files in `dir`.
+pub(crate) fn add_nameservers_in(
+    dir: &str,
+    ifname: &str,
+    nameservers: &[String],
+) -> Result<Vec<String>> {
+    for ipaddr in nameservers {
+        validate_ipaddress(ipaddr)
+            .map_err(|e| anyhow!("invalid nameserver address: {}. {:?}", ipaddr, e))?;
+    }
+    let mut netplan = load_netplan_yaml(dir)?;
+    let nic = netplan.add_nameservers(ifname, "addresses", nameservers)?;
+    let warnings = nameserver_warnings(nic);
+    netplan.apply(dir)?;
+    Ok(warnings)
+}
+
+// Adds search domains to the interface, keeping the domains already set. The
+// domains already in it are skipped.
+//
+// Possible errors:
+// * invalid domain name
+// * the interface is not found in netplan
+// * fail to read or write netplan yaml files
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn add_search(ifname: &str, domains: &[String]) -> Result<()> {
+    let _lock = NetplanLock::acquire()?;
+    add_search_in(NETPLAN_PATH, ifname, domains)
+}
+
+// Same as `add_search`, but uses the netplan yaml files in `dir`.
+pub(crate) fn add_search_in(dir: &str, ifname: &str, domains: &[String]) -> Result<()> {
+    if let Some(domain) = domains.iter().find(|d| !is_valid_domain(d)) {
+        return Err(anyhow!("invalid search domain: {}", domain));
+    }
+    let mut netplan = load_netplan_yaml(dir)?;
+    netplan.add_nameservers(ifname, "search", domains)?;
+    netplan.apply(dir)
+}
+
+// Sets whether the interface accepts IPv6 router advertisements.
+//
+// Possible errors:
+// * the interface is not found in netplan
+// * fail to read or write netplan yaml files
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn set_accept_ra(ifname: &str, accept: bool) -> Result<()> {
+    let _lock = NetplanLock::acquire()?;
+    set_accept_ra_in(NETPLAN_PATH, ifname, accept)
+}
+
+// Same as `set_accept_ra`, but uses the netplan yaml files in `dir`.
+pub(crate) fn set_accept_ra_in(dir: &str, ifname: &str, accept: bool) -> Result<()> {
+    let mut netplan = load_netplan_yaml(dir)?;
+    netplan.set_accept_ra(ifname, accept)?;
+    netplan.apply(dir)
+}
+
+// Marks every ethernet except the given ones, e.g. the management interface,
+// as optional, so that booting does not wait for them to come up.
+//
+// Possible errors:
+// * an exception is not found in netplan
+// * fail to read or write netplan yaml files
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn set_all_optional(except: &[String]) -> Result<Vec<String>> {
+    let _lock = NetplanLock::acquire()?;
+    set_all_optional_in(NETPLAN_PATH, except)
+}
+
+// Same as `set_all_optional`, but uses the netplan yaml files in `dir`.
+pub(crate) fn set_all_optional_in(dir: &str, except: &[String]) -> Result<Vec<String>> {
+    let mut netplan = load_netplan_yaml(dir)?;
+    let marked = netplan.set_all_optional(except)?;
+    netplan.apply(dir)?;
+    Ok(marked)
+}
+
+// The renderer netplan uses when no yaml file sets it.
+const DEFAULT_RENDERER: &str = "networkd";
+const RENDERERS: &[&str] = &["networkd", "NetworkManager"];
+
+// Writes the minimal netplan yaml with version 2 and the renderer, if
+// /etc/netplan has no yaml file. `set` starts from this on a freshly imaged
+// system, or from `DEFAULT_RENDERER` without it. Nothing is applied.
+//
+// # Return
+//
+// * true if the yaml file is written, false if a yaml file already exists
+//
+// # Errors
+//
+// * the renderer is neither "networkd" nor "NetworkManager"
+// * `NetplanLoadError` if /etc/netplan is unreadable or has only invalid yaml
+//   files
+// * fail to write the yaml file
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn bootstrap(renderer: &str) -> Result<bool> {
+    let _lock = NetplanLock::acquire()?;
+    bootstrap_in(NETPLAN_PATH, renderer)
+}
+
+// Same as `bootstrap`, but uses the netplan yaml files in `dir`.
+pub(crate) fn bootstrap_in(dir: &str, renderer: &str) -> Result<bool> {
+    if !RENDERERS.contains(&renderer) {
+        return Err(anyhow!("invalid renderer: {}", renderer));
+    }
+    match load_netplan_yaml_tolerant(dir) {
+        Ok(_) => return Ok(false),
+        Err(e)
+            if matches!(
+                e.downcast_ref::<NetplanLoadError>(),
+                Some(NetplanLoadError::NoYaml { .. })
+            ) => {}
+        Err(e) => return Err(e),
+    }
+    SystemFileSystem.write(
+        &format!("{dir}/{DEFAULT_NETPLAN_YAML}"),
+        &NetplanYaml::bootstrap(renderer).to_string(),
+    )?;
+    Ok(true)
+}
+
+// Gets the netplan yaml merged from /etc/netplan yaml files, e.g. to attach
+// to a bug report. Wifi passwords are replaced with `PASSWORD_MASK`.
+//
+// # Errors
+//
+// * `NetplanLoadError` if no netplan conf is loaded, or a yaml file is invalid
+pub(crate) fn merged_yaml() -> Result<String> {
+    merged_yaml_in(NETPLAN_PATH)
+}
+
+// Same as `merged_yaml`, but uses the netplan yaml files in `dir`.
+pub(crate) fn merged_yaml_in(dir: &str) -> Result<String> {
+    let mut netplan = load_netplan_yaml(dir)?;
+    for wifi in netplan
+        .network
+        .wifis
+        .iter_mut()
+        .flat_map(HashMap::values_mut)
+    {
+        wifi.mask_passwords();
+    }
+    Ok(netplan.to_string())
+}
+
+// Gets the effective renderer of netplan, i.e. `renderer` of the merged yaml
+// files, or "networkd" if no file sets it. Files failing to be parsed are
+// ignored.
+//
+// # Return
+//
+// * String: "networkd" or "NetworkManager"
+//
+// # Errors
+//
+// * `NetplanLoadError` if no netplan conf is loaded
+pub(crate) fn renderer() -> Result<String> {
+    renderer_in(NETPLAN_PATH)
+}
+
+// Same as `renderer`, but uses the netplan yaml files in `dir`.
+pub(crate) fn renderer_in(dir: &str) -> Result<String> {
+    let (netplan, _) = load_netplan_yaml_tolerant(dir)?;
+    Ok(netplan
+        .network
+        .renderer
+        .unwrap_or_else(|| DEFAULT_RENDERER.to_string()))
+}
+
+// Gets the ring buffer sizes of the interface, and their hardware maximums,
+// with `ethtool -g`.
+//
+// # Errors
+//
+// * the interface does not exist
+// * fail to execute ethtool, e.g. the driver does not report ring sizes
+pub(crate) fn get_ring(ifname: &str) -> Result<RingSize> {
+    check_running_interface(ifname)?;
+    let output = run_command_output("ethtool", &["-g", ifname])?;
+    parse_ring(&output).ok_or_else(|| anyhow!("unexpected ethtool output for {}", ifname))
+}
 
-    if let Some(addrs) = &nic_output.addresses {
-        for ipnetwork in addrs {
-            if let Err(e) = validate_ipnetworks(ipnetwork) {
-                return Err(anyhow!("invalid interface address: {}. {:?}", ipnetwork, e));
-            }
+// Sets the ring buffer sizes of the interface with `ethtool -G`. The sizes
+// are not persistent across reboots.
+//
+// # Errors
+//
+// * the interface does not exist
+// * `rx` or `tx` is zero, or larger than the hardware maximum
+// * fail to execute ethtool
+pub(crate) fn set_ring(ifname: &str, rx: u32, tx: u32) -> Result<()> {
+    let ring = get_ring(ifname)?;
+    if rx == 0 || rx > ring.rx_max {
+        return Err(anyhow!(
+            "rx ring size of {} should be 1 to {}: {}",
+            ifname,
+            ring.rx_max,
+            rx
+        ));
+    }
+    if tx == 0 || tx > ring.tx_max {
+        return Err(anyhow!(
+            "tx ring size of {} should be 1 to {}: {}",
+            ifname,
+            ring.tx_max,
+            tx
+        ));
+    }
+    run_command_output(
+        "ethtool",
+        &["-G", ifname, "rx", &rx.to_string(), "tx", &tx.to_string()],
+    )?;
+    Ok(())
+}
+
+fn check_running_interface(ifname: &str) -> Result<()> {
+    if interfaces().iter().any(|iface| iface.name == ifname) {
+        Ok(())
+    } else {
+        Err(anyhow!("interface not found: {}", ifname))
+    }
+}
+
+// Parses the output of `ethtool -g` like:
+//
+// Ring parameters for eth0:
+// Pre-set maximums:
+// RX:             4096
+// RX Mini:        n/a
+// TX:             4096
+// Current hardware settings:
+// RX:             256
+// RX Mini:        n/a
+// TX:             256
+fn parse_ring(output: &str) -> Option<RingSize> {
+    let mut max = (None, None);
+    let mut current = (None, None);
+    let mut section = &mut max;
+    for line in output.lines() {
+        let line = line.trim();
+        if line.starts_with("Current hardware settings") {
+            section = &mut current;
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().parse::<u32>().ok();
+        match key.trim() {
+            "RX" => section.0 = value,
+            "TX" => section.1 = value,
+            _ => {}
         }
     }
+    Some(RingSize {
+        rx: current.0?,
+        tx: current.1?,
+        rx_max: max.0?,
+        tx_max: max.1?,
+    })
+}
 
-    if let Some(ipaddr) = &nic_output.gateway4 {
-        if let Err(e) = validate_ipaddress(ipaddr) {
-            return Err(anyhow!("invalid gateway4 address: {}. {:?}", ipaddr, e));
+// Generates the backend configuration from the netplan yaml files with
+// `netplan generate`, without applying it. The running interfaces are not
+// changed, so this checks the yaml files without disrupting the network.
+//
+// # Errors
+//
+// * fail to run netplan generate. The error contains its stderr.
+// * `CommandTimeout` if netplan generate hangs
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn generate() -> Result<()> {
+    let _lock = NetplanLock::acquire()?;
+    run_command_output_timeout("netplan", &["generate"], NETPLAN_APPLY_TIMEOUT)?;
+    Ok(())
+}
+
+// Compares the addresses and the gateway of the setting with the running
+// interface, to confirm that `set` took effect. Netplan saves the setting
+// without applying it to an unplugged link, so the items are unmatched then.
+//
+// If dhcp4 is set, the running interface should have an IPv4 address.
+//
+// # Errors
+//
+// * interface name not found
+// * invalid address in nic_output
+// * fail to execute ip command
+pub(crate) fn verify(ifname: &str, nic_output: &NicOutput) -> Result<Verification> {
+    let iface = interfaces()
+        .into_iter()
+        .find(|iface| iface.name == *ifname)
+        .ok_or_else(|| anyhow!("interface \"{}\" not found.", ifname))?;
+    let live = iface
+        .ips
+        .iter()
+        .filter_map(|ip| ip.to_string().parse::<IpNet>().ok())
+        .collect::<Vec<_>>();
+
+    let mut ret = Verification::default();
+    for addr in nic_output.addresses.iter().flatten() {
+        let addr = addr
+            .parse::<IpNet>()
+            .map_err(|e| anyhow!("invalid interface address: {}. {:?}", addr, e))?;
+        let item = format!("address {addr}");
+        if live.contains(&addr) {
+            ret.matched.push(item);
+        } else {
+            ret.unmatched.push(item);
         }
+    }
 
-        for (nic_name, nic) in &netplan.network.ethernets {
-            if nic_name != ifname && nic.gateway4.is_some() {
-                return Err(anyhow!("only one interface can have gateway."));
-            }
+    if nic_output.dhcp4 == Some(true) {
+        let item = "dhcp4 address".to_string();
+        if live.iter().any(|addr| matches!(addr, IpNet::V4(_))) {
+            ret.matched.push(item);
+        } else {
+            ret.unmatched.push(item);
         }
     }
 
-    for ip in &nic_output.nameservers {
-        for ipaddr in ip {
-            if let Err(e) = validate_ipaddress(ipaddr) {
-                return Err(anyhow!("invalid nameserver address: {}. {:?}", ipaddr, e));
-            }
+    if let Some(gateway4) = &nic_output.gateway4 {
+        let routes = run_command_output("ip", &["route", "show", "default", "dev", ifname])?;
+        let item = format!("gateway {gateway4}");
+        let found = routes.lines().any(|line| {
+            let mut fields = line.split_whitespace();
+            fields.any(|field| field == "via") && fields.next() == Some(gateway4.as_str())
+        });
+        if found {
+            ret.matched.push(item);
+        } else {
+            ret.unmatched.push(item);
         }
     }
 
-    if nic_output.dhcp4 == Some(true)
-        && (nic_output.addresses.is_some() || nic_output.nameservers.is_some())
-    {
-        return Err(anyhow!(
-            "dhcp4 and static address cannot be set in the same interface"
-        ));
+    Ok(ret)
+}
+
+// Sets addresses, gateway, and nameservers to the running interface directly,
+// without netplan. The change is not saved in yaml and is lost after reboot.
+// Use `set` to persist it.
+//
+// Addresses are added to the existing ones of the interface. The gateway
+// replaces the default route. Nameservers are set with resolvectl.
+//
+// # Errors
+//
+// * interface name not found
+// * dhcp4 is set, or invalid address in nic_output
+// * fail to execute ip or resolvectl command. The error contains its stderr.
+pub(crate) fn apply_runtime(ifname: &str, nic_output: &NicOutput) -> Result<()> {
+    if !get_interface_names(&None).iter().any(|name| name == ifname) {
+        return Err(anyhow!("interface \"{}\" not found.", ifname));
+    }
+    if nic_output.dhcp4.is_some() {
+        return Err(anyhow!("dhcp4 cannot be applied without netplan"));
     }
+    validate_nic_output(nic_output)?;
 
-    netplan.set_interface(ifname, nic_output.to());
-    netplan.apply(NETPLAN_PATH)?;
+    run_command_output("ip", &["link", "set", "dev", ifname, "up"])?;
+    for addr in nic_output.addresses.iter().flatten() {
+        run_command_output("ip", &["addr", "replace", addr, "dev", ifname])?;
+    }
+    if let Some(gateway) = &nic_output.gateway4 {
+        let metric = nic_output.gateway_metric.map(|m| m.to_string());
+        let mut args = vec!["route", "replace", "default", "via", gateway, "dev", ifname];
+        if let Some(metric) = &metric {
+            args.extend(["metric", metric]);
+        }
+        run_command_output("ip", &args)?;
+    }
+    if let Some(nameservers) = &nic_output.nameservers {
+        let mut args = vec!["dns", ifname];
+        args.extend(nameservers.iter().map(String::as_str));
+        run_command_output("resolvectl", &args)?;
+    }
     Ok(())
 }
 
-// Gets interface configurations
+// Sets a wifi interface to connect to the access point. This command will
+// OVERWRITE all existing setting in the wifi interface if exist.
 //
-// To get all interfaces:
-// let all_interfaces = ifconfig::get(&None)?;
+// The addressing of the interface, e.g. addresses or dhcp4, is taken from
+// `nic_output`.
 //
-// To get "eno1" interface:
-// let eno1_interface = ifconfig::get(&Some("eno1".to_string()))?;
+// # Errors
 //
-// Error: fail to load /etc/netplan yaml files
-pub(crate) fn get(ifname: &Option<String>) -> Result<Option<Vec<(String, NicOutput)>>> {
-    let netplan = load_netplan_yaml(NETPLAN_PATH)?;
-    if let Some(name) = ifname {
-        if let Some((_, nic)) = netplan.network.ethernets.iter().find(|(x, _)| *x == *name) {
-            return Ok(Some(vec![(name.to_string(), NicOutput::from(nic))]));
+// * empty ssid or ssid longer than 32 bytes
+// * password shorter than 8 or longer than 63 characters, or the same as
+//   `PASSWORD_MASK`
+// * dhcp4 and static ip address or nameserver address is set
+// * the gateway conflicts with the gateway of another interface
+// * fail to get or save, apply netplan yaml conf
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn set_wifi(
+    ifname: &str,
+    ssid: &str,
+    password: Option<String>,
+    nic_output: &NicOutput,
+) -> Result<()> {
+    if ssid.is_empty() || ssid.len() > 32 {
+        return Err(anyhow!("ssid should be 1 to 32 bytes"));
+    }
+    if let Some(password) = &password {
+        if !(8..=63).contains(&password.chars().count()) {
+            return Err(anyhow!("password should be 8 to 63 characters"));
         }
-    } else {
-        let mut nic_output = Vec::new();
-        for (name, nic) in &netplan.network.ethernets {
-            nic_output.push((name.to_string(), NicOutput::from(nic)));
+        if password == PASSWORD_MASK {
+            return Err(anyhow!("password should not be the mask"));
         }
-        return Ok(Some(nic_output));
     }
-    Ok(None)
+    validate_nic_output(nic_output)?;
+
+    let nic = nic_output.to();
+    let mut access_points = HashMap::new();
+    access_points.insert(ssid.to_string(), AccessPoint { password });
+    let wifi = Wifi {
+        access_points,
+        addresses: nic.addresses,
+        dhcp4: nic.dhcp4,
+        gateway4: nic.gateway4,
+        nameservers: nic.nameservers,
+        optional: nic.optional,
+        routes: nic.routes,
+    };
+
+    let _lock = NetplanLock::acquire()?;
+    let mut netplan = load_netplan_yaml(NETPLAN_PATH)?;
+    validate_gateway(&netplan, ifname, nic_output)?;
+    netplan
+        .network
+        .wifis
+        .get_or_insert_with(HashMap::new)
+        .insert(ifname.to_string(), wifi);
+    netplan.apply(NETPLAN_PATH)
 }
 
-// Removes interface or name server or gateway address from the specified interface.
-//
-// To delete interface address "192.168.3.7/24", nameserver "164.124.101.2":
-// let nic_output = NicOutput::new(
-//     Some(vec!["192.168.3.7/24".to_string()]),
-//     None,
-//     None,
-//     Some(vec!["164.124.101.2".to_string()]),);
+// Gets wifi interfaces. Passwords are replaced with `PASSWORD_MASK` unless
+// `show_password` is set.
 //
-// ifconfig::delete("eno3", &nic_output)?;
+// # Errors
 //
-// Possible errors:
 // * fail to load /etc/netplan yaml files
-// * fail to apply the change to system
-// * interface not found
-pub(crate) fn delete(ifname: &str, nic_output: &NicOutput) -> Result<()> {
-    let mut netplan = load_netplan_yaml(NETPLAN_PATH)?;
-    netplan.delete(ifname, nic_output)?;
-    netplan.apply(NETPLAN_PATH)?;
+pub(crate) fn get_wifi(show_password: bool) -> Result<Vec<(String, Wifi)>> {
+    let netplan = load_netplan_yaml(NETPLAN_PATH)?;
+    let mut wifis = netplan.wifis();
+    if !show_password {
+        for (_, wifi) in &mut wifis {
+            wifi.mask_passwords();
+        }
+    }
+    Ok(wifis)
+}
 
-    if let Some(addrs) = &nic_output.addresses {
-        for addr in addrs {
-            // apply to running interface
-            // if the device does not have this ip address, then this command will return ERROR!!!!
-            run_command("ip", &["addr", "del", addr, "dev", ifname])?;
+// Sets the description of an interface, e.g. "management" or "capture". An
+// empty or `None` description removes it.
+//
+// Descriptions are saved in `METADATA_PATH` rather than in netplan yaml,
+// because netplan does not allow unknown keys.
+//
+// # Errors
+//
+// * description is longer than `MAX_DESCRIPTION_LEN` or has control characters
+// * fail to read or write `METADATA_PATH`
+// * `NetplanBusy` if another netplan edit is in progress for a while
+pub(crate) fn set_description(ifname: &str, description: Option<String>) -> Result<()> {
+    let description = description.filter(|d| !d.is_empty());
+    if let Some(d) = &description {
+        if d.chars().count() > MAX_DESCRIPTION_LEN || d.chars().any(char::is_control) {
+            return Err(anyhow!(
+                "description should be up to {} characters without control characters",
+                MAX_DESCRIPTION_LEN
+            ));
         }
     }
+
+    let _lock = NetplanLock::acquire()?;
+    let mut descriptions = descriptions()?;
+    if let Some(d) = description {
+        descriptions.insert(ifname.to_string(), d);
+    } else {
+        descriptions.remove(ifname);
+    }
+    save_descriptions(&descriptions)
+}
+
+fn save_descriptions(descriptions: &HashMap<String, String>) -> Result<()> {
+    let tmp = format!("{METADATA_PATH}.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(descriptions)?)?;
+    fs::rename(&tmp, METADATA_PATH)?;
     Ok(())
 }
 
+// Reads the descriptions of interfaces from `METADATA_PATH`.
+fn descriptions() -> Result<HashMap<String, String>> {
+    match fs::read_to_string(METADATA_PATH) {
+        Ok(contents) => Ok(serde_json::from_str(&contents)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
 // Gets interface names starting with the specified prefix.
 // To get interface names starting with "en":
 // let names = ifconfig::get_interface_names(&Some("en".to_string()));
@@ -459,10 +2636,1007 @@ fn list_files(
     Ok(files)
 }
 
-fn run_command(cmd: &str, args: &[&str]) -> Result<bool> {
-    let status = Command::new(cmd)
-        .env("PATH", DEFAULT_PATH_ENV)
-        .args(args)
-        .status()?;
-    Ok(status.success())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::BridgeParams;
+    use std::{cell::RefCell, collections::BTreeMap};
+
+    #[derive(Default)]
+    struct FakeFileSystem {
+        files: RefCell<BTreeMap<String, String>>,
+    }
+
+    impl FakeFileSystem {
+        fn with_files(files: &[(&str, &str)]) -> Self {
+            let fs = Self::default();
+            for (path, contents) in files {
+                fs.files
+                    .borrow_mut()
+                    .insert((*path).to_string(), (*contents).to_string());
+            }
+            fs
+        }
+
+        fn paths(&self) -> Vec<String> {
+            self.files.borrow().keys().cloned().collect()
+        }
+    }
+
+    impl FileSystem for FakeFileSystem {
+        fn list_files(&self, dir: &str) -> Result<Vec<String>> {
+            let prefix = format!("{dir}/");
+            Ok(self
+                .files
+                .borrow()
+                .keys()
+                .filter_map(|path| path.strip_prefix(&prefix))
+                .map(ToString::to_string)
+                .collect())
+        }
+
+        fn write(&self, path: &str, contents: &str) -> Result<()> {
+            self.files
+                .borrow_mut()
+                .insert(path.to_string(), contents.to_string());
+            Ok(())
+        }
+
+        fn copy(&self, from: &str, to: &str) -> Result<()> {
+            let contents = self
+                .files
+                .borrow()
+                .get(from)
+                .cloned()
+                .ok_or_else(|| anyhow!("{} not found", from))?;
+            self.files.borrow_mut().insert(to.to_string(), contents);
+            Ok(())
+        }
+
+        fn remove_file(&self, path: &str) -> Result<()> {
+            self.files
+                .borrow_mut()
+                .remove(path)
+                .map(|_| ())
+                .ok_or_else(|| anyhow!("{} not found", path))
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeCommandRunner {
+        commands: RefCell<Vec<String>>,
+    }
+
+    impl CommandRunner for FakeCommandRunner {
+        fn run(&self, cmd: &str, args: &[&str]) -> Result<String> {
+            let mut command = vec![cmd];
+            command.extend_from_slice(args);
+            self.commands.borrow_mut().push(command.join(" "));
+            Ok(String::new())
+        }
+    }
+
+    const NETPLAN_ENO1: &str = "
+network:
+  version: 2
+  renderer: networkd
+  ethernets:
+    eno1:
+      dhcp4: true
+";
+
+    const NETPLAN_ENO2: &str = "
+network:
+  ethernets:
+    eno2:
+      addresses:
+        - 192.168.0.5/24
+";
+
+    fn netplan(yaml: &str) -> NetplanYaml {
+        serde_yaml::from_str(yaml).expect("valid netplan yaml")
+    }
+
+    #[test]
+    fn pending_changes_against_running() {
+        let netplan = netplan(
+            "network:
+  version: 2
+  ethernets:
+    eth0:
+      addresses: [192.168.0.6/24]
+    eth1:
+      dhcp4: true
+    eth2:
+      addresses: [10.0.0.5/8]
+",
+        );
+        let running = HashMap::from([
+            (
+                "eth0".to_string(),
+                vec![
+                    "192.168.0.5/24".parse::<IpNet>().unwrap(),
+                    "fe80::1/64".parse::<IpNet>().unwrap(),
+                ],
+            ),
+            (
+                "eth1".to_string(),
+                vec!["172.16.0.9/16".parse::<IpNet>().unwrap()],
+            ),
+        ]);
+
+        let diff = netplan.live(&running).diff(&netplan);
+        assert_eq!(diff.added, vec!["eth2".to_string()]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].name, "eth0");
+        assert_eq!(
+            diff.changed[0].fields,
+            vec![FieldChange {
+                field: "addresses".to_string(),
+                old: Some("[\"192.168.0.5/24\"]".to_string()),
+                new: Some("[\"192.168.0.6/24\"]".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn ring_from_ethtool() {
+        let output = "Ring parameters for eth0:
+Pre-set maximums:
+RX:\t\t4096
+RX Mini:\tn/a
+RX Jumbo:\tn/a
+TX:\t\t2048
+Current hardware settings:
+RX:\t\t256
+RX Mini:\tn/a
+RX Jumbo:\tn/a
+TX:\t\t512
+RX Buf Len:\tn/a
+";
+        assert_eq!(
+            parse_ring(output),
+            Some(RingSize {
+                rx: 256,
+                tx: 512,
+                rx_max: 4096,
+                tx_max: 2048,
+            })
+        );
+        assert_eq!(parse_ring("Ring parameters for lo:\n"), None);
+    }
+
+    #[test]
+    fn diff_by_interface_and_field() {
+        let old = netplan(
+            "network:
+  version: 2
+  ethernets:
+    eth0:
+      addresses: [192.168.0.5/24]
+      gateway4: 192.168.0.1
+    eth1:
+      dhcp4: true
+  wifis:
+    wlan0:
+      access-points:
+        office:
+          password: secret1
+",
+        );
+        let new = netplan(
+            "network:
+  version: 2
+  renderer: networkd
+  ethernets:
+    eth0:
+      addresses: [192.168.0.6/24]
+      gateway4: 192.168.0.1
+    eth2:
+      dhcp4: true
+  wifis:
+    wlan0:
+      access-points:
+        office:
+          password: secret2
+",
+        );
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.network,
+            vec![FieldChange {
+                field: "renderer".to_string(),
+                old: None,
+                new: Some("\"networkd\"".to_string()),
+            }]
+        );
+        assert_eq!(diff.added, vec!["eth2".to_string()]);
+        assert_eq!(diff.removed, vec!["eth1".to_string()]);
+        assert_eq!(diff.changed.len(), 2);
+        assert_eq!(diff.changed[0].name, "eth0");
+        assert_eq!(
+            diff.changed[0].fields,
+            vec![FieldChange {
+                field: "addresses".to_string(),
+                old: Some("[\"192.168.0.5/24\"]".to_string()),
+                new: Some("[\"192.168.0.6/24\"]".to_string()),
+            }]
+        );
+        assert_eq!(diff.changed[1].name, "wlan0");
+        let masked = diff.changed[1].fields[0].new.as_deref().unwrap_or_default();
+        assert!(masked.contains(PASSWORD_MASK) && !masked.contains("secret"));
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn ipv6_addresses_round_trip() {
+        let addresses = vec!["192.168.0.5/24".to_string(), "2001:db8::5/64".to_string()];
+        let nameservers = vec!["2001:4860:4860::8888".to_string(), "8.8.8.8".to_string()];
+        let nic_output = NicOutput::new(
+            Some(addresses.clone()),
+            None,
+            None,
+            Some(nameservers.clone()),
+            None,
+            None,
+        );
+        let mut yaml = netplan(NETPLAN_ENO1);
+        yaml.set_interface("eno1", nic_output.to());
+
+        let saved = netplan(&yaml.to_string());
+        let (_, nic) = &saved.network.ethernets[0];
+        let nic_output = NicOutput::from(nic);
+        assert_eq!(nic_output.addresses, Some(addresses));
+        assert_eq!(nic_output.nameservers, Some(nameservers));
+    }
+
+    #[test]
+    fn accept_ra_round_trip() {
+        let mut yaml = netplan(NETPLAN_ENO1);
+        yaml.set_accept_ra("eno1", false).unwrap();
+        assert!(yaml.to_string().contains("accept-ra: false"));
+
+        let saved = netplan(&yaml.to_string());
+        let (_, nic) = &saved.network.ethernets[0];
+        assert_eq!(nic.accept_ra, Some(false));
+        assert!(yaml.set_accept_ra("eno9", true).is_err());
+    }
+
+    #[test]
+    fn set_all_optional_except_management() {
+        let mut yaml = netplan(NETPLAN_ENO1);
+        yaml.merge(netplan(NETPLAN_ENO2));
+        assert!(yaml.set_all_optional(&["eno9".to_string()]).is_err());
+
+        let marked = yaml.set_all_optional(&["eno1".to_string()]).unwrap();
+        assert_eq!(marked, vec!["eno2".to_string()]);
+        let saved = netplan(&yaml.to_string());
+        for (ifname, nic) in &saved.network.ethernets {
+            let expected = if ifname == "eno1" { None } else { Some(true) };
+            assert_eq!(nic.optional, expected, "{ifname}");
+        }
+    }
+
+    #[test]
+    fn set_interface_by_mac_writes_match_and_set_name() {
+        assert!(parse_mac("00:11:22:33:44").is_err());
+        let mac = parse_mac("00:11:22:33:44:AA").unwrap().to_string();
+        let mut yaml = netplan(NETPLAN_ENO1);
+        yaml.set_interface_by_mac(&mac, "lan0", Nic::new(None, Some(true), None, None, None))
+            .unwrap();
+
+        let saved = netplan(&yaml.to_string());
+        let (_, nic) = saved
+            .network
+            .ethernets
+            .iter()
+            .find(|(name, _)| name == "lan0")
+            .unwrap();
+        assert_eq!(
+            nic.matching.as_ref().and_then(|m| m.macaddress.as_deref()),
+            Some("00:11:22:33:44:aa")
+        );
+        assert_eq!(nic.set_name.as_deref(), Some("lan0"));
+
+        let err = yaml
+            .set_interface_by_mac(&mac, "lan1", Nic::new(None, Some(true), None, None, None))
+            .unwrap_err();
+        assert!(err.to_string().contains("already matched by lan0"));
+    }
+
+    #[test]
+    fn rename_interface_follows_mac() {
+        let mut yaml = netplan(
+            "network:
+  ethernets:
+    enp3s0:
+      dhcp4: true
+    eno2:
+      dhcp4: true
+  vlans:
+    vlan10:
+      id: 10
+      link: enp3s0
+",
+        );
+        let mac = parse_mac("00:11:22:33:44:aa").unwrap().to_string();
+        assert!(yaml.rename_interface("enp3s0", "eno2", &mac).is_err());
+        yaml.rename_interface("enp3s0", "lan0", &mac).unwrap();
+
+        let saved = netplan(&yaml.to_string());
+        assert!(!saved.defines("enp3s0"));
+        let (_, nic) = saved
+            .network
+            .ethernets
+            .iter()
+            .find(|(name, _)| name == "lan0")
+            .unwrap();
+        assert_eq!(nic.dhcp4, Some(true));
+        assert_eq!(nic.set_name.as_deref(), Some("lan0"));
+        assert_eq!(
+            nic.matching.as_ref().and_then(|m| m.macaddress.as_deref()),
+            Some(mac.as_str())
+        );
+        assert_eq!(saved.network.vlans.unwrap()["vlan10"].link, "lan0");
+    }
+
+    #[test]
+    fn add_nameservers_keeps_existing() {
+        let mut yaml = netplan(
+            "
+network:
+  ethernets:
+    eno2:
+      addresses:
+        - 192.168.0.5/24
+      nameservers:
+        addresses:
+          - 8.8.8.8
+",
+        );
+        let new = vec!["1.1.1.1".to_string(), "8.8.8.8".to_string()];
+        yaml.add_nameservers("eno2", "addresses", &new).unwrap();
+        yaml.add_nameservers("eno2", "search", &["example.com".to_string()])
+            .unwrap();
+
+        let saved = netplan(&yaml.to_string());
+        let nameservers = saved.network.ethernets[0].1.nameservers.clone().unwrap();
+        assert_eq!(nameservers["addresses"], vec!["8.8.8.8", "1.1.1.1"]);
+        assert_eq!(nameservers["search"], vec!["example.com"]);
+
+        let mut yaml = netplan(NETPLAN_ENO1);
+        assert!(yaml.add_nameservers("eno1", "addresses", &new).is_err());
+    }
+
+    #[test]
+    fn bridge_parameters_round_trip() {
+        let yaml = netplan(
+            "network:
+  version: 2
+  ethernets: {}
+  bridges:
+    br0:
+      interfaces: [eno1, eno2]
+      addresses: []
+      nameservers:
+        addresses: null
+      parameters:
+        stp: false
+        forward-delay: 4
+",
+        );
+        let params = yaml.network.bridges.as_ref().unwrap()["br0"]
+            .parameters
+            .clone()
+            .unwrap();
+        assert_eq!(
+            params,
+            BridgeParams {
+                stp: Some(false),
+                forward_delay: Some(4),
+                hello_time: None,
+                max_age: None,
+                priority: None,
+            }
+        );
+        assert!(params.validate().is_ok());
+        let text = yaml.to_string();
+        assert!(text.contains("stp: false") && text.contains("forward-delay: 4"));
+        assert!(!text.contains("priority"));
+
+        let params = BridgeParams {
+            forward_delay: Some(1),
+            ..BridgeParams::default()
+        };
+        assert!(params.validate().is_err());
+    }
+
+    #[test]
+    fn bridge_parameters_in_range() {
+        let params = BridgeParams {
+            stp: Some(true),
+            forward_delay: Some(30),
+            hello_time: Some(1),
+            max_age: Some(40),
+            priority: Some(65535),
+        };
+        assert!(params.validate().is_ok());
+
+        for params in [
+            BridgeParams {
+                hello_time: Some(0),
+                ..BridgeParams::default()
+            },
+            BridgeParams {
+                hello_time: Some(11),
+                ..BridgeParams::default()
+            },
+            BridgeParams {
+                max_age: Some(5),
+                ..BridgeParams::default()
+            },
+            BridgeParams {
+                max_age: Some(41),
+                ..BridgeParams::default()
+            },
+        ] {
+            assert!(params.validate().is_err(), "{params:?}");
+        }
+
+        let yaml = "stp: true\nhello-time: 2\nmax-age: 20\npriority: 32768\n";
+        let params = serde_yaml::from_str::<BridgeParams>(yaml).unwrap();
+        assert_eq!(params.hello_time, Some(2));
+        assert_eq!(params.max_age, Some(20));
+        assert_eq!(params.priority, Some(32768));
+        assert_eq!(serde_yaml::to_string(&params).unwrap(), yaml);
+        assert!(serde_yaml::from_str::<BridgeParams>("priority: 65536\n").is_err());
+    }
+
+    #[test]
+    fn merged_yaml_masks_passwords() {
+        let dir = std::env::temp_dir().join(format!("roxy-merged-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("01-netcfg.yaml"), NETPLAN_ENO1).unwrap();
+        fs::write(
+            dir.join("02-wifi.yaml"),
+            "network:
+  ethernets: {}
+  wifis:
+    wlan0:
+      access-points:
+        office:
+          password: secret
+",
+        )
+        .unwrap();
+
+        let merged = netplan(&merged_yaml_in(dir.to_str().unwrap()).unwrap());
+        assert!(merged.defines("eno1") && merged.defines("wlan0"));
+        let yaml = merged.to_string();
+        assert!(yaml.contains(PASSWORD_MASK) && !yaml.contains("secret"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bootstrap_empty_dir() {
+        let dir = std::env::temp_dir().join(format!("roxy-bootstrap-{}", std::process::id()));
+        let dir_str = dir.to_str().unwrap();
+        fs::create_dir_all(&dir).unwrap();
+
+        assert!(bootstrap_in(dir_str, "systemd").is_err());
+        assert!(load_netplan_yaml_or_bootstrap(dir_str).is_ok());
+        assert!(bootstrap_in(dir_str, "NetworkManager").unwrap());
+        assert!(!bootstrap_in(dir_str, "networkd").unwrap());
+        assert_eq!(renderer_in(dir_str).unwrap(), "NetworkManager");
+        assert_eq!(load_netplan_yaml(dir_str).unwrap().network.version, Some(2));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validate_gateway_checks_every_interface_kind() {
+        let netplan = netplan(
+            "network:
+  ethernets:
+    eno1:
+      dhcp4: true
+  bridges:
+    br0:
+      interfaces: [eno2]
+      addresses: [10.0.0.2/24]
+      gateway4: 10.0.0.1
+      nameservers:
+        addresses: null
+  vlans:
+    vlan10:
+      id: 10
+      link: eno1
+      routes:
+        - to: default
+          via: 10.0.10.1
+          metric: 100
+  wifis:
+    wlan0:
+      access-points:
+        home: {}
+      routes:
+        - to: default
+          via: 10.0.20.1
+          metric: 200
+",
+        );
+        let gateway = |metric| {
+            NicOutput::new(
+                Some(vec!["192.168.0.5/24".to_string()]),
+                None,
+                Some("192.168.0.1".to_string()),
+                None,
+                None,
+                metric,
+            )
+        };
+
+        let err = validate_gateway(&netplan, "eno1", &gateway(Some(300))).unwrap_err();
+        assert!(err.to_string().contains("br0"));
+        let err = validate_gateway(&netplan, "br0", &gateway(Some(100))).unwrap_err();
+        assert!(err.to_string().contains("vlan10"));
+        let err = validate_gateway(&netplan, "br0", &gateway(Some(200))).unwrap_err();
+        assert!(err.to_string().contains("wlan0"));
+        assert!(validate_gateway(&netplan, "br0", &gateway(Some(300))).is_ok());
+    }
+
+    // Fails every command, e.g. `netplan apply` rejecting the yaml files.
+    #[derive(Default)]
+    struct FailingCommandRunner {
+        commands: RefCell<Vec<String>>,
+    }
+
+    impl CommandRunner for FailingCommandRunner {
+        fn run(&self, cmd: &str, args: &[&str]) -> Result<String> {
+            let mut command = vec![cmd];
+            command.extend_from_slice(args);
+            self.commands.borrow_mut().push(command.join(" "));
+            Err(anyhow!("{} failed: invalid yaml", command.join(" ")))
+        }
+    }
+
+    fn tarball(path: &std::path::Path, files: &[(&str, &str)]) {
+        let file = File::create(path).unwrap();
+        let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o600);
+            header.set_cksum();
+            tar.append_data(&mut header, name, contents.as_bytes())
+                .unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn read_tarball_rejects_invalid_netplan_yaml() {
+        let dir = std::env::temp_dir().join(format!("roxy-tarball-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("netplan.tar.gz");
+
+        tarball(
+            &path,
+            &[
+                ("01-netcfg.yaml", NETPLAN_ENO1),
+                ("02-netcfg.yaml", "network: ["),
+            ],
+        );
+        let err = read_tarball(path.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("02-netcfg.yaml"));
+
+        tarball(&path, &[("01-netcfg.yaml", NETPLAN_ENO1)]);
+        let files = read_tarball(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            files,
+            vec![("01-netcfg.yaml".to_string(), NETPLAN_ENO1.to_string())]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replace_yaml_files_in_dir() {
+        let dir = std::env::temp_dir().join(format!("roxy-replace-{}", std::process::id()));
+        let dir_str = dir.to_str().unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("01-netcfg.yaml"), NETPLAN_ENO1).unwrap();
+        fs::write(dir.join(".roxy-meta.json"), "{}").unwrap();
+        let files = vec![("02-netcfg.yaml".to_string(), NETPLAN_ENO2.to_string())];
+
+        let runner = FakeCommandRunner::default();
+        replace_yaml_files(dir_str, &files, &runner).unwrap();
+        assert_eq!(*runner.commands.borrow(), vec!["netplan apply"]);
+        assert_eq!(read_yaml_files(dir_str).unwrap(), files);
+        // hidden files are kept, and no staged file is left
+        let names: Vec<_> = list_files(dir_str, None, false)
+            .unwrap()
+            .into_iter()
+            .map(|(_, _, name)| name)
+            .collect();
+        assert_eq!(names, vec![".roxy-meta.json", "02-netcfg.yaml"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replace_yaml_files_restores_previous_on_failure() {
+        let dir = std::env::temp_dir().join(format!("roxy-replace-fail-{}", std::process::id()));
+        let dir_str = dir.to_str().unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("01-netcfg.yaml"), NETPLAN_ENO1).unwrap();
+        let files = vec![("02-netcfg.yaml".to_string(), NETPLAN_ENO2.to_string())];
+
+        let runner = FailingCommandRunner::default();
+        let err = replace_yaml_files(dir_str, &files, &runner).unwrap_err();
+        assert!(err.to_string().contains("invalid yaml"));
+        assert_eq!(
+            read_yaml_files(dir_str).unwrap(),
+            vec![("01-netcfg.yaml".to_string(), NETPLAN_ENO1.to_string())]
+        );
+        // the previous yaml files are applied again
+        assert_eq!(
+            *runner.commands.borrow(),
+            vec!["netplan apply", "netplan apply"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn snapshot_netplan_saves_yaml_files() {
+        let dir = std::env::temp_dir().join(format!("roxy-netplan-bak-{}", std::process::id()));
+        let dir_str = dir.to_str().unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        let backup = dir.join(NETPLAN_BACKUP);
+
+        snapshot_netplan(dir_str).unwrap();
+        assert!(!backup.exists());
+
+        fs::write(dir.join("01-netcfg.yaml"), NETPLAN_ENO1).unwrap();
+        snapshot_netplan(dir_str).unwrap();
+        let mode = fs::metadata(&backup).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, NETPLAN_FILE_MODE);
+        assert_eq!(
+            read_tarball(backup.to_str().unwrap()).unwrap(),
+            vec![("01-netcfg.yaml".to_string(), NETPLAN_ENO1.to_string())]
+        );
+        // the backup is not read as a yaml file
+        assert_eq!(list_configs_in(dir_str).unwrap().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn netplan_files_are_owner_only() {
+        let dir = std::env::temp_dir().join(format!("roxy-netplan-mode-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let from = dir.join("staged.yaml");
+        let to = dir.join("01-netcfg.yaml");
+        let (from, to) = (from.to_str().unwrap(), to.to_str().unwrap());
+        // an existing file keeps its mode unless it is changed explicitly
+        fs::write(from, "").unwrap();
+        fs::set_permissions(from, fs::Permissions::from_mode(0o644)).unwrap();
+
+        SystemFileSystem.write(from, "network: {}\n").unwrap();
+        SystemFileSystem.copy(from, to).unwrap();
+        for path in [from, to] {
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, NETPLAN_FILE_MODE);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn set_keeps_search_domains() {
+        let dir = std::env::temp_dir().join(format!("roxy-set-search-{}", std::process::id()));
+        let dir_str = dir.to_str().unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        let mut yaml = netplan(NETPLAN_ENO2);
+        yaml.add_nameservers("eno2", "search", &["example.com".to_string()])
+            .unwrap();
+        fs::write(
+            dir.join("01-netcfg.yaml"),
+            serde_yaml::to_string(&yaml).unwrap(),
+        )
+        .unwrap();
+
+        let nic_output = NicOutput::new(
+            Some(vec!["192.168.0.6/24".to_string()]),
+            None,
+            None,
+            Some(vec!["8.8.8.8".to_string()]),
+            None,
+            None,
+        );
+        let (netplan, _) = prepare_set(dir_str, "eno2", &nic_output).unwrap();
+        let nameservers = netplan.network.ethernets[0].1.nameservers.as_ref();
+        assert_eq!(
+            nameservers.and_then(|nm| nm.get("search")),
+            Some(&vec!["example.com".to_string()])
+        );
+        assert_eq!(
+            nameservers.and_then(|nm| nm.get("addresses")),
+            Some(&vec!["8.8.8.8".to_string()])
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn set_keeps_static_routes() {
+        let dir = std::env::temp_dir().join(format!("roxy-set-routes-{}", std::process::id()));
+        let dir_str = dir.to_str().unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("01-netcfg.yaml"),
+            "network:
+  version: 2
+  ethernets:
+    eno1:
+      addresses: [192.168.0.5/24]
+      routes:
+        - to: default
+          via: 192.168.0.1
+          metric: 100
+        - to: 10.10.0.0/16
+          via: 192.168.0.254
+",
+        )
+        .unwrap();
+
+        let nic_output = NicOutput::new(
+            Some(vec!["192.168.0.6/24".to_string()]),
+            None,
+            Some("192.168.0.2".to_string()),
+            None,
+            None,
+            Some(200),
+        );
+        let (netplan, _) = prepare_set(dir_str, "eno1", &nic_output).unwrap();
+        let routes = netplan.network.ethernets[0].1.routes.clone().unwrap();
+        assert_eq!(routes.len(), 2);
+        assert!(routes[0].is_default());
+        assert_eq!(routes[0].via.as_deref(), Some("192.168.0.2"));
+        assert_eq!(routes[0].metric, Some(200));
+        assert_eq!(routes[1].to, "10.10.0.0/16");
+        assert_eq!(routes[1].via.as_deref(), Some("192.168.0.254"));
+
+        // round trip: the routes survive saving and loading the yaml
+        fs::write(
+            dir.join("01-netcfg.yaml"),
+            serde_yaml::to_string(&netplan).unwrap(),
+        )
+        .unwrap();
+        let reloaded = load_netplan_yaml(dir_str).unwrap();
+        assert_eq!(reloaded.network.ethernets[0].1.routes, Some(routes));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn set_keeps_link_scope_routes() {
+        let dir = std::env::temp_dir().join(format!("roxy-set-scope-{}", std::process::id()));
+        let dir_str = dir.to_str().unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("01-netcfg.yaml"),
+            "network:
+  version: 2
+  ethernets:
+    eno1:
+      addresses: [192.168.0.5/24]
+      routes:
+        - to: 10.20.0.0/16
+          scope: link
+          table: 100
+        - to: 10.30.0.0/16
+          type: unreachable
+",
+        )
+        .unwrap();
+
+        let nic_output = NicOutput::new(
+            Some(vec!["192.168.0.6/24".to_string()]),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let (netplan, _) = prepare_set(dir_str, "eno1", &nic_output).unwrap();
+        let routes = netplan.network.ethernets[0].1.routes.clone().unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].via, None);
+        assert_eq!(
+            routes[0].extra.get("scope"),
+            Some(&serde_yaml::Value::from("link"))
+        );
+        assert_eq!(
+            routes[0].extra.get("table"),
+            Some(&serde_yaml::Value::from(100))
+        );
+        assert_eq!(
+            routes[1].extra.get("type"),
+            Some(&serde_yaml::Value::from("unreachable"))
+        );
+
+        let yaml = serde_yaml::to_string(&netplan).unwrap();
+        assert!(!yaml.contains("via"));
+        fs::write(dir.join("01-netcfg.yaml"), yaml).unwrap();
+        let reloaded = load_netplan_yaml(dir_str).unwrap();
+        assert_eq!(reloaded.network.ethernets[0].1.routes, Some(routes));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validate_accepts_what_set_accepts_on_empty_dir() {
+        let dir = std::env::temp_dir().join(format!("roxy-validate-{}", std::process::id()));
+        let dir_str = dir.to_str().unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        let nic_output = NicOutput::new(
+            Some(vec!["192.168.0.5/24".to_string()]),
+            None,
+            Some("192.168.0.1".to_string()),
+            None,
+            None,
+            None,
+        );
+
+        let (netplan, _) = prepare_set(dir_str, "eno1", &nic_output).unwrap();
+        assert!(netplan.defines("eno1"));
+        validate_in(dir_str, "eno1", &nic_output).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn load_netplan_yaml_errors() {
+        let dir = std::env::temp_dir().join(format!("roxy-netplan-{}", std::process::id()));
+        let dir_str = dir.to_str().unwrap();
+        let error = |dir: &str| {
+            load_netplan_yaml(dir)
+                .unwrap_err()
+                .downcast::<NetplanLoadError>()
+                .unwrap()
+        };
+
+        assert!(matches!(
+            error(dir_str),
+            NetplanLoadError::Unreadable { .. }
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        assert!(matches!(error(dir_str), NetplanLoadError::NoYaml { .. }));
+        fs::write(dir.join("01-netcfg.yaml"), "network: [").unwrap();
+        assert!(matches!(
+            error(dir_str),
+            NetplanLoadError::AllInvalid { .. }
+        ));
+        fs::write(dir.join("02-netcfg.yaml"), NETPLAN_ENO2).unwrap();
+        assert!(load_netplan_yaml(dir_str)
+            .unwrap_err()
+            .to_string()
+            .starts_with("01-netcfg.yaml: "));
+        assert!(load_netplan_yaml_tolerant(dir_str).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nameserver_order_round_trip() {
+        let nameservers = [
+            "10.0.0.53",
+            "8.8.8.8",
+            "10.0.0.53",
+            "1.1.1.1",
+            "192.168.0.1",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+        let nic_output = NicOutput::new(
+            Some(vec!["192.168.0.5/24".to_string()]),
+            None,
+            None,
+            Some(nameservers),
+            None,
+            None,
+        );
+        let mut yaml = netplan(NETPLAN_ENO2);
+        yaml.set_interface("eno2", nic_output.to());
+
+        let saved = netplan(&yaml.to_string());
+        let nic_output = NicOutput::from(&saved.network.ethernets[0].1);
+        assert_eq!(
+            nic_output.nameservers,
+            Some(vec![
+                "10.0.0.53".to_string(),
+                "8.8.8.8".to_string(),
+                "1.1.1.1".to_string(),
+                "192.168.0.1".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn apply_keeps_only_first_file() {
+        let fs = FakeFileSystem::with_files(&[
+            ("/etc/netplan/00-installer.yaml", NETPLAN_ENO1),
+            ("/etc/netplan/50-cloud.yaml", NETPLAN_ENO2),
+        ]);
+        let runner = FakeCommandRunner::default();
+        let mut yaml = netplan(NETPLAN_ENO1);
+        yaml.merge(netplan(NETPLAN_ENO2));
+
+        yaml.apply_with("/etc/netplan", &fs, &runner).unwrap();
+
+        assert_eq!(fs.paths(), vec!["/etc/netplan/00-installer.yaml"]);
+        let saved = netplan(&fs.files.borrow()["/etc/netplan/00-installer.yaml"]);
+        let names = saved
+            .network
+            .ethernets
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["eno1", "eno2"]);
+        assert_eq!(*runner.commands.borrow(), vec!["netplan apply"]);
+    }
+
+    #[test]
+    fn apply_stages_in_tmp() {
+        struct StagingFileSystem {
+            inner: FakeFileSystem,
+            copied: RefCell<Vec<(String, String)>>,
+        }
+
+        impl FileSystem for StagingFileSystem {
+            fn list_files(&self, dir: &str) -> Result<Vec<String>> {
+                self.inner.list_files(dir)
+            }
+            fn write(&self, path: &str, contents: &str) -> Result<()> {
+                self.inner.write(path, contents)
+            }
+            fn copy(&self, from: &str, to: &str) -> Result<()> {
+                self.copied
+                    .borrow_mut()
+                    .push((from.to_string(), to.to_string()));
+                self.inner.copy(from, to)
+            }
+            fn remove_file(&self, path: &str) -> Result<()> {
+                self.inner.remove_file(path)
+            }
+        }
+
+        let fs = StagingFileSystem {
+            inner: FakeFileSystem::with_files(&[("/etc/netplan/50-cloud.yaml", NETPLAN_ENO1)]),
+            copied: RefCell::new(Vec::new()),
+        };
+        let runner = FakeCommandRunner::default();
+
+        netplan(NETPLAN_ENO1)
+            .apply_with("/etc/netplan", &fs, &runner)
+            .unwrap();
+
+        assert_eq!(
+            *fs.copied.borrow(),
+            vec![(
+                "/tmp/50-cloud.yaml".to_string(),
+                "/etc/netplan/50-cloud.yaml".to_string()
+            )]
+        );
+        assert_eq!(fs.inner.paths(), vec!["/etc/netplan/50-cloud.yaml"]);
+    }
+
+    #[test]
+    fn apply_to_empty_dir_writes_default_file() {
+        let fs = FakeFileSystem::default();
+        let runner = FakeCommandRunner::default();
+
+        netplan(NETPLAN_ENO1)
+            .apply_with("/etc/netplan", &fs, &runner)
+            .unwrap();
+
+        assert_eq!(
+            fs.paths(),
+            vec![format!("/etc/netplan/{DEFAULT_NETPLAN_YAML}")]
+        );
+    }
 }
@@ -0,0 +1,173 @@
+use super::{create_verified, run_command_output, JournalVacuum, LogRotation};
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use std::{fs, io::ErrorKind};
+
+const LOGROTATE_PATH: &str = "/etc/logrotate.d";
+const AICE_LOG_PATH: &str = "/var/log/aice";
+const LOGROTATE_HEADER: &str = "# Managed by roxy. Manual changes will be overwritten.";
+const MAX_ROTATION_SIZE_MB: u32 = 10240;
+const MAX_ROTATION_KEEP: u32 = 365;
+
+// Gets the disk usage of the journal in bytes.
+//
+// The output of `journalctl --disk-usage` looks like:
+//
+// Archived and active journals take up 1.2G in the file system.
+//
+// # Errors
+//
+// * fail to execute journalctl command
+// * unrecognized output of journalctl command
+pub(crate) fn journal_usage() -> Result<u64> {
+    let output = run_command_output("journalctl", &["--disk-usage"])?;
+    let re = Regex::new(r"take up ([\d.]+)([KMGTPE]?)B?")?;
+    let cap = re
+        .captures(&output)
+        .ok_or_else(|| anyhow!("unrecognized output: {}", output.trim()))?;
+    let size = cap[1].parse::<f64>()?;
+    let exp = match &cap[2] {
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        "P" => 5,
+        "E" => 6,
+        _ => 0,
+    };
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let bytes = (size * 1024_f64.powi(exp)) as u64;
+    Ok(bytes)
+}
+
+// Removes archived journal files by size or time.
+//
+// # Example
+//
+// logs::journal_vacuum(JournalVacuum::Size(500))?;
+// logs::journal_vacuum(JournalVacuum::Time(30))?;
+//
+// # Errors
+//
+// * size or time is zero
+// * fail to execute journalctl command
+pub(crate) fn journal_vacuum(vacuum: JournalVacuum) -> Result<()> {
+    let arg = match vacuum {
+        JournalVacuum::Size(0) | JournalVacuum::Time(0) => {
+            return Err(anyhow!("vacuum size or time should be greater than zero"));
+        }
+        JournalVacuum::Size(size_mb) => format!("--vacuum-size={size_mb}M"),
+        JournalVacuum::Time(days) => format!("--vacuum-time={days}d"),
+    };
+    run_command_output("journalctl", &[&arg])?;
+    Ok(())
+}
+
+// Writes the logrotate policy for /var/log/aice/<service>.log into
+// /etc/logrotate.d/<service>.
+//
+// # Example
+//
+// logs::set_rotation("review", LogRotation { max_size_mb: 100, keep: 7 })?;
+//
+// # Errors
+//
+// * invalid service name
+// * max_size_mb or keep is zero or too big
+// * /etc/logrotate.d/<service> exists but is not written by roxy
+// * fail to write the file
+pub(crate) fn set_rotation(service: &str, rotation: LogRotation) -> Result<()> {
+    validate_service_name(service)?;
+    if rotation.max_size_mb == 0 || rotation.max_size_mb > MAX_ROTATION_SIZE_MB {
+        return Err(anyhow!(
+            "max size should be between 1 and {} MB",
+            MAX_ROTATION_SIZE_MB
+        ));
+    }
+    if rotation.keep == 0 || rotation.keep > MAX_ROTATION_KEEP {
+        return Err(anyhow!(
+            "the number of files to keep should be between 1 and {}",
+            MAX_ROTATION_KEEP
+        ));
+    }
+
+    let path = format!("{LOGROTATE_PATH}/{service}");
+    match fs::read_to_string(&path) {
+        Ok(contents) if !contents.starts_with(LOGROTATE_HEADER) => {
+            return Err(anyhow!("{} is not managed by roxy", path));
+        }
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+
+    let contents = format!(
+        "{LOGROTATE_HEADER}\n\
+        {AICE_LOG_PATH}/{service}.log {{\n\
+        \tsize {}M\n\
+        \trotate {}\n\
+        \tmissingok\n\
+        \tnotifempty\n\
+        \tcompress\n\
+        \tdelaycompress\n\
+        \tcopytruncate\n\
+        }}\n",
+        rotation.max_size_mb, rotation.keep
+    );
+    create_verified(&path, &contents)
+}
+
+// Reads the logrotate policy written by `set_rotation`.
+//
+// # Return
+//
+// * None if /etc/logrotate.d/<service> does not exist
+//
+// # Errors
+//
+// * invalid service name
+// * fail to read the file
+// * the file is not written by roxy
+pub(crate) fn get_rotation(service: &str) -> Result<Option<LogRotation>> {
+    validate_service_name(service)?;
+    let path = format!("{LOGROTATE_PATH}/{service}");
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if !contents.starts_with(LOGROTATE_HEADER) {
+        return Err(anyhow!("{} is not managed by roxy", path));
+    }
+
+    let re_size = Regex::new(r"(?m)^\s*size\s+(\d+)M\s*$")?;
+    let re_keep = Regex::new(r"(?m)^\s*rotate\s+(\d+)\s*$")?;
+    let max_size_mb = re_size
+        .captures(&contents)
+        .ok_or_else(|| anyhow!("size not found in {}", path))?[1]
+        .parse::<u32>()?;
+    let keep = re_keep
+        .captures(&contents)
+        .ok_or_else(|| anyhow!("rotate not found in {}", path))?[1]
+        .parse::<u32>()?;
+    Ok(Some(LogRotation { max_size_mb, keep }))
+}
+
+// True if the path is /etc/logrotate.d/<service> of a valid service name,
+// i.e. a file `set_rotation` writes.
+pub(crate) fn is_rotation_path(path: &str) -> bool {
+    path.strip_prefix(LOGROTATE_PATH)
+        .and_then(|name| name.strip_prefix('/'))
+        .is_some_and(|name| validate_service_name(name).is_ok())
+}
+
+fn validate_service_name(service: &str) -> Result<()> {
+    if !service.is_empty()
+        && service
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(anyhow!("invalid service name: {}", service))
+    }
+}
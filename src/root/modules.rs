@@ -0,0 +1,79 @@
+use super::{create_verified, run_command_output};
+use anyhow::{anyhow, Result};
+use std::{fmt::Write as FmtWrite, fs, io::ErrorKind};
+
+pub(crate) const MODULES_CONF: &str = "/etc/modules-load.d/roxy.conf";
+
+// Gets the kernel modules roxy loads at boot, in the order of
+// /etc/modules-load.d/roxy.conf. Empty if the file does not exist.
+//
+// # Example
+//
+// let modules = modules::get_loaded()?;
+//
+// # Errors
+//
+// * fail to read /etc/modules-load.d/roxy.conf
+pub(crate) fn get_loaded() -> Result<Vec<String>> {
+    let contents = match fs::read_to_string(MODULES_CONF) {
+        Ok(r) => r,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with(';'))
+        .map(ToString::to_string)
+        .collect())
+}
+
+// Sets the kernel modules to load at boot, replacing the existing ones. The
+// modules are written to /etc/modules-load.d/roxy.conf and loaded with
+// `modprobe`. Nothing is written unless every module exists.
+//
+// # Example
+//
+// modules::set_loaded(&["tcp_bbr".to_string()])?;
+//
+// # Errors
+//
+// * invalid module name
+// * the module does not exist, i.e. `modinfo` cannot find it
+// * fail to write /etc/modules-load.d/roxy.conf
+// * fail to load a module with `modprobe`
+pub(crate) fn set_loaded(modules: &[String]) -> Result<()> {
+    for module in modules {
+        validate(module)?;
+    }
+
+    let mut contents = String::new();
+    for module in modules {
+        writeln!(contents, "{module}").expect("writing to string should not fail");
+    }
+    create_verified(MODULES_CONF, &contents)?;
+
+    for module in modules {
+        run_command_output("modprobe", &["--", module])?;
+    }
+    Ok(())
+}
+
+// Checks the module name, and that the module exists with `modinfo`.
+//
+// # Errors
+//
+// * invalid module name
+// * the module does not exist
+fn validate(module: &str) -> Result<()> {
+    let valid = !module.is_empty()
+        && module
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(anyhow!("invalid module name: {}", module));
+    }
+    run_command_output("modinfo", &["--", module])
+        .map_err(|e| anyhow!("unknown module {}: {}", module, e))?;
+    Ok(())
+}
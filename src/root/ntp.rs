@@ -1,72 +1,224 @@
-use anyhow::Result;
+use super::{restart_service, run_command_output, write_verified, NtpPeer};
+use anyhow::{anyhow, Result};
+use ipnet::IpNet;
 use regex::Regex;
 use std::{
     fmt::Write as FmtWrite,
-    fs::{self, OpenOptions},
-    io::Write as IoWrite,
+    fs,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Duration,
 };
 
-const NTP_CONF: &str = "/etc/ntp.conf";
+pub(crate) const NTP_CONF: &str = "/etc/ntp.conf";
+const NTP_PORT: u16 = 123;
+const NTP_TIMEOUT: Duration = Duration::from_secs(2);
+// The options of the `restrict` line for a network `set_serve` serves. The
+// clients get the time, but cannot query or modify ntpd.
+const SERVE_OPTIONS: &str = "nomodify notrap nopeer noquery";
+// Lets ntpd accept the responses from its own servers and pools while the
+// default `restrict` lines deny time service.
+const RESTRICT_SOURCE: &str = "restrict source notrap nomodify noquery";
 
-// Set NTP server addresses.
+// Set NTP server addresses. Both `server` and `pool` lines are replaced with
+// the given servers. A server which was on a `pool` line stays a pool. Other
+// directives like `driftfile` and `restrict`, and comments are preserved.
+//
+// If `check` is true, each server is checked whether it responds to an NTP
+// request. Unreachable servers are still written, but reported as warnings.
 //
 // # Example
 //
-// let ret = ntp::set(&vec!["time.bora.net".to_string(), "time2.kriss.re.kr".to_string()])?;
+// let warnings = ntp::set(&vec!["time.bora.net".to_string(), "time2.kriss.re.kr".to_string()], true)?;
+//
+// # Return
+//
+// * Vec<String>: warnings for unreachable servers. Empty if `check` is false.
 //
 // # Errors
 //
 // * fail to open /etc/ntp.conf
-// * fail to write modified contents to /etc/ntp.conf
+// * fail to write modified contents to /etc/ntp.conf, or `WriteVerifyFailed`
+//   if it does not read back as written. ntp is not restarted.
 // * fail to restart ntp service
-pub(crate) fn set(servers: &[String]) -> Result<bool> {
+pub(crate) fn set(servers: &[String], check: bool) -> Result<Vec<String>> {
+    let warnings = if check {
+        check_servers(servers)
+    } else {
+        Vec::new()
+    };
+
+    let contents = fs::read_to_string(NTP_CONF)?;
+    let new_contents = update_servers(&contents, servers)?;
+
+    write_verified(NTP_CONF, &new_contents)?;
+
+    if !restart_service("ntp")? {
+        return Err(anyhow!("fail to restart ntp"));
+    }
+    Ok(warnings)
+}
+
+// Sets whether ntpd serves time to the clients in `allow_networks`, e.g. for
+// the downstream devices of an isolated site. Time service to the other
+// hosts is denied with `noserve` on the default `restrict` lines. The
+// `restrict` lines with a mask are managed by this, and replaced with the
+// given networks. `allow_networks` is ignored if `enabled` is false.
+//
+// # Return
+//
+// * true if ntp service is restarted
+//
+// # Errors
+//
+// * a network is not a valid IP network, or no network is given to serve
+// * fail to open /etc/ntp.conf
+// * fail to write modified contents to /etc/ntp.conf, or `WriteVerifyFailed`
+//   if it does not read back as written. ntp is not restarted.
+// * fail to restart ntp service
+pub(crate) fn set_serve(enabled: bool, allow_networks: &[String]) -> Result<bool> {
+    let networks = if enabled {
+        if allow_networks.is_empty() {
+            return Err(anyhow!("no network to serve"));
+        }
+        allow_networks
+            .iter()
+            .map(|net| {
+                net.parse::<IpNet>()
+                    .map_err(|_| anyhow!("invalid network: {}", net))
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+
     let contents = fs::read_to_string(NTP_CONF)?;
-    let lines = contents.lines();
+    write_verified(NTP_CONF, &update_restrictions(&contents, &networks)?)?;
+    restart_service("ntp")
+}
+
+// Adds `noserve` to the default `restrict` lines, and replaces the `restrict`
+// lines with a mask with the lines serving the networks. `RESTRICT_SOURCE`
+// is added if there is no `restrict source` line, so that the servers of
+// ntpd are not denied by the default lines.
+fn update_restrictions(contents: &str, networks: &[IpNet]) -> Result<String> {
+    let default_re = Regex::new(r"^\s*restrict\s+(?:-[46]\s+)?default\b")?;
+    let network_re = Regex::new(r"^\s*restrict\s+(?:-[46]\s+)?\S+\s+mask\s")?;
+    let source_re = Regex::new(r"^\s*restrict\s+source\b")?;
+    let mut new_contents = String::new();
+    let mut has_source = false;
+    for line in contents.lines() {
+        if network_re.is_match(line) {
+            continue;
+        }
+        has_source |= source_re.is_match(line);
+        new_contents.push_str(line);
+        if default_re.is_match(line) && !line.split_whitespace().any(|opt| opt == "noserve") {
+            new_contents.push_str(" noserve");
+        }
+        new_contents.push('\n');
+    }
+
+    if !has_source {
+        writeln!(new_contents, "{RESTRICT_SOURCE}").expect("writing to string should not fail");
+    }
+    for net in networks {
+        match net {
+            IpNet::V4(_) => writeln!(
+                new_contents,
+                "restrict {} mask {} {SERVE_OPTIONS}",
+                net.network(),
+                net.netmask()
+            ),
+            IpNet::V6(_) => writeln!(
+                new_contents,
+                "restrict -6 {} mask {} {SERVE_OPTIONS}",
+                net.network(),
+                net.netmask()
+            ),
+        }
+        .expect("writing to string should not fail");
+    }
+    Ok(new_contents)
+}
+
+// Replaces `server` and `pool` lines of ntp.conf with the servers. The lines
+// of the servers already in ntp.conf are kept with their options, and new
+// servers are written as `server <addr> iburst`. The servers are written at
+// the end, in the given order.
+fn update_servers(contents: &str, servers: &[String]) -> Result<String> {
+    let re = Regex::new(r"^\s*(?:server|pool)\s+(\S+)")?;
+    let mut existing = Vec::new();
     let mut new_contents = String::new();
-    for line in lines {
-        if !line.starts_with("server ") {
+    for line in contents.lines() {
+        if let Some(server) = re.captures(line).and_then(|cap| cap.get(1)) {
+            existing.push((server.as_str(), line.trim()));
+        } else {
             new_contents.push_str(line);
             new_contents.push('\n');
         }
     }
 
     for server in servers {
-        writeln!(new_contents, "server {server} iburst")
-            .expect("writing to string should not fail");
+        if let Some((_, line)) = existing.iter().find(|(addr, _)| addr == server) {
+            writeln!(new_contents, "{line}")
+        } else {
+            writeln!(new_contents, "server {server} iburst")
+        }
+        .expect("writing to string should not fail");
     }
+    Ok(new_contents)
+}
 
-    let mut file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(NTP_CONF)?;
+// Checks whether each server responds to an NTP request.
+//
+// # Return
+//
+// * Vec<String>: warnings for the servers which fail to be resolved or do not respond.
+fn check_servers(servers: &[String]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for server in servers {
+        let addrs = match (server.as_str(), NTP_PORT).to_socket_addrs() {
+            Ok(addrs) => addrs.collect::<Vec<_>>(),
+            Err(e) => {
+                warnings.push(format!("{server}: fail to resolve. {e}"));
+                continue;
+            }
+        };
+        if !addrs.iter().any(|addr| query(addr).is_ok()) {
+            warnings.push(format!("{server}: no response"));
+        }
+    }
+    warnings
+}
 
-    file.write_all(new_contents.as_bytes())?;
+// Sends an NTP client request and waits for the response.
+fn query(addr: &SocketAddr) -> Result<()> {
+    let local = if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(NTP_TIMEOUT))?;
 
-    systemctl::restart("ntp")
-        .map(|status| status.success())
-        .map_err(Into::into)
+    // LI = 0, VN = 4, Mode = 3 (client)
+    let mut request = [0_u8; 48];
+    request[0] = 0x23;
+    socket.send_to(&request, addr)?;
+
+    let mut buf = [0_u8; 48];
+    socket.recv_from(&mut buf)?;
+    Ok(())
 }
 
-// Get ntp server addresses.
+// Get ntp server addresses, including the addresses of `pool` lines.
 //
 // # Errors
 //
 // * fail to open /etc/ntp.conf
 pub(crate) fn get() -> Result<Option<Vec<String>>> {
-    let re = Regex::new(r#"server\s+([a-z0-9\.]+)\s+iburst"#)?;
     let contents = fs::read_to_string(NTP_CONF)?;
-    let lines = contents.lines();
-
-    let mut ret = Vec::new();
-    for line in lines {
-        if line.starts_with("server ") {
-            if let Some(cap) = re.captures(line) {
-                if let Some(server) = cap.get(1) {
-                    ret.push(server.as_str().to_string());
-                }
-            }
-        }
-    }
+    let ret = parse_servers(&contents)?;
     if ret.is_empty() {
         Ok(None)
     } else {
@@ -74,10 +226,83 @@ pub(crate) fn get() -> Result<Option<Vec<String>>> {
     }
 }
 
+// Gets the addresses of `server` and `pool` lines. The address can be an IPv4,
+// IPv6 address or a host name, with or without options like `iburst`.
+fn parse_servers(contents: &str) -> Result<Vec<String>> {
+    let re = Regex::new(r"^\s*(?:server|pool)\s+(\S+)")?;
+    Ok(contents
+        .lines()
+        .filter_map(|line| re.captures(line))
+        .filter_map(|cap| cap.get(1))
+        .map(|server| server.as_str().to_string())
+        .collect())
+}
+
+// Gets the peers of ntp service.
+//
+// # Errors
+//
+// * fail to execute ntpq command
+// * unrecognized output of ntpq command
+pub(crate) fn peers() -> Result<Vec<NtpPeer>> {
+    let output = run_command_output("ntpq", &["-pn"])?;
+    parse_peers(&output)
+}
+
+// Parses the output of `ntpq -pn` like:
+//
+//      remote           refid      st t when poll reach   delay   offset  jitter
+// ==============================================================================
+// *192.168.0.1     .GPS.            1 u   33   64  377    0.512    0.123   0.045
+//
+// The first character is the tally code. '*' and 'o' mean the peer is
+// selected for synchronization.
+fn parse_peers(output: &str) -> Result<Vec<NtpPeer>> {
+    let mut peers = Vec::new();
+    for line in output
+        .lines()
+        .skip_while(|line| !line.starts_with("=="))
+        .skip(1)
+    {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (tally, rest) = line.split_at(line.chars().next().map_or(0, char::len_utf8));
+        let fields = rest.split_whitespace().collect::<Vec<_>>();
+        let [remote, refid, stratum, _, _, poll, reach, delay, offset, jitter] = fields[..] else {
+            return Err(anyhow!("unrecognized ntpq output: {}", line));
+        };
+        peers.push(NtpPeer {
+            remote: remote.to_string(),
+            refid: refid.to_string(),
+            stratum: stratum.parse()?,
+            poll: parse_poll(poll)?,
+            reach: u8::from_str_radix(reach, 8)?,
+            delay: delay.parse()?,
+            offset: offset.parse()?,
+            jitter: jitter.parse()?,
+            selected: tally == "*" || tally == "o",
+        });
+    }
+    Ok(peers)
+}
+
+// Parses the polling interval. ntpq shows a long interval with a unit, e.g.
+// "2m" or "1h".
+fn parse_poll(poll: &str) -> Result<u32> {
+    let (value, unit) = match poll.char_indices().last() {
+        Some((i, 'm')) => (&poll[..i], 60),
+        Some((i, 'h')) => (&poll[..i], 3600),
+        Some((i, 'd')) => (&poll[..i], 86400),
+        _ => (poll, 1),
+    };
+    Ok(value.parse::<u32>()? * unit)
+}
+
 // True if ntp service is active
 #[must_use]
 pub(crate) fn is_active() -> bool {
-    systemctl::is_active("ntp").map_or(false, |ret| ret)
+    systemctl::is_active("ntp").unwrap_or(false)
 }
 
 // Start ntp client service
@@ -86,9 +311,7 @@ pub(crate) fn is_active() -> bool {
 //
 // * systemctl return error when starting ntp service
 pub(crate) fn enable() -> Result<bool> {
-    systemctl::restart("ntp")
-        .map(|status| status.success())
-        .map_err(Into::into)
+    restart_service("ntp")
 }
 
 // Stop ntp client service
@@ -101,3 +324,146 @@ pub(crate) fn disable() -> Result<bool> {
         .map(|status| status.success())
         .map_err(Into::into)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_servers_with_ipv6_and_fqdn() {
+        let contents = "\
+# comment
+driftfile /var/lib/ntp/ntp.drift
+server 192.168.0.1 iburst
+server 2001:db8::123 iburst
+server [fe80::1]
+server Time.Bora.NET
+pool 0.Ubuntu.pool.ntp.org iburst
+restrict -6 default kod notrap nomodify nopeer noquery limited
+";
+        assert_eq!(
+            parse_servers(contents).unwrap(),
+            vec![
+                "192.168.0.1",
+                "2001:db8::123",
+                "[fe80::1]",
+                "Time.Bora.NET",
+                "0.Ubuntu.pool.ntp.org",
+            ]
+        );
+    }
+
+    #[test]
+    fn update_servers_preserves_other_directives() {
+        let contents = "\
+# /etc/ntp.conf
+driftfile /var/lib/ntp/ntp.drift
+restrict -4 default kod notrap nomodify nopeer noquery limited
+pool 0.ubuntu.pool.ntp.org iburst
+server 192.168.0.1 iburst
+# server 10.0.0.1
+";
+        let servers = vec!["time.bora.net".to_string()];
+        assert_eq!(
+            update_servers(contents, &servers).unwrap(),
+            "\
+# /etc/ntp.conf
+driftfile /var/lib/ntp/ntp.drift
+restrict -4 default kod notrap nomodify nopeer noquery limited
+# server 10.0.0.1
+server time.bora.net iburst
+"
+        );
+    }
+
+    #[test]
+    fn update_servers_keeps_pool() {
+        let contents = "pool 0.ubuntu.pool.ntp.org iburst\nserver 192.168.0.1 prefer\n";
+        let servers = vec![
+            "192.168.0.1".to_string(),
+            "0.ubuntu.pool.ntp.org".to_string(),
+            "time.bora.net".to_string(),
+        ];
+        let new_contents = update_servers(contents, &servers).unwrap();
+        assert_eq!(
+            new_contents,
+            "\
+server 192.168.0.1 prefer
+pool 0.ubuntu.pool.ntp.org iburst
+server time.bora.net iburst
+"
+        );
+        assert_eq!(parse_servers(&new_contents).unwrap(), servers);
+    }
+
+    #[test]
+    fn update_restrictions_serves_networks() {
+        let contents = "\
+driftfile /var/lib/ntp/ntp.drift
+restrict -4 default kod notrap nomodify nopeer noquery limited
+restrict -6 default kod notrap nomodify nopeer noquery limited noserve
+restrict 10.0.0.0 mask 255.0.0.0 nomodify notrap nopeer noquery
+pool 0.ubuntu.pool.ntp.org iburst
+";
+        let networks = vec![
+            "192.168.10.7/24".parse().unwrap(),
+            "2001:db8::/64".parse().unwrap(),
+        ];
+        let served = update_restrictions(contents, &networks).unwrap();
+        assert_eq!(
+            served,
+            "\
+driftfile /var/lib/ntp/ntp.drift
+restrict -4 default kod notrap nomodify nopeer noquery limited noserve
+restrict -6 default kod notrap nomodify nopeer noquery limited noserve
+pool 0.ubuntu.pool.ntp.org iburst
+restrict source notrap nomodify noquery
+restrict 192.168.10.0 mask 255.255.255.0 nomodify notrap nopeer noquery
+restrict -6 2001:db8:: mask ffff:ffff:ffff:ffff:: nomodify notrap nopeer noquery
+"
+        );
+
+        let stopped = update_restrictions(&served, &[]).unwrap();
+        assert_eq!(
+            stopped,
+            "\
+driftfile /var/lib/ntp/ntp.drift
+restrict -4 default kod notrap nomodify nopeer noquery limited noserve
+restrict -6 default kod notrap nomodify nopeer noquery limited noserve
+pool 0.ubuntu.pool.ntp.org iburst
+restrict source notrap nomodify noquery
+"
+        );
+    }
+
+    #[test]
+    fn parse_peers_with_tally_codes() {
+        let output = "\
+     remote           refid      st t when poll reach   delay   offset  jitter
+==============================================================================
+ 0.ubuntu.pool.n .POOL.          16 p    -   64    0    0.000    0.000   0.000
+*192.168.0.1     .GPS.            1 u   33   64  377    0.512    0.123   0.045
++2001:db8::123   10.0.0.1         2 u   12  128   17    1.204   -0.310   0.102
+";
+        let peers = parse_peers(output).unwrap();
+        assert_eq!(peers.len(), 3);
+        assert!(!peers[0].selected);
+        assert_eq!(peers[0].stratum, 16);
+        assert_eq!(peers[0].reach, 0);
+        assert_eq!(peers[1].remote, "192.168.0.1");
+        assert_eq!(peers[1].refid, ".GPS.");
+        assert!(peers[1].selected);
+        assert_eq!(peers[1].reach, 0o377);
+        assert!((peers[1].delay - 0.512).abs() < f64::EPSILON);
+        assert_eq!(peers[2].remote, "2001:db8::123");
+        assert_eq!(peers[2].poll, 128);
+        assert!(!peers[2].selected);
+        assert!((peers[2].offset + 0.310).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn parse_servers_ignores_comments() {
+        let contents = "#server 192.168.0.1 iburst\n# pool ntp.ubuntu.com\n";
+        assert!(parse_servers(contents).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,77 @@
+use super::task::log_debug;
+use anyhow::{anyhow, Result};
+use nix::{
+    sys::reboot::{reboot, RebootMode},
+    unistd::{dup2, fork, setsid, sync, ForkResult},
+};
+use roxy::common::ShutdownAck;
+use std::{
+    fs::OpenOptions,
+    os::unix::io::AsRawFd,
+    process, thread,
+    time::{Duration, SystemTime},
+};
+
+// The minimum wait before rebooting or turning off, so that roxy can respond
+// to the caller first.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(1);
+
+// Schedules a reboot or power off after `delay_secs` seconds, and returns
+// once it is scheduled. A detached child process waits and performs it, so
+// the caller gets the response even for an immediate reboot.
+//
+// Failures after scheduling, e.g. of `reboot(2)`, cannot be reported to the
+// caller. They are logged in roxy.log.
+//
+// # Errors
+//
+// * fail to fork the child process
+pub(crate) fn schedule(mode: RebootMode, delay_secs: u64) -> Result<ShutdownAck> {
+    let wait = shutdown_wait(delay_secs);
+    let scheduled_at = SystemTime::now() + wait;
+
+    // SAFETY: roxy is single-threaded here, and the child only calls
+    // async-signal-safe functions before sleeping and rebooting.
+    match unsafe { fork() }.map_err(|e| anyhow!("fail to fork: {}", e))? {
+        ForkResult::Parent { .. } => Ok(ShutdownAck { scheduled_at }),
+        ForkResult::Child => {
+            detach();
+            thread::sleep(wait);
+            sync();
+            // `reboot` returns only on failure.
+            match reboot(mode) {
+                Ok(never) => match never {},
+                Err(e) => log_debug(&format!("fail to {mode:?}: {e}")),
+            }
+            process::exit(1);
+        }
+    }
+}
+
+// Returns how long to wait before the action. An immediate action still
+// waits `SHUTDOWN_GRACE`.
+fn shutdown_wait(delay_secs: u64) -> Duration {
+    Duration::from_secs(delay_secs).max(SHUTDOWN_GRACE)
+}
+
+// Detaches the child from the caller. The caller reads stdout of roxy until
+// it is closed, so the child should not hold it.
+fn detach() {
+    let _r = setsid();
+    if let Ok(null) = OpenOptions::new().read(true).write(true).open("/dev/null") {
+        for fd in 0..=2 {
+            let _r = dup2(null.as_raw_fd(), fd);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn immediate_shutdown_waits_grace() {
+        assert_eq!(shutdown_wait(0), SHUTDOWN_GRACE);
+        assert_eq!(shutdown_wait(60), Duration::from_secs(60));
+    }
+}
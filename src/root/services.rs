@@ -1,15 +1,113 @@
+use super::{restart_service, run_command_output, run_command_output_timeout, CommandTimeout};
 use anyhow::{anyhow, Result};
-use roxy::common::SubCommand;
+use roxy::common::{waitfor_up, SubCommand};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+// The number of journal lines included in the error of `restart_checked` and
+// `stop_checked`.
+const JOURNAL_LINES: &str = "20";
+
+// The interval to check whether a stopping service is inactive.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 pub fn service_control(unit: &str, cmd: SubCommand) -> Result<bool> {
     match cmd {
         SubCommand::Disable => systemctl::stop(unit)
             .map(|status| status.success())
             .map_err(Into::into),
-        SubCommand::Enable | SubCommand::Update => systemctl::restart(unit)
-            .map(|status| status.success())
-            .map_err(Into::into),
+        SubCommand::Enable | SubCommand::Update => restart_service(unit),
         SubCommand::Status => systemctl::is_active(unit).map_err(Into::into),
         _ => Err(anyhow!("invalid command")),
     }
 }
+
+// Restarts the service, and waits until it accepts connections on the local
+// port.
+//
+// # Errors
+//
+// * invalid unit name
+// * fail to restart the service
+// * the service does not open the port within `timeout` seconds
+//
+// The error of a failed restart contains the last journal lines of the
+// service.
+pub fn restart_checked(service: &str, port: u16, timeout: u64) -> Result<()> {
+    check_unit_name(service)?;
+    let reason = match restart_service(service) {
+        Ok(true) => {
+            if waitfor_up("127.0.0.1", &port.to_string(), timeout)? {
+                return Ok(());
+            }
+            format!("{service} is not up on port {port} within {timeout} seconds")
+        }
+        Ok(false) => format!("fail to restart {service}"),
+        Err(e) => format!("{e:#}"),
+    };
+    Err(journal_error(service, &reason))
+}
+
+// Stops the service, and waits until the unit is inactive, i.e. the processes
+// of the service have exited.
+//
+// # Errors
+//
+// * invalid unit name
+// * fail to stop the service
+// * the service is still active after `timeout` seconds
+//
+// The error contains the last journal lines of the service.
+pub fn stop_checked(service: &str, timeout: u64) -> Result<()> {
+    check_unit_name(service)?;
+    let timeout = Duration::from_secs(timeout);
+    let start = Instant::now();
+    let reason = match run_command_output_timeout("systemctl", &["stop", service], timeout) {
+        Ok(_) => loop {
+            if !systemctl::is_active(service)? {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                break format!(
+                    "{service} is still active after {} seconds",
+                    timeout.as_secs()
+                );
+            }
+            thread::sleep(STOP_POLL_INTERVAL);
+        },
+        Err(e) if e.is::<CommandTimeout>() => format!(
+            "{service} is still stopping after {} seconds",
+            timeout.as_secs()
+        ),
+        Err(_) => format!("fail to stop {service}"),
+    };
+    Err(journal_error(service, &reason))
+}
+
+fn check_unit_name(service: &str) -> Result<()> {
+    if service.is_empty() || service.starts_with('-') {
+        return Err(anyhow!("invalid unit name: {}", service));
+    }
+    Ok(())
+}
+
+// Builds an error with the reason and the last journal lines of the service.
+fn journal_error(service: &str, reason: &str) -> anyhow::Error {
+    match run_command_output(
+        "journalctl",
+        &[
+            "-u",
+            service,
+            "-n",
+            JOURNAL_LINES,
+            "--no-pager",
+            "-o",
+            "cat",
+        ],
+    ) {
+        Ok(journal) => anyhow!("{}\n{}", reason, journal.trim_end()),
+        Err(_) => anyhow!("{}", reason),
+    }
+}
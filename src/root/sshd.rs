@@ -1,50 +1,80 @@
-use anyhow::Result;
-use std::{
-    fmt::Write as FmtWrite,
-    fs::{self, OpenOptions},
-    io::Write as IoWrite,
+use super::{
+    backup, restart_service, ufw, write_verified, FirewallAction, FirewallDirection,
+    FirewallProtocol,
 };
+use anyhow::{anyhow, Result};
+use std::{fmt, fs};
 
-const SSHD_CONFIG: &str = "/etc/ssh/sshd_config";
+pub(crate) const SSHD_CONFIG: &str = "/etc/ssh/sshd_config";
 const SSHD_DEFAULT_PORT: u16 = 22;
 
 // Sets sshd port.
 //
+// If `update_firewall` is true, the new port is allowed in the firewall
+// before sshd_config is written and sshd restarts, and the rule for the old
+// port is deleted only after sshd restarts on the new port. If anything
+// fails before that, the rule for the new port is deleted again, so that
+// sshd is never left on a port the firewall blocks.
+//
+// If sshd fails to restart, the previous sshd_config is restored from its
+// backup and sshd is restarted with it.
+//
 // # Example
 //
-// let ret = sshd::set("10022")?;
+// let ret = sshd::set("10022", true)?;
 //
 // # Errors
 //
 // * invalid port
+// * fail to update the firewall rules
 // * fail to open ``/etc/ssh/sshd_config``
-// * fail to write modified contents to ``/etc/ssh/sshd_config``
+// * fail to write modified contents to ``/etc/ssh/sshd_config``, or
+//   `WriteVerifyFailed` if it does not read back as written. sshd is not
+//   restarted.
 // * fail to restart sshd service
-pub(crate) fn set(port: &str) -> Result<bool> {
+// * fail to delete the rule for the old port. sshd runs on the new port.
+pub(crate) fn set(port: &str, update_firewall: bool) -> Result<bool> {
     let port = port.parse::<u16>()?;
+    let old_port = get()?;
+    let update_firewall = update_firewall && old_port != port;
 
-    let contents = fs::read_to_string(SSHD_CONFIG)?;
-    let lines = contents.lines();
-    let mut new_contents = String::new();
-    for line in lines {
-        if !line.starts_with("Port ") {
-            new_contents.push_str(line);
-            new_contents.push('\n');
+    if update_firewall {
+        allow_in_firewall(port)?;
+    }
+    let restarted = save_and_restart(port).and_then(|restarted| {
+        if restarted {
+            Ok(())
+        } else {
+            Err(anyhow!("fail to restart sshd"))
         }
+    });
+    if !update_firewall {
+        return restarted.map(|()| true);
     }
+    if let Err(e) = restarted {
+        let _r = ufw::delete_rule(FirewallAction::Allow, port, FirewallProtocol::Tcp);
+        return Err(e);
+    }
+    ufw::delete_rule(FirewallAction::Allow, old_port, FirewallProtocol::Tcp)?;
+    Ok(true)
+}
 
-    writeln!(new_contents, "Port {port}").expect("writing to string should not fail");
-
-    let mut file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(SSHD_CONFIG)?;
-
-    file.write_all(new_contents.as_bytes())?;
+// Writes the port to sshd_config, and restarts sshd. If sshd fails to
+// restart, the previous sshd_config is restored and sshd is restarted with it.
+fn save_and_restart(port: u16) -> Result<bool> {
+    let mut config = SshdConfig::load()?;
+    config.set("Port", &port.to_string());
+    config.save()?;
 
-    systemctl::restart("sshd")
-        .map(|status| status.success())
-        .map_err(Into::into)
+    match restart_service("sshd") {
+        Ok(true) => Ok(true),
+        ret => {
+            if backup::restore(SSHD_CONFIG).is_ok() {
+                let _r = restart_service("sshd");
+            }
+            ret
+        }
+    }
 }
 
 // Gets sshd port number
@@ -53,18 +83,224 @@ pub(crate) fn set(port: &str) -> Result<bool> {
 //
 // * fail to open ``/etc/ssh/sshd_config``
 pub(crate) fn get() -> Result<u16> {
-    let contents = fs::read_to_string(SSHD_CONFIG)?;
-    let lines = contents.lines();
-
-    for line in lines {
-        if line.starts_with("Port ") {
-            let s = line.split(' ').collect::<Vec<_>>();
-            if let Some(port) = s.get(1) {
-                if let Ok(port) = port.parse::<u16>() {
-                    return Ok(port);
+    let config = SshdConfig::load()?;
+    Ok(config
+        .get("Port")
+        .and_then(|port| port.parse::<u16>().ok())
+        .unwrap_or(SSHD_DEFAULT_PORT))
+}
+
+// Allows the port, and checks that the active firewall has the rule. If the
+// rule is not found, it is deleted again.
+//
+// # Errors
+//
+// * fail to execute ufw command
+// * the port is not allowed in the active firewall
+fn allow_in_firewall(port: u16) -> Result<()> {
+    ufw::allow(port, FirewallProtocol::Tcp)?;
+    if ufw::is_active()? {
+        let allowed = ufw::status()?.iter().any(|rule| {
+            rule.action == FirewallAction::Allow
+                && rule.direction == FirewallDirection::In
+                && rule.port() == Some((port, FirewallProtocol::Tcp))
+        });
+        if !allowed {
+            let _r = ufw::delete_rule(FirewallAction::Allow, port, FirewallProtocol::Tcp);
+            return Err(anyhow!("port {} is not allowed in the firewall", port));
+        }
+    }
+    Ok(())
+}
+
+// A line of sshd_config.
+enum Line {
+    // A directive of the global section, e.g. "Port 22" or "Port=22". `raw` is
+    // the line as read, written back as is unless the directive is set.
+    Directive {
+        key: String,
+        value: String,
+        raw: Option<String>,
+    },
+    // Comment, empty line, or any line in or after the first `Match` block.
+    // The line is kept verbatim.
+    Verbatim(String),
+}
+
+// sshd_config which preserves comments and the order of directives.
+//
+// Only the directives in the global section, before the first `Match` block,
+// are managed. Setting or removing a directive does not change other lines.
+pub(crate) struct SshdConfig {
+    lines: Vec<Line>,
+}
+
+impl SshdConfig {
+    // Loads ``/etc/ssh/sshd_config``.
+    //
+    // # Errors
+    //
+    // * fail to open ``/etc/ssh/sshd_config``
+    pub(crate) fn load() -> Result<Self> {
+        let contents = fs::read_to_string(SSHD_CONFIG)?;
+        Ok(Self::parse(&contents))
+    }
+
+    fn parse(contents: &str) -> Self {
+        let mut in_match = false;
+        let mut lines = Vec::new();
+        for line in contents.lines() {
+            let trimmed = line.trim();
+            let end = trimmed
+                .find(|c: char| c.is_whitespace() || c == '=')
+                .unwrap_or(trimmed.len());
+            let (key, rest) = trimmed.split_at(end);
+            let rest = rest.trim_start();
+            let value = rest.strip_prefix('=').unwrap_or(rest).trim();
+            if key.eq_ignore_ascii_case("Match") {
+                in_match = true;
+            }
+            if in_match || trimmed.is_empty() || trimmed.starts_with('#') {
+                lines.push(Line::Verbatim(line.to_string()));
+                continue;
+            }
+            lines.push(Line::Directive {
+                key: key.to_string(),
+                value: value.to_string(),
+                raw: Some(line.to_string()),
+            });
+        }
+        SshdConfig { lines }
+    }
+
+    // Gets the value of the first directive. The key is case-insensitive.
+    pub(crate) fn get(&self, key: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line {
+            Line::Directive { key: k, value, .. } if k.eq_ignore_ascii_case(key) => {
+                Some(value.as_str())
+            }
+            _ => None,
+        })
+    }
+
+    // Sets the directive. The first directive with the key is replaced and
+    // the others are removed. If the key does not exist, the directive is
+    // added before the first `Match` block.
+    pub(crate) fn set(&mut self, key: &str, value: &str) {
+        let new_line = Line::Directive {
+            key: key.to_string(),
+            value: value.to_string(),
+            raw: None,
+        };
+        if let Some(pos) = self.position(key) {
+            self.remove(key);
+            self.lines.insert(pos, new_line);
+        } else {
+            let pos = self
+                .lines
+                .iter()
+                .position(|line| match line {
+                    Line::Verbatim(line) => line
+                        .split_whitespace()
+                        .next()
+                        .is_some_and(|word| word.eq_ignore_ascii_case("Match")),
+                    Line::Directive { .. } => false,
+                })
+                .unwrap_or(self.lines.len());
+            self.lines.insert(pos, new_line);
+        }
+    }
+
+    // Removes all directives with the key.
+    pub(crate) fn remove(&mut self, key: &str) {
+        self.lines.retain(|line| match line {
+            Line::Directive { key: k, .. } => !k.eq_ignore_ascii_case(key),
+            Line::Verbatim(_) => true,
+        });
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.lines.iter().position(|line| match line {
+            Line::Directive { key: k, .. } => k.eq_ignore_ascii_case(key),
+            Line::Verbatim(_) => false,
+        })
+    }
+
+    // Writes to ``/etc/ssh/sshd_config``, and reads it back.
+    //
+    // # Errors
+    //
+    // * fail to open or write ``/etc/ssh/sshd_config``
+    // * `WriteVerifyFailed` if it does not read back as written
+    pub(crate) fn save(&self) -> Result<()> {
+        write_verified(SSHD_CONFIG, &self.to_string())
+    }
+}
+
+impl fmt::Display for SshdConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Directive { raw: Some(raw), .. } | Line::Verbatim(raw) => {
+                    writeln!(f, "{raw}")?
                 }
+                Line::Directive { key, value, .. } => writeln!(f, "{key} {value}")?,
             }
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SSHD_CONFIG_SAMPLE: &str = "\
+# comment
+Port=2222
+PermitRootLogin\tno
+  PasswordAuthentication = yes
+
+Match User backup
+    Port 22
+";
+
+    #[test]
+    fn parse_directives() {
+        let config = SshdConfig::parse(SSHD_CONFIG_SAMPLE);
+        assert_eq!(config.get("port"), Some("2222"));
+        assert_eq!(config.get("PermitRootLogin"), Some("no"));
+        assert_eq!(config.get("PasswordAuthentication"), Some("yes"));
+        assert_eq!(config.get("User"), None);
+    }
+
+    #[test]
+    fn untouched_lines_are_kept() {
+        let config = SshdConfig::parse(SSHD_CONFIG_SAMPLE);
+        assert_eq!(config.to_string(), SSHD_CONFIG_SAMPLE);
+    }
+
+    #[test]
+    fn set_replaces_only_the_directive() {
+        let mut config = SshdConfig::parse(SSHD_CONFIG_SAMPLE);
+        config.set("Port", "10022");
+        config.set("ClientAliveInterval", "60");
+        assert_eq!(
+            config.to_string(),
+            "\
+# comment
+Port 10022
+PermitRootLogin\tno
+  PasswordAuthentication = yes
+
+ClientAliveInterval 60
+Match User backup
+    Port 22
+"
+        );
+
+        config.remove("PermitRootLogin");
+        assert_eq!(config.get("PermitRootLogin"), None);
+        assert_eq!(config.get("port"), Some("10022"));
     }
-    Ok(SSHD_DEFAULT_PORT)
 }
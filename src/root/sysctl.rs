@@ -0,0 +1,92 @@
+use super::{create_verified, run_command_output};
+use anyhow::{anyhow, Result};
+use std::{fmt::Write as FmtWrite, fs, io::ErrorKind, path::PathBuf};
+
+const PROC_SYS: &str = "/proc/sys";
+pub(crate) const SYSCTL_CONF: &str = "/etc/sysctl.d/99-roxy.conf";
+
+// Gets the current value of the kernel parameter.
+//
+// # Example
+//
+// let value = sysctl::get("net.core.rmem_max")?;
+//
+// # Errors
+//
+// * invalid or unknown key
+// * fail to read the parameter in /proc/sys
+pub(crate) fn get(key: &str) -> Result<String> {
+    let path = proc_path(key)?;
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+// Sets the kernel parameter persistently. The parameter is set with
+// `sysctl -w`, and written to /etc/sysctl.d/99-roxy.conf only if the kernel
+// accepts it. If the file fails to be written, the previous value is set back.
+//
+// # Example
+//
+// sysctl::set("net.core.rmem_max", "33554432")?;
+//
+// # Errors
+//
+// * invalid or unknown key
+// * invalid value
+// * fail to read or write /etc/sysctl.d/99-roxy.conf
+// * fail to execute sysctl command
+pub(crate) fn set(key: &str, value: &str) -> Result<()> {
+    proc_path(key)?;
+    let value = value.trim();
+    if value.is_empty() || value.contains('\n') {
+        return Err(anyhow!("invalid value: {:?}", value));
+    }
+
+    let previous = get(key)?;
+    let contents = match fs::read_to_string(SYSCTL_CONF) {
+        Ok(r) => r,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut new_contents = String::new();
+    for line in contents.lines() {
+        let is_key = line.split_once('=').is_some_and(|(k, _)| k.trim() == key);
+        if !is_key {
+            new_contents.push_str(line);
+            new_contents.push('\n');
+        }
+    }
+    writeln!(new_contents, "{key} = {value}").expect("writing to string should not fail");
+
+    run_command_output("sysctl", &["-w", &format!("{key}={value}")])?;
+    if let Err(e) = create_verified(SYSCTL_CONF, &new_contents) {
+        let _r = run_command_output("sysctl", &["-w", &format!("{key}={previous}")]);
+        return Err(e);
+    }
+    Ok(())
+}
+
+// Gets the path of the parameter in /proc/sys. e.g. "net.core.rmem_max" to
+// "/proc/sys/net/core/rmem_max".
+//
+// # Errors
+//
+// * invalid key
+// * the parameter does not exist
+fn proc_path(key: &str) -> Result<PathBuf> {
+    let valid = !key.is_empty()
+        && key.split('.').all(|s| {
+            !s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        });
+    if !valid {
+        return Err(anyhow!("invalid sysctl key: {}", key));
+    }
+
+    let path = PathBuf::from(PROC_SYS).join(key.replace('.', "/"));
+    if path.is_file() {
+        Ok(path)
+    } else {
+        Err(anyhow!("unknown sysctl key: {}", key))
+    }
+}
@@ -1,122 +1,156 @@
-use anyhow::{anyhow, Result};
-use std::{
-    fmt::Write as FmtWrite,
-    fs::{self, OpenOptions},
-    io::Write as IoWrite,
-    net::SocketAddr,
-};
+use super::{restart_service, run_command_output, write_verified, SyslogServer};
+use anyhow::Result;
+use std::{fmt::Write as FmtWrite, fs};
 
-const RSYSLOG_CONF: &str = "/etc/rsyslog.d/50-default.conf";
-const DEFAULT_FACILITY: &str = "user.*";
-
-// Sets or init rsyslog remote servers. Currently the facility is fixed to `user.*`.
+pub(crate) const RSYSLOG_CONF: &str = "/etc/rsyslog.d/50-default.conf";
+// Sets or init rsyslog remote servers. Only the forwarding rules `get`
+// reports are replaced. The ones it cannot parse, e.g. with a hostname or an
+// `action(type="omfwd")`, are kept as they are.
 //
 // # Example
 //
 // To set remote addresses:
-// let cmd = Some(vec![
-//     "@@192.168.0.205:7500".to_string(), // tcp
-//     "@192.168.1.71:500".to_string()     // udp
-// ]);
-// let ret = syslog::set(&cmd)?;
+// let servers = vec![SyslogServer {
+//     facility: SyslogFacility::User,
+//     proto: SyslogProtocol::Tcp,
+//     addr: "192.168.0.205".parse()?,
+//     port: 7500,
+// }];
+// let ret = syslog::set(Some(&servers))?;
 //
 // To init(delete) remote addresses:
 // let ret = syslog::set(None)?;
 //
+// # Return
+//
+// * true if rsyslogd is restarted
+//
 // # Errors
 //
-// * invalid protocol, remote address, port
 // * fail to open /etc/rsyslog.d/50-default.conf
-// * fail to write modified contents to /etc/rsyslog.d/50-default.conf
+// * fail to write modified contents to /etc/rsyslog.d/50-default.conf, or
+//   `WriteVerifyFailed` if it does not read back as written. rsyslogd is not
+//   restarted.
+// * rsyslogd rejects the modified configuration. The previous contents are
+//   restored, and rsyslogd is not restarted.
 // * fail to restart rsyslogd service
-pub(crate) fn set(remote_addrs: &Option<Vec<String>>) -> Result<bool> {
-    if let Some(addrs) = remote_addrs {
-        for addr in addrs {
-            let _addr = addr
-                .replace('@', "")
-                .trim()
-                .parse::<SocketAddr>()
-                .map_err(|e| anyhow!("invalid address: {:?}", e))?;
-        }
-    }
+pub(crate) fn set(servers: Option<&[SyslogServer]>) -> Result<bool> {
+    let contents = fs::read_to_string(RSYSLOG_CONF)?;
+    let new_contents = replace_servers(&contents, servers.unwrap_or_default());
+    apply(&contents, &new_contents)
+}
 
+// Removes every remote forwarding rule, i.e. the lines with `@` or `@@`
+// targets and the `omfwd` actions, so that only local logging remains.
+// Unlike `set(None)`, this also removes the rules `get` cannot parse.
+//
+// # Errors
+//
+// * same as `set`
+pub(crate) fn clear() -> Result<bool> {
     let contents = fs::read_to_string(RSYSLOG_CONF)?;
-    let lines = contents.lines();
+    apply(&contents, &remove_forwarding(&contents))
+}
+
+// Writes the new contents, and restarts rsyslogd if it accepts them. If not,
+// the previous contents are written back.
+fn apply(contents: &str, new_contents: &str) -> Result<bool> {
+    write_conf(new_contents)?;
+    if let Err(e) = validate() {
+        write_conf(contents)?;
+        return Err(e);
+    }
+    restart_service("rsyslog")
+}
+
+// Replaces the forwarding rules `get` reports with the servers.
+fn replace_servers(contents: &str, servers: &[SyslogServer]) -> String {
+    let mut new_contents = String::new();
+    for line in contents.lines().filter(|line| !is_server_line(line)) {
+        new_contents.push_str(line);
+        new_contents.push('\n');
+    }
+    for server in servers {
+        writeln!(new_contents, "{server}").expect("writing to string should not fail");
+    }
+    new_contents
+}
+
+// Removes the lines forwarding to a remote host. An `action(...)` spanning
+// several lines is removed up to the line closing it.
+fn remove_forwarding(contents: &str) -> String {
     let mut new_contents = String::new();
-    for line in lines {
-        if line.starts_with('#') || !line.contains('@') {
+    let mut in_action = false;
+    for line in contents.lines() {
+        if in_action {
+            in_action = !line.contains(')');
+            continue;
+        }
+        if line.trim_start().starts_with('#') || !(line.contains('@') || line.contains("omfwd")) {
             new_contents.push_str(line);
             new_contents.push('\n');
+            continue;
         }
+        in_action = line.contains("action(") && !line.contains(')');
     }
+    new_contents
+}
 
-    if let Some(addrs) = remote_addrs {
-        for addr in addrs {
-            writeln!(new_contents, "{DEFAULT_FACILITY} {addr}")
-                .expect("writing to string should not fail");
-        }
-    }
+// True if the line is a forwarding rule `get` reports.
+fn is_server_line(line: &str) -> bool {
+    !line.starts_with('#') && line.contains('@') && line.parse::<SyslogServer>().is_ok()
+}
 
-    let mut file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(RSYSLOG_CONF)?;
+// Checks the rsyslog configuration, and restarts rsyslogd only if it is
+// valid. Use this after editing the configuration out-of-band.
+//
+// # Errors
+//
+// * rsyslogd rejects the configuration. The error contains its output.
+// * fail to restart rsyslogd service
+pub(crate) fn reload() -> Result<bool> {
+    validate()?;
+    restart_service("rsyslog")
+}
 
-    file.write_all(new_contents.as_bytes())?;
+// Checks the rsyslog configuration with `rsyslogd -N1`, without restarting.
+//
+// # Errors
+//
+// * fail to execute rsyslogd
+// * rsyslogd rejects the configuration. The error contains its stderr.
+fn validate() -> Result<()> {
+    run_command_output("rsyslogd", &["-N1"])?;
+    Ok(())
+}
 
-    systemctl::restart("rsyslog")
-        .map(|status| status.success())
-        .map_err(Into::into)
+fn write_conf(contents: &str) -> Result<()> {
+    write_verified(RSYSLOG_CONF, contents)
 }
 
-// Gets rsyslog remote servers.
+// Gets rsyslog remote servers. Forwarding rules which cannot be represented
+// as `SyslogServer`, e.g. with a priority other than `*` or a hostname, are
+// skipped.
 //
 // # Example
 //
-// if let Some(addrs) = syslog::get() {
-//     for (facility, proto, addr) in &addrs {
-//         println!("facility = {}, proto = {}, dest addr = {}", facility, proto, addr);
+// if let Some(servers) = syslog::get()? {
+//     for server in &servers {
+//         println!("facility = {}, proto = {}, dest = {}:{}",
+//             server.facility, server.proto, server.addr, server.port);
 //     }
 // }
 //
 // # Errors
 //
 // * fail to open /etc/rsyslog.d/50-default.conf
-pub(crate) fn get() -> Result<Option<Vec<(String, String, String)>>> {
+pub(crate) fn get() -> Result<Option<Vec<SyslogServer>>> {
     let contents = fs::read_to_string(RSYSLOG_CONF)?;
-    let lines = contents.lines();
-
-    let mut ret = Vec::new();
-    for line in lines {
-        if line.starts_with('#') {
-            continue;
-        }
-
-        let (r, proto) = if line.contains("@@") {
-            (
-                line.trim().split("@@").collect::<Vec<_>>(),
-                "tcp".to_string(),
-            )
-        } else if line.contains('@') {
-            (
-                line.trim().split('@').collect::<Vec<_>>(),
-                "udp".to_string(),
-            )
-        } else {
-            continue;
-        };
-
-        if r.len() == 2 {
-            if let Some(first) = r.first() {
-                let facility = (*first).trim().to_string();
-                if let Some(last) = r.last() {
-                    if !last.trim().is_empty() {
-                        ret.push((facility, proto, (*last).to_string()));
-                    }
-                }
-            }
-        }
-    }
+    let ret = contents
+        .lines()
+        .filter(|line| is_server_line(line))
+        .filter_map(|line| line.parse::<SyslogServer>().ok())
+        .collect::<Vec<_>>();
 
     if ret.is_empty() {
         Ok(None)
@@ -124,3 +158,49 @@ pub(crate) fn get() -> Result<Option<Vec<(String, String, String)>>> {
         Ok(Some(ret))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use roxy::common::{SyslogFacility, SyslogProtocol};
+
+    const CONF: &str = "\
+# Default rules for rsyslog.
+auth,authpriv.*\t\t\t/var/log/auth.log
+*.* @@192.168.0.205:7500
+user.* @10.0.0.1:514
+mail.info @@loghost.example.com:514
+*.* action(type=\"omfwd\" target=\"10.0.0.9\"
+         port=\"514\" protocol=\"tcp\")
+#*.* @@10.0.0.2:514
+";
+
+    #[test]
+    fn replace_servers_keeps_unparsed_rules() {
+        let servers = [SyslogServer {
+            facility: SyslogFacility::User,
+            proto: SyslogProtocol::Tcp,
+            addr: "192.168.0.10".parse().unwrap(),
+            port: 7500,
+        }];
+        let new_contents = replace_servers(CONF, &servers);
+        assert!(!new_contents.contains("192.168.0.205"));
+        assert!(!new_contents.contains("10.0.0.1:514"));
+        assert!(new_contents.contains("mail.info @@loghost.example.com:514\n"));
+        assert!(new_contents.contains("target=\"10.0.0.9\""));
+        assert!(new_contents.contains("#*.* @@10.0.0.2:514\n"));
+        assert!(new_contents.ends_with("user.* @@192.168.0.10:7500\n"));
+    }
+
+    #[test]
+    fn remove_forwarding_removes_every_remote_rule() {
+        assert_eq!(
+            remove_forwarding(CONF),
+            "\
+# Default rules for rsyslog.
+auth,authpriv.*\t\t\t/var/log/auth.log
+#*.* @@10.0.0.2:514
+"
+        );
+    }
+}
@@ -1,24 +1,41 @@
-use super::{NicOutput, SubCommand};
+use super::{
+    Dhcp4Overrides, FirewallAction, FirewallProtocol, JournalVacuum, LogRotation, NetworkSnapshot,
+    NicOutput, SubCommand, SyslogServer,
+};
 use crate::root;
 use anyhow::{anyhow, Result};
 use chrono::Local;
 use data_encoding::BASE64;
+use roxy::common::{
+    commands, ErrorCode, RemoteError, RoxyInfo, MAX_RESPONSE_SIZE, PROTOCOL_VERSION,
+};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::Write;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) enum Task {
+    Backup { cmd: SubCommand, arg: String },
+    Banner { cmd: SubCommand, arg: String },
+    Capabilities(String),
+    Dns { cmd: SubCommand, arg: String },
     Hostname { cmd: SubCommand, arg: String },
+    Hosts { cmd: SubCommand, arg: String },
     Interface { cmd: SubCommand, arg: String },
+    Journal { cmd: SubCommand, arg: String },
+    Logrotate { cmd: SubCommand, arg: String },
+    Modules { cmd: SubCommand, arg: String },
     Ntp { cmd: SubCommand, arg: String },
+    Ping(String),
     PowerOff(String),
     Reboot(String),
     Service { cmd: SubCommand, arg: String },
     Sshd { cmd: SubCommand, arg: String },
+    Sysctl { cmd: SubCommand, arg: String },
     Syslog { cmd: SubCommand, arg: String },
     Ufw { cmd: SubCommand, arg: String },
     Version { cmd: SubCommand, arg: String },
+    Wifi { cmd: SubCommand, arg: String },
 }
 
 impl Task {
@@ -27,13 +44,23 @@ impl Task {
         T: serde::de::DeserializeOwned + std::fmt::Debug,
     {
         match self {
-            Task::Hostname { cmd: _, arg }
+            Task::Backup { cmd: _, arg }
+            | Task::Banner { cmd: _, arg }
+            | Task::Dns { cmd: _, arg }
+            | Task::Hostname { cmd: _, arg }
+            | Task::Hosts { cmd: _, arg }
             | Task::Interface { cmd: _, arg }
+            | Task::Journal { cmd: _, arg }
+            | Task::Logrotate { cmd: _, arg }
+            | Task::Modules { cmd: _, arg }
             | Task::Ntp { cmd: _, arg }
             | Task::Service { cmd: _, arg }
             | Task::Sshd { cmd: _, arg }
+            | Task::Sysctl { cmd: _, arg }
             | Task::Syslog { cmd: _, arg }
-            | Task::Version { cmd: _, arg } => {
+            | Task::Ufw { cmd: _, arg }
+            | Task::Version { cmd: _, arg }
+            | Task::Wifi { cmd: _, arg } => {
                 match bincode::deserialize::<T>(&BASE64.decode(arg.as_bytes())?) {
                     Ok(r) => {
                         log_debug(&format!("arg={r:?}"));
@@ -47,12 +74,45 @@ impl Task {
     }
 }
 
-pub(crate) type ExecResult = std::result::Result<String, &'static str>;
+pub(crate) type ExecResult = std::result::Result<String, ExecError>;
+
+// The error of a task: one of the `ERR_*` codes, and the message for the
+// caller. The message is the code itself unless the cause is known, e.g. the
+// stderr of the failed command.
+#[derive(Debug)]
+pub(crate) struct ExecError {
+    code: &'static str,
+    message: String,
+}
+
+impl ExecError {
+    fn with_cause(code: &'static str, cause: &anyhow::Error) -> Self {
+        ExecError {
+            code,
+            message: format!("{cause:#}"),
+        }
+    }
+}
+
+impl From<&'static str> for ExecError {
+    fn from(code: &'static str) -> Self {
+        ExecError {
+            code,
+            message: code.to_string(),
+        }
+    }
+}
+
 pub(crate) const OKAY: &str = "Ok";
 pub(crate) const ERR_INVALID_COMMAND: &str = "invalid command";
+const ERR_BUSY: &str = "busy";
 const ERR_FAIL: &str = "fail";
 const ERR_MESSAGE_TOO_LONG: &str = "message too long";
+const ERR_NETPLAN_INVALID: &str = "every netplan yaml file fails to be parsed";
+const ERR_NETPLAN_UNREADABLE: &str = "fail to read the netplan directory";
+const ERR_NO_NETPLAN_YAML: &str = "no netplan yaml file";
 const ERR_PARSE_FAIL: &str = "fail to serialize response message";
+pub(crate) const ERR_PROTOCOL_MISMATCH: &str = "protocol mismatch";
 
 impl Task {
     // # Errors
@@ -62,33 +122,90 @@ impl Task {
     pub fn execute(&self) -> ExecResult {
         log_debug(&format!("task {self:?}"));
         match self {
-            #[cfg(any(target_os = "linux"))]
+            Task::Capabilities(_) => self.capabilities(),
+            Task::Ping(_) => self.ping(),
+            #[cfg(target_os = "linux")]
             Task::PowerOff(_) => self.poweroff(),
-            #[cfg(any(target_os = "linux"))]
+            #[cfg(target_os = "linux")]
             Task::Reboot(_) => self.reboot(),
+            Task::Backup { cmd, arg: _ } => self.backup(*cmd),
+            Task::Banner { cmd, arg: _ } => self.banner(*cmd),
+            Task::Dns { cmd, arg: _ } => self.dns(*cmd),
             Task::Hostname { cmd, arg: _ } => self.hostname(*cmd),
+            Task::Hosts { cmd, arg: _ } => self.hosts(*cmd),
             Task::Interface { cmd, arg: _ } => self.interface(*cmd),
+            Task::Journal { cmd, arg: _ } => self.journal(*cmd),
+            Task::Logrotate { cmd, arg: _ } => self.logrotate(*cmd),
+            Task::Modules { cmd, arg: _ } => self.modules(*cmd),
             Task::Ntp { cmd, arg: _ } => self.ntp(*cmd),
             Task::Sshd { cmd, arg: _ } => self.sshd(*cmd),
+            Task::Sysctl { cmd, arg: _ } => self.sysctl(*cmd),
             Task::Syslog { cmd, arg: _ } => self.syslog(*cmd),
+            Task::Ufw { cmd, arg: _ } => self.ufw(*cmd),
             Task::Version { cmd, arg: _ } => self.version(*cmd),
             Task::Service { cmd, arg: _ } => self.service(*cmd),
-            _ => Err(ERR_INVALID_COMMAND),
+            Task::Wifi { cmd, arg: _ } => self.wifi(*cmd),
+            #[cfg(not(target_os = "linux"))]
+            _ => Err(ERR_INVALID_COMMAND.into()),
         }
     }
 
-    #[cfg(any(target_os = "linux"))]
+    // Returns the version and the protocol revision of roxy
+    //
+    // # Return
+    //
+    // * RoxyInfo
+    fn ping(&self) -> ExecResult {
+        response(
+            self,
+            RoxyInfo {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                protocol: PROTOCOL_VERSION,
+            },
+        )
+    }
+
+    // Returns the commands roxy implements
+    //
+    // # Return
+    //
+    // * Vec<String>: the `Node` of `commands()` in JSON. They are in JSON
+    //   instead of `Node`, so that a library can read the commands it does
+    //   not know.
+    fn capabilities(&self) -> ExecResult {
+        let commands = commands()
+            .iter()
+            .map(|spec| serde_json::to_string(&spec.node))
+            .collect::<serde_json::Result<Vec<_>>>()
+            .map_err(|_| ERR_PARSE_FAIL)?;
+        response(self, commands)
+    }
+
+    // Schedules a reboot after the delay in seconds
+    //
+    // # Return
+    //
+    // * ShutdownAck: the reboot is scheduled. The response is sent before
+    //   the reboot even if the delay is zero.
+    #[cfg(target_os = "linux")]
     fn reboot(&self) -> ExecResult {
-        nix::sys::reboot::reboot(nix::sys::reboot::RebootMode::RB_AUTOBOOT)
-            .map_err(|_| ERR_INVALID_COMMAND)?;
-        response(self, OKAY)
+        let delay = self.parse::<u64>().map_err(|_| ERR_INVALID_COMMAND)?;
+        let ack = root::power::schedule(nix::sys::reboot::RebootMode::RB_AUTOBOOT, delay)
+            .map_err(|e| fail(&e))?;
+        response(self, ack)
     }
 
-    #[cfg(any(target_os = "linux"))]
+    // Schedules a power off after the delay in seconds
+    //
+    // # Return
+    //
+    // * ShutdownAck: the power off is scheduled
+    #[cfg(target_os = "linux")]
     fn poweroff(&self) -> ExecResult {
-        nix::sys::reboot::reboot(nix::sys::reboot::RebootMode::RB_POWER_OFF)
-            .map_err(|_| ERR_INVALID_COMMAND)?;
-        response(self, OKAY)
+        let delay = self.parse::<u64>().map_err(|_| ERR_INVALID_COMMAND)?;
+        let ack = root::power::schedule(nix::sys::reboot::RebootMode::RB_POWER_OFF, delay)
+            .map_err(|e| fail(&e))?;
+        response(self, ack)
     }
 
     // Gets or sets version for OS and Product
@@ -103,27 +220,49 @@ impl Task {
         match cmd {
             SubCommand::SetOsVersion | SubCommand::SetProductVersion => {
                 let arg = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
-                if crate::root::hwinfo::set_version(cmd, &arg).is_ok() {
-                    response(self, OKAY)
-                } else {
-                    Err(ERR_FAIL)
-                }
+                crate::root::hwinfo::set_version(cmd, &arg).map_err(|e| fail(&e))?;
+                response(self, OKAY)
             }
-            _ => Err(ERR_INVALID_COMMAND),
+            _ => Err(ERR_INVALID_COMMAND.into()),
         }
     }
 
     // Start, stop, status(is-active), restart(update) the services or get status
+    //
+    // # Return
+    //
+    // * bool: Disable, Enable, Status, Update command
+    // * OKAY: Restart command. The service is restarted and opens the port
+    // * OKAY: Stop command. The service is stopped and inactive
+    //
+    // # Errors
+    //
+    // * Restart, Stop command: the reason with the last journal lines of the
+    //   service
     fn service(&self, cmd: SubCommand) -> ExecResult {
         match cmd {
             SubCommand::Disable | SubCommand::Enable | SubCommand::Status | SubCommand::Update => {
                 let service = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
                 match root::services::service_control(&service, cmd) {
                     Ok(r) => response(self, r),
-                    _ => Err(ERR_FAIL),
+                    Err(e) => Err(fail(&e)),
                 }
             }
-            _ => Err(ERR_INVALID_COMMAND),
+            SubCommand::Restart => {
+                let (service, port, timeout) = self
+                    .parse::<(String, u16, u64)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::services::restart_checked(&service, port, timeout).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::Stop => {
+                let (service, timeout) = self
+                    .parse::<(String, u64)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::services::stop_checked(&service, timeout).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            _ => Err(ERR_INVALID_COMMAND.into()),
         }
     }
 
@@ -131,9 +270,9 @@ impl Task {
     //
     // # Return
     //
-    // * OKAY: Init, Set command. success to execute command
-    // * Option<Vec<(String, String, String)>>: Get command.
-    //   None if remote server addresses are not exist, else (facility, proto, addr) list
+    // * OKAY: Clear, Init, Set, Update command. success to execute command
+    // * Option<Vec<SyslogServer>>: Get command.
+    //   None if remote server addresses are not exist
     //
     // # Errors
     //
@@ -141,29 +280,43 @@ impl Task {
     // * unknown subcommand or invalid argument
     fn syslog(&self, cmd: SubCommand) -> ExecResult {
         match cmd {
+            SubCommand::Clear => {
+                if root::syslog::clear().map_err(|e| fail(&e))? {
+                    response(self, OKAY)
+                } else {
+                    Err(ERR_FAIL.into())
+                }
+            }
             SubCommand::Get => {
-                let ret = root::syslog::get().map_err(|_| ERR_FAIL)?;
+                let ret = root::syslog::get().map_err(|e| fail(&e))?;
                 response(self, ret)
             }
             SubCommand::Init => {
-                if root::syslog::set(&None).is_ok() {
+                if root::syslog::set(None).map_err(|e| fail(&e))? {
                     response(self, OKAY)
                 } else {
-                    Err(ERR_FAIL)
+                    Err(ERR_FAIL.into())
                 }
             }
             SubCommand::Set => {
-                let remote_addrs = self
-                    .parse::<Vec<String>>()
+                let servers = self
+                    .parse::<Vec<SyslogServer>>()
                     .map_err(|_| ERR_INVALID_COMMAND)?;
 
-                if root::syslog::set(&Some(remote_addrs)).is_ok() {
+                if root::syslog::set(Some(&servers)).map_err(|e| fail(&e))? {
                     response(self, OKAY)
                 } else {
-                    Err(ERR_FAIL)
+                    Err(ERR_FAIL.into())
                 }
             }
-            _ => Err(ERR_INVALID_COMMAND),
+            SubCommand::Update => {
+                if root::syslog::reload().map_err(|e| fail(&e))? {
+                    response(self, OKAY)
+                } else {
+                    Err(ERR_FAIL.into())
+                }
+            }
+            _ => Err(ERR_INVALID_COMMAND.into()),
         }
     }
 
@@ -171,7 +324,7 @@ impl Task {
     //
     // # Return
     //
-    // * OKAY: Set command. Success to execute command
+    // * OKAY: Set, SetPretty command. Success to execute command
     // * String: Get command. Hostname
     //
     // # Errors
@@ -180,16 +333,55 @@ impl Task {
     // * unknown subcommand or invalid argument
     fn hostname(&self, cmd: SubCommand) -> ExecResult {
         match cmd {
-            SubCommand::Get => response(self, roxy::hostname()),
+            SubCommand::Get => response(self, root::hwinfo::current_hostname()),
             SubCommand::Set => {
                 let hostname = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
-                if hostname::set(hostname).is_ok() {
-                    response(self, OKAY)
-                } else {
-                    Err(ERR_FAIL)
+                hostname::set(hostname).map_err(|e| fail(&e.into()))?;
+                response(self, OKAY)
+            }
+            SubCommand::SetPretty => {
+                let name = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                if name.chars().any(char::is_control) {
+                    return Err(ERR_INVALID_COMMAND.into());
                 }
+                root::run_command_output("hostnamectl", &["set-hostname", "--pretty", "--", &name])
+                    .map_err(|e| fail(&e))?;
+                response(self, OKAY)
             }
-            _ => Err(ERR_INVALID_COMMAND),
+            _ => Err(ERR_INVALID_COMMAND.into()),
+        }
+    }
+
+    // Gets, sets or deletes static host entries in /etc/hosts
+    //
+    // # Return
+    //
+    // * OKAY: Delete, Set command. Success to execute command
+    // * Vec<(String, Vec<String>)>: Get command. IP address and its hostnames
+    //
+    // # Errors
+    //
+    // * fail to execute command
+    // * unknown subcommand or invalid argument
+    fn hosts(&self, cmd: SubCommand) -> ExecResult {
+        match cmd {
+            SubCommand::Delete => {
+                let ip_or_name = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                root::hosts::remove(&ip_or_name).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::Get => {
+                let ret = root::hosts::list().map_err(|e| fail(&e))?;
+                response(self, ret)
+            }
+            SubCommand::Set => {
+                let (ip, names) = self
+                    .parse::<(String, Vec<String>)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::hosts::set(&ip, &names).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            _ => Err(ERR_INVALID_COMMAND.into()),
         }
     }
 
@@ -198,9 +390,26 @@ impl Task {
     //
     // # Return
     //
-    // * OKAY: all commands except Get and List. Success to execute command
-    // * Option<Vec<(String, Nic)>>: Get command. Interface name and it's configuration.
+    // * OKAY: all commands except Export, Get, List and Set. Success to execute command
+    // * (Option<Vec<(String, NicOutput)>>, Vec<(String, String)>): Get command. Interface
+    //   name and it's configuration, and the skipped yaml file names with their errors
+    // * Vec<String>: AddNameservers, Set, SetByMac command. Warnings
+    // * Vec<String>: SetAllOptional command. Interface names marked as optional
+    // * bool: Bootstrap command. True if the netplan yaml file is written
+    // * NetplanDiff: Diff, Pending command. Changes from the running or exported
+    //   configuration to the new one
+    // * String: Export command. NetworkSnapshot in JSON
+    // * String: GetMerged command. The merged netplan yaml with masked passwords
+    // * String: GetRenderer command. The effective renderer of netplan
+    // * RingSize: GetRing command. Ring buffer sizes and their maximums
     // * Vec<String>: List command. Interface names list
+    // * Vec<(String, Result<String, String>)>: ListConfigs command. Netplan yaml file name
+    //   and its parsed conf in yaml, or the parse error
+    // * Option<String>: SourceFile command. Netplan yaml file name defining the interface
+    // * Vec<(String, AddressDrift)>: Sync command. Interface name and its address differences
+    //   between netplan yaml and the running interface
+    // * Option<String>: Validate, ValidateGenerate command. The reason if the setting is
+    //   invalid
     //
     // # Errors
     //
@@ -208,15 +417,61 @@ impl Task {
     // * unknown subcommand or invalid argument
     fn interface(&self, cmd: SubCommand) -> ExecResult {
         match cmd {
+            SubCommand::AddNameservers => {
+                let (ifname, nameservers) = self
+                    .parse::<(String, Vec<String>)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                let warnings = root::ifconfig::add_nameservers(&ifname, &nameservers)
+                    .map_err(|e| netplan_error(&e))?;
+                response(self, warnings)
+            }
+            SubCommand::AddSearch => {
+                let (ifname, domains) = self
+                    .parse::<(String, Vec<String>)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ifconfig::add_search(&ifname, &domains).map_err(|e| netplan_error(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::Apply => {
+                let (ifname, nic_output) = self
+                    .parse::<(String, NicOutput)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ifconfig::apply_runtime(&ifname, &nic_output).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::Backup => {
+                let dest = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ifconfig::backup_configs(&dest).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
             SubCommand::Delete => {
                 let (ifname, nic_output) = self
                     .parse::<(String, NicOutput)>()
                     .map_err(|_| ERR_INVALID_COMMAND)?;
-                if root::ifconfig::delete(&ifname, &nic_output).is_ok() {
-                    response(self, OKAY)
-                } else {
-                    Err(ERR_FAIL)
-                }
+                root::ifconfig::delete(&ifname, &nic_output).map_err(|e| netplan_error(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::Bootstrap => {
+                let renderer = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                let written =
+                    root::ifconfig::bootstrap(&renderer).map_err(|e| netplan_error(&e))?;
+                response(self, written)
+            }
+            SubCommand::Diff => {
+                let json = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                let snapshot = serde_json::from_str::<NetworkSnapshot>(&json)
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                let diff = root::ifconfig::diff(snapshot).map_err(|e| netplan_error(&e))?;
+                response(self, diff)
+            }
+            SubCommand::Export => {
+                let snapshot = root::ifconfig::export().map_err(|e| netplan_error(&e))?;
+                let json = serde_json::to_string(&snapshot).map_err(|_| ERR_FAIL)?;
+                response(self, json)
+            }
+            SubCommand::Generate => {
+                root::ifconfig::generate().map_err(|e| netplan_error(&e))?;
+                response(self, OKAY)
             }
             SubCommand::Get => {
                 let arg = self
@@ -224,34 +479,234 @@ impl Task {
                     .map_err(|_| ERR_INVALID_COMMAND)?;
                 match root::ifconfig::get(&arg) {
                     Ok(ret) => response(self, ret),
-                    Err(_) => Err(ERR_FAIL),
+                    Err(e) => Err(fail(&e)),
                 }
             }
+            SubCommand::GetMerged => {
+                let yaml = root::ifconfig::merged_yaml().map_err(|e| netplan_error(&e))?;
+                response(self, yaml)
+            }
+            SubCommand::GetRenderer => {
+                let renderer = root::ifconfig::renderer().map_err(|e| netplan_error(&e))?;
+                response(self, renderer)
+            }
+            SubCommand::Pending => {
+                let diff = root::ifconfig::pending_changes().map_err(|e| netplan_error(&e))?;
+                response(self, diff)
+            }
+            SubCommand::GetRing => {
+                let ifname = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                let ring = root::ifconfig::get_ring(&ifname).map_err(|e| fail(&e))?;
+                response(self, ring)
+            }
+            SubCommand::Import => {
+                let json = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                let snapshot = serde_json::from_str::<NetworkSnapshot>(&json)
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ifconfig::import(snapshot).map_err(|e| netplan_error(&e))?;
+                response(self, OKAY)
+            }
             SubCommand::Init => {
                 let ifname = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
-                if root::ifconfig::init(&ifname).is_ok() {
-                    response(self, OKAY)
-                } else {
-                    Err(ERR_FAIL)
-                }
+                root::ifconfig::init(&ifname).map_err(|e| netplan_error(&e))?;
+                response(self, OKAY)
             }
             SubCommand::List => {
                 if let Ok(arg) = self.parse::<Option<String>>() {
                     response(self, root::ifconfig::get_interface_names(&arg))
                 } else {
-                    Err(ERR_INVALID_COMMAND)
+                    Err(ERR_INVALID_COMMAND.into())
                 }
             }
+            SubCommand::ListConfigs => {
+                let configs = root::ifconfig::list_configs().map_err(|e| fail(&e))?;
+                let ret = configs
+                    .into_iter()
+                    .map(|(file, netplan)| {
+                        (
+                            file,
+                            netplan
+                                .map(|netplan| netplan.to_string())
+                                .map_err(|e| e.to_string()),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                response(self, ret)
+            }
+            SubCommand::Remove => {
+                let (ifname, force) = self
+                    .parse::<(String, bool)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ifconfig::remove_interface(&ifname, force).map_err(|e| netplan_error(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::Rename => {
+                let (old, new, force) = self
+                    .parse::<(String, String, bool)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ifconfig::rename(&old, &new, force).map_err(|e| netplan_error(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::Restore => {
+                let src = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ifconfig::restore_configs(&src).map_err(|e| netplan_error(&e))?;
+                response(self, OKAY)
+            }
             SubCommand::Set => {
+                let (ifname, nic_output, force, check_gateway) = self
+                    .parse::<(String, NicOutput, bool, bool)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                let warnings = root::ifconfig::set(&ifname, &nic_output, force, check_gateway)
+                    .map_err(|e| netplan_error(&e))?;
+                response(self, warnings)
+            }
+            SubCommand::SetByMac => {
+                let (mac, ifname, nic_output) = self
+                    .parse::<(String, String, NicOutput)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                let warnings = root::ifconfig::set_by_mac(&mac, &ifname, &nic_output)
+                    .map_err(|e| netplan_error(&e))?;
+                response(self, warnings)
+            }
+            SubCommand::SetDescription => {
+                let (ifname, description) = self
+                    .parse::<(String, Option<String>)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ifconfig::set_description(&ifname, description)
+                    .map_err(|e| netplan_error(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::SetAcceptRa => {
+                let (ifname, accept) = self
+                    .parse::<(String, bool)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ifconfig::set_accept_ra(&ifname, accept).map_err(|e| netplan_error(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::SetAllOptional => {
+                let except = self
+                    .parse::<Vec<String>>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                let marked =
+                    root::ifconfig::set_all_optional(&except).map_err(|e| netplan_error(&e))?;
+                response(self, marked)
+            }
+            SubCommand::SetDhcpOverrides => {
+                let (ifname, overrides, identifier) = self
+                    .parse::<(String, Option<String>, Option<String>)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                let overrides = overrides
+                    .map(|json| serde_json::from_str::<Dhcp4Overrides>(&json))
+                    .transpose()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ifconfig::set_dhcp_overrides(&ifname, overrides, identifier)
+                    .map_err(|e| netplan_error(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::SetMany => {
+                let (interfaces, force) = self
+                    .parse::<(Vec<(String, NicOutput)>, bool)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                let warnings =
+                    root::ifconfig::set_many(&interfaces, force).map_err(|e| netplan_error(&e))?;
+                response(self, warnings)
+            }
+            SubCommand::SetRing => {
+                let (ifname, rx, tx) = self
+                    .parse::<(String, u32, u32)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ifconfig::set_ring(&ifname, rx, tx).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::SourceFile => {
+                let ifname = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                match root::ifconfig::source_file(&ifname) {
+                    Ok(file) => response(self, file),
+                    Err(e) => Err(fail(&e)),
+                }
+            }
+            SubCommand::Sync => match root::ifconfig::sync() {
+                Ok(ret) => response(self, ret),
+                Err(e) => Err(fail(&e)),
+            },
+            SubCommand::Validate => {
                 let (ifname, nic_output) = self
                     .parse::<(String, NicOutput)>()
                     .map_err(|_| ERR_INVALID_COMMAND)?;
-                if root::ifconfig::set(&ifname, &nic_output).is_err() {
-                    return Err(ERR_FAIL);
+                match root::ifconfig::validate(&ifname, &nic_output) {
+                    Ok(()) => response(self, None::<String>),
+                    Err(e) => response(self, Some(e.to_string())),
                 }
+            }
+            SubCommand::ValidateGenerate => {
+                let (ifname, nic_output) = self
+                    .parse::<(String, NicOutput)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                match root::ifconfig::validate_generate(&ifname, &nic_output) {
+                    Ok(()) => response(self, None::<String>),
+                    Err(e) => response(self, Some(e.to_string())),
+                }
+            }
+            SubCommand::Verify => {
+                let (ifname, nic_output) = self
+                    .parse::<(String, NicOutput)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                match root::ifconfig::verify(&ifname, &nic_output) {
+                    Ok(ret) => response(self, ret),
+                    Err(e) => Err(fail(&e)),
+                }
+            }
+            _ => Err(ERR_INVALID_COMMAND.into()),
+        }
+    }
+
+    // Restores a config file roxy writes from its latest snapshot
+    //
+    // # Return
+    //
+    // * OKAY: Restore command. The file is restored
+    //
+    // # Errors
+    //
+    // * the file is not managed by roxy, or has no snapshot
+    // * unknown subcommand or invalid argument
+    fn backup(&self, cmd: SubCommand) -> ExecResult {
+        match cmd {
+            SubCommand::Restore => {
+                let path = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                root::restore_config(&path).map_err(|e| fail(&e))?;
                 response(self, OKAY)
             }
-            _ => Err(ERR_INVALID_COMMAND),
+            _ => Err(ERR_INVALID_COMMAND.into()),
+        }
+    }
+
+    // Gets or sets the login banner of sshd
+    //
+    // # Return
+    //
+    // * OKAY: Set command. Success to execute command
+    // * Option<String>: Get command. None if the banner is not set
+    //
+    // # Errors
+    //
+    // * fail to execute command
+    // * unknown subcommand or invalid argument
+    fn banner(&self, cmd: SubCommand) -> ExecResult {
+        match cmd {
+            SubCommand::Get => {
+                let ret = root::banner::get().map_err(|e| fail(&e))?;
+                response(self, ret)
+            }
+            SubCommand::Set => {
+                let text = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                if root::banner::set(&text).map_err(|e| fail(&e))? {
+                    response(self, OKAY)
+                } else {
+                    Err(ERR_FAIL.into())
+                }
+            }
+            _ => Err(ERR_INVALID_COMMAND.into()),
         }
     }
 
@@ -259,6 +714,7 @@ impl Task {
     //
     // # Return
     //
+    // * OKAY: Set command. Success to execute command
     // * u16: Get command. Port number
     //
     // # Errors
@@ -268,28 +724,288 @@ impl Task {
     fn sshd(&self, cmd: SubCommand) -> ExecResult {
         match cmd {
             SubCommand::Get => {
-                if let Ok(port) = root::sshd::get() {
-                    response(self, port)
-                } else {
-                    Err(ERR_FAIL)
+                let port = root::sshd::get().map_err(|e| fail(&e))?;
+                response(self, port)
+            }
+            SubCommand::Set => {
+                let (port, update_firewall) = self
+                    .parse::<(String, bool)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::sshd::set(&port, update_firewall).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            _ => Err(ERR_INVALID_COMMAND.into()),
+        }
+    }
+
+    // Gets the disk usage of the journal or removes archived journal files
+    //
+    // # Return
+    //
+    // * OKAY: Delete command. Success to execute command
+    // * u64: Get command. Disk usage in bytes
+    //
+    // # Errors
+    //
+    // * fail to execute command
+    // * unknown subcommand or invalid argument
+    fn journal(&self, cmd: SubCommand) -> ExecResult {
+        match cmd {
+            SubCommand::Delete => {
+                let vacuum = self
+                    .parse::<JournalVacuum>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::logs::journal_vacuum(vacuum).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::Get => match root::logs::journal_usage() {
+                Ok(usage) => response(self, usage),
+                Err(e) => Err(fail(&e)),
+            },
+            _ => Err(ERR_INVALID_COMMAND.into()),
+        }
+    }
+
+    // Flushes the DNS cache, or gets or sets the global DNS servers
+    //
+    // # Return
+    //
+    // * OKAY: Flush, Set command. Success to execute command
+    // * Vec<String>: Get command. Global DNS servers
+    // * Vec<String>: SetWithSearch command. Rejected servers and domains
+    // * EffectiveDns: Status command. DNS servers and domains in use
+    //
+    // # Errors
+    //
+    // * fail to execute command
+    // * unknown subcommand or invalid argument
+    fn dns(&self, cmd: SubCommand) -> ExecResult {
+        match cmd {
+            SubCommand::Flush => {
+                root::dns::flush().map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::Get => match root::dns::get_global() {
+                Ok(servers) => response(self, servers),
+                Err(e) => Err(fail(&e)),
+            },
+            SubCommand::GetLink => {
+                let ifname = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                match root::dns::get_link_dns(&ifname) {
+                    Ok(ret) => response(self, ret),
+                    Err(e) => Err(fail(&e)),
                 }
             }
+            SubCommand::GetSearch => match root::dns::get_search() {
+                Ok(domains) => response(self, domains),
+                Err(e) => Err(fail(&e)),
+            },
             SubCommand::Set => {
-                let port = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
-                if root::sshd::set(&port).is_ok() {
-                    response(self, OKAY)
-                } else {
-                    Err(ERR_FAIL)
+                let servers = self
+                    .parse::<Vec<String>>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::dns::set_global(&servers).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::SetLink => {
+                let (ifname, servers, domains) = self
+                    .parse::<(String, Vec<String>, Vec<String>)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::dns::set_link_dns(&ifname, &servers, &domains).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::SetSearch => {
+                let domains = self
+                    .parse::<Vec<String>>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::dns::set_search(&domains).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::SetWithSearch => {
+                let (servers, search) = self
+                    .parse::<(Vec<String>, Vec<String>)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                match root::dns::set_global_with_search(&servers, &search) {
+                    Ok(rejected) => response(self, rejected),
+                    Err(e) => Err(fail(&e)),
+                }
+            }
+            SubCommand::Status => match root::dns::effective() {
+                Ok(effective) => response(self, effective),
+                Err(e) => Err(fail(&e)),
+            },
+            _ => Err(ERR_INVALID_COMMAND.into()),
+        }
+    }
+
+    // Gets or sets wifi interfaces
+    //
+    // # Return
+    //
+    // * OKAY: Set command. Success to execute command
+    // * String: Get command. JSON of Vec<(String, Wifi)>
+    //
+    // # Errors
+    //
+    // * fail to execute command
+    // * unknown subcommand or invalid argument
+    fn wifi(&self, cmd: SubCommand) -> ExecResult {
+        match cmd {
+            SubCommand::Get => {
+                let show_password = self.parse::<bool>().map_err(|_| ERR_INVALID_COMMAND)?;
+                let wifis =
+                    root::ifconfig::get_wifi(show_password).map_err(|e| netplan_error(&e))?;
+                let json = serde_json::to_string(&wifis).map_err(|_| ERR_FAIL)?;
+                response(self, json)
+            }
+            SubCommand::Set => {
+                let (ifname, ssid, password, nic_output) = self
+                    .parse::<(String, String, Option<String>, NicOutput)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ifconfig::set_wifi(&ifname, &ssid, password, &nic_output)
+                    .map_err(|e| netplan_error(&e))?;
+                response(self, OKAY)
+            }
+            _ => Err(ERR_INVALID_COMMAND.into()),
+        }
+    }
+
+    // Gets or sets the logrotate policy of a service
+    //
+    // # Return
+    //
+    // * OKAY: Set command. Success to execute command
+    // * Option<LogRotation>: Get command. None if the policy is not set
+    //
+    // # Errors
+    //
+    // * fail to execute command
+    // * unknown subcommand or invalid argument
+    fn logrotate(&self, cmd: SubCommand) -> ExecResult {
+        match cmd {
+            SubCommand::Get => {
+                let service = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                match root::logs::get_rotation(&service) {
+                    Ok(rotation) => response(self, rotation),
+                    Err(e) => Err(fail(&e)),
+                }
+            }
+            SubCommand::Set => {
+                let (service, rotation) = self
+                    .parse::<(String, LogRotation)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::logs::set_rotation(&service, rotation).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            _ => Err(ERR_INVALID_COMMAND.into()),
+        }
+    }
+
+    // Gets or sets the kernel modules loaded at boot
+    //
+    // # Return
+    //
+    // * OKAY: Set command. Success to execute command
+    // * Vec<String>: Get command. The kernel modules loaded at boot
+    //
+    // # Errors
+    //
+    // * fail to execute command
+    // * unknown subcommand or invalid argument
+    fn modules(&self, cmd: SubCommand) -> ExecResult {
+        match cmd {
+            SubCommand::Get => {
+                let ret = root::modules::get_loaded().map_err(|e| fail(&e))?;
+                response(self, ret)
+            }
+            SubCommand::Set => {
+                let modules = self
+                    .parse::<Vec<String>>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::modules::set_loaded(&modules).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            _ => Err(ERR_INVALID_COMMAND.into()),
+        }
+    }
+
+    // Gets or sets kernel parameters
+    //
+    // # Return
+    //
+    // * OKAY: Set command. Success to execute command
+    // * String: Get command. Value of the kernel parameter
+    //
+    // # Errors
+    //
+    // * fail to execute command
+    // * unknown subcommand or invalid argument
+    fn sysctl(&self, cmd: SubCommand) -> ExecResult {
+        match cmd {
+            SubCommand::Get => {
+                let key = self.parse::<String>().map_err(|_| ERR_INVALID_COMMAND)?;
+                match root::sysctl::get(&key) {
+                    Ok(value) => response(self, value),
+                    Err(e) => Err(fail(&e)),
                 }
             }
-            _ => Err(ERR_INVALID_COMMAND),
+            SubCommand::Set => {
+                let (key, value) = self
+                    .parse::<(String, String)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::sysctl::set(&key, &value).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            _ => Err(ERR_INVALID_COMMAND.into()),
         }
     }
 
+    // Adds, deletes or gets firewall rules
+    //
     // # Return
     //
-    // * OKAY: Disable, Enable, Set command. Success to execute command
+    // * OKAY: Add, Delete command. Success to execute command. Add command
+    //   supports allow and deny rules only.
+    // * Vec<FirewallRule>: Get command. Firewall rules
+    //
+    // # Errors
+    //
+    // * fail to execute command
+    // * unknown subcommand or invalid argument
+    fn ufw(&self, cmd: SubCommand) -> ExecResult {
+        match cmd {
+            SubCommand::Add => {
+                let (action, port, proto) = self
+                    .parse::<(FirewallAction, u16, FirewallProtocol)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                let ret = match action {
+                    FirewallAction::Allow => root::ufw::allow(port, proto),
+                    FirewallAction::Deny => root::ufw::deny(port, proto),
+                    _ => return Err(ERR_INVALID_COMMAND.into()),
+                };
+                ret.map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::Delete => {
+                let (action, port, proto) = self
+                    .parse::<(FirewallAction, u16, FirewallProtocol)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                root::ufw::delete_rule(action, port, proto).map_err(|e| fail(&e))?;
+                response(self, OKAY)
+            }
+            SubCommand::Get => match root::ufw::status() {
+                Ok(ret) => response(self, ret),
+                Err(e) => Err(fail(&e)),
+            },
+            _ => Err(ERR_INVALID_COMMAND.into()),
+        }
+    }
+
+    // # Return
+    //
+    // * OKAY: Disable, Enable, SetServe command. Success to execute command
     // * Option<Vec<String>>: Get command. NTP server list
+    // * Vec<String>: Set command. Warnings for unreachable servers
     // * true/false: Status command.
     //
     // # Errors
@@ -299,70 +1015,130 @@ impl Task {
     fn ntp(&self, cmd: SubCommand) -> ExecResult {
         match cmd {
             SubCommand::Get => {
-                if let Ok(ret) = root::ntp::get() {
-                    response(self, ret)
-                } else {
-                    Err(ERR_FAIL)
-                }
+                let ret = root::ntp::get().map_err(|e| fail(&e))?;
+                response(self, ret)
             }
             SubCommand::Disable => {
-                if root::ntp::disable().is_ok() {
-                    response(self, OKAY)
-                } else {
-                    Err(ERR_FAIL)
-                }
+                root::ntp::disable().map_err(|e| fail(&e))?;
+                response(self, OKAY)
             }
             SubCommand::Enable => {
-                if root::ntp::enable().is_ok() {
-                    response(self, OKAY)
-                } else {
-                    Err(ERR_FAIL)
-                }
+                root::ntp::enable().map_err(|e| fail(&e))?;
+                response(self, OKAY)
             }
             SubCommand::Set => {
-                let servers = self
-                    .parse::<Vec<String>>()
+                let (servers, check) = self
+                    .parse::<(Vec<String>, bool)>()
                     .map_err(|_| ERR_INVALID_COMMAND)?;
 
-                if root::ntp::set(&servers).is_ok() {
+                match root::ntp::set(&servers, check) {
+                    Ok(warnings) => response(self, warnings),
+                    Err(e) => Err(fail(&e)),
+                }
+            }
+            SubCommand::SetServe => {
+                let (enabled, allow_networks) = self
+                    .parse::<(bool, Vec<String>)>()
+                    .map_err(|_| ERR_INVALID_COMMAND)?;
+                if root::ntp::set_serve(enabled, &allow_networks).map_err(|e| fail(&e))? {
                     response(self, OKAY)
                 } else {
-                    Err(ERR_FAIL)
+                    Err(ERR_FAIL.into())
                 }
             }
+            SubCommand::List => match root::ntp::peers() {
+                Ok(peers) => response(self, peers),
+                Err(e) => Err(fail(&e)),
+            },
             SubCommand::Status => response(self, root::ntp::is_active()),
-            _ => Err(ERR_INVALID_COMMAND),
+            _ => Err(ERR_INVALID_COMMAND.into()),
         }
     }
 }
 
-// Makes response message. max size is u32 bit long.
+// Makes response message. The serialized size should not be over
+// `MAX_RESPONSE_SIZE`, which the library rejects.
 //
 // # Errors
 //
-// * message size is over 64k
+// * the serialized size is over `MAX_RESPONSE_SIZE`
 // * fail to serialize input
 fn response<I>(taskcode: &Task, input: I) -> ExecResult
 where
     I: Serialize,
 {
-    if let Ok(message) = bincode::serialize(&input) {
-        if u32::try_from(message.len()).is_err() {
+    match bincode::serialized_size(&input) {
+        Ok(size) if size > MAX_RESPONSE_SIZE => {
             log::error!("reponse is too long. Task: {:?}", taskcode);
-            Err(ERR_MESSAGE_TOO_LONG)
-        } else {
-            Ok(BASE64.encode(&message))
+            return Err(ERR_MESSAGE_TOO_LONG.into());
+        }
+        Ok(_) => {}
+        Err(_) => {
+            log::error!("failed to serialize response message. Task: {:?}", taskcode);
+            return Err(ERR_PARSE_FAIL.into());
         }
+    }
+    if let Ok(message) = bincode::serialize(&input) {
+        Ok(BASE64.encode(&message))
     } else {
         log::error!("failed to serialize response message. Task: {:?}", taskcode);
-        Err(ERR_PARSE_FAIL)
+        Err(ERR_PARSE_FAIL.into())
+    }
+}
+
+// Returns `ERR_FAIL` with the cause of the failure.
+fn fail(e: &anyhow::Error) -> ExecError {
+    ExecError::with_cause(ERR_FAIL, e)
+}
+
+// Returns `ERR_BUSY` if another netplan edit holds the lock, the `ERR_*` code
+// of `NetplanLoadError` if no netplan conf is loaded, or `ERR_FAIL`. The
+// message is the error, e.g. the stderr of `netplan apply`.
+fn netplan_error(e: &anyhow::Error) -> ExecError {
+    use root::ifconfig::NetplanLoadError;
+
+    let code = if e.is::<root::ifconfig::NetplanBusy>() {
+        ERR_BUSY
+    } else {
+        match e.downcast_ref::<NetplanLoadError>() {
+            Some(NetplanLoadError::Unreadable { .. }) => ERR_NETPLAN_UNREADABLE,
+            Some(NetplanLoadError::NoYaml { .. }) => ERR_NO_NETPLAN_YAML,
+            Some(NetplanLoadError::AllInvalid { .. }) => ERR_NETPLAN_INVALID,
+            None => ERR_FAIL,
+        }
+    };
+    ExecError::with_cause(code, e)
+}
+
+// Encodes the error into base64 and bincode like the response message, so that
+// the caller gets the error code and message.
+pub(crate) fn encode_error(err: ExecError) -> String {
+    let code = match err.code {
+        ERR_BUSY => ErrorCode::Busy,
+        ERR_FAIL => ErrorCode::Fail,
+        ERR_INVALID_COMMAND => ErrorCode::InvalidCommand,
+        ERR_MESSAGE_TOO_LONG => ErrorCode::MessageTooLong,
+        ERR_NETPLAN_INVALID => ErrorCode::NetplanInvalid,
+        ERR_NETPLAN_UNREADABLE => ErrorCode::NetplanUnreadable,
+        ERR_NO_NETPLAN_YAML => ErrorCode::NoNetplanYaml,
+        ERR_PARSE_FAIL => ErrorCode::SerializeFail,
+        ERR_PROTOCOL_MISMATCH => ErrorCode::ProtocolMismatch,
+        _ => ErrorCode::Unknown,
+    };
+    let remote_error = RemoteError {
+        code,
+        message: err.message,
+    };
+    if let Ok(message) = bincode::serialize(&remote_error) {
+        BASE64.encode(&message)
+    } else {
+        remote_error.message
     }
 }
 
 // TODO: define the full path for roxy.log file
 pub fn log_debug(msg: &str) {
     if let Ok(mut writer) = fs::OpenOptions::new()
-        .write(true)
         .create(true)
         .append(true)
         .open("/data/logs/apps/roxy.log")
@@ -370,3 +1146,93 @@ pub fn log_debug(msg: &str) {
         let _r = writeln!(writer, "{:?}: {msg}", Local::now());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn command_stderr_reaches_remote_error() {
+        // fails the way `netplan apply` does on an invalid yaml file
+        let err = root::run_command_output_timeout(
+            "sh",
+            &[
+                "-c",
+                "echo 'Invalid YAML: inconsistent indentation' >&2; exit 1",
+            ],
+            Duration::from_secs(5),
+        )
+        .unwrap_err();
+
+        let encoded = encode_error(netplan_error(&err));
+        let remote_error: RemoteError =
+            bincode::deserialize(&BASE64.decode(encoded.as_bytes()).unwrap()).unwrap();
+        assert_eq!(remote_error.code, ErrorCode::Fail);
+        assert!(remote_error
+            .message
+            .contains("Invalid YAML: inconsistent indentation"));
+    }
+
+    #[test]
+    fn netplan_load_errors_have_their_codes() {
+        use root::ifconfig::NetplanLoadError;
+
+        let cases = [
+            (
+                NetplanLoadError::Unreadable {
+                    dir: "/etc/netplan".to_string(),
+                    reason: "permission denied".to_string(),
+                },
+                ErrorCode::NetplanUnreadable,
+            ),
+            (
+                NetplanLoadError::NoYaml {
+                    dir: "/etc/netplan".to_string(),
+                },
+                ErrorCode::NoNetplanYaml,
+            ),
+            (
+                NetplanLoadError::AllInvalid {
+                    dir: "/etc/netplan".to_string(),
+                },
+                ErrorCode::NetplanInvalid,
+            ),
+        ];
+        for (err, code) in cases {
+            let message = err.to_string();
+            let encoded = encode_error(netplan_error(&err.into()));
+            let remote_error: RemoteError =
+                bincode::deserialize(&BASE64.decode(encoded.as_bytes()).unwrap()).unwrap();
+            assert_eq!(remote_error.code, code);
+            assert_eq!(remote_error.message, message);
+        }
+    }
+
+    #[test]
+    fn response_over_max_size_is_too_long() {
+        struct Huge;
+
+        impl Serialize for Huge {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                #[allow(clippy::cast_possible_truncation)]
+                let len = MAX_RESPONSE_SIZE as usize;
+                serializer.collect_seq(std::iter::repeat_n(0_u8, len))
+            }
+        }
+
+        let task = Task::Ping(String::new());
+        assert!(response(&task, "eno1").is_ok());
+        let err = response(&task, Huge).unwrap_err();
+        assert_eq!(err.code, ERR_MESSAGE_TOO_LONG);
+    }
+
+    #[test]
+    fn static_error_keeps_its_message() {
+        let encoded = encode_error(ERR_INVALID_COMMAND.into());
+        let remote_error: RemoteError =
+            bincode::deserialize(&BASE64.decode(encoded.as_bytes()).unwrap()).unwrap();
+        assert_eq!(remote_error.code, ErrorCode::InvalidCommand);
+        assert_eq!(remote_error.message, ERR_INVALID_COMMAND);
+    }
+}
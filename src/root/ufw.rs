@@ -0,0 +1,195 @@
+use super::{
+    run_command_output, FirewallAction, FirewallDirection, FirewallProtocol, FirewallRule,
+};
+use anyhow::{anyhow, Result};
+
+// Adds a rule to allow the port.
+//
+// # Example
+//
+// ufw::allow(10022, FirewallProtocol::Tcp)?;
+//
+// # Errors
+//
+// * fail to execute ufw command
+pub(crate) fn allow(port: u16, proto: FirewallProtocol) -> Result<()> {
+    add_rule(FirewallAction::Allow, port, proto)
+}
+
+// Adds a rule to deny the port.
+//
+// # Errors
+//
+// * fail to execute ufw command
+pub(crate) fn deny(port: u16, proto: FirewallProtocol) -> Result<()> {
+    add_rule(FirewallAction::Deny, port, proto)
+}
+
+// Adds a rule for the port.
+//
+// # Errors
+//
+// * fail to execute ufw command
+fn add_rule(action: FirewallAction, port: u16, proto: FirewallProtocol) -> Result<()> {
+    run_command_output("ufw", &[&action.to_string(), &rule_target(port, proto)])?;
+    Ok(())
+}
+
+// Deletes the rule. It's not an error if the rule does not exist.
+//
+// # Example
+//
+// ufw::delete_rule(FirewallAction::Allow, 22, FirewallProtocol::Tcp)?;
+//
+// # Errors
+//
+// * fail to execute ufw command
+pub(crate) fn delete_rule(
+    action: FirewallAction,
+    port: u16,
+    proto: FirewallProtocol,
+) -> Result<()> {
+    run_command_output(
+        "ufw",
+        &["delete", &action.to_string(), &rule_target(port, proto)],
+    )?;
+    Ok(())
+}
+
+// True if ufw is active.
+//
+// # Errors
+//
+// * fail to execute ufw command
+pub(crate) fn is_active() -> Result<bool> {
+    let output = run_command_output("ufw", &["status"])?;
+    Ok(output.lines().any(|line| line.trim() == "Status: active"))
+}
+
+// Gets the firewall rules. Empty if ufw is inactive.
+//
+// The output of `ufw status` looks like:
+//
+// Status: active
+//
+// To                         Action      From
+// --                         ------      ----
+// 22/tcp                     ALLOW       Anywhere
+// 22/tcp (v6)                ALLOW       Anywhere (v6)
+//
+// # Errors
+//
+// * fail to execute ufw command
+// * unknown action in the output
+pub(crate) fn status() -> Result<Vec<FirewallRule>> {
+    let output = run_command_output("ufw", &["status"])?;
+    let mut rules = Vec::new();
+    let mut in_rules = false;
+    for line in output.lines() {
+        if line.starts_with("--") {
+            in_rules = true;
+            continue;
+        }
+        if !in_rules || line.trim().is_empty() {
+            continue;
+        }
+        rules.push(parse_rule(line)?);
+    }
+    Ok(rules)
+}
+
+// Parses a rule line of `ufw status`, e.g.
+// "22/tcp                     ALLOW OUT   Anywhere                   # ssh".
+// The direction is omitted for incoming rules, and the comment is optional.
+fn parse_rule(line: &str) -> Result<FirewallRule> {
+    let (rule, comment) = match line.split_once(" # ") {
+        Some((rule, comment)) => (rule, Some(comment.trim().to_string())),
+        None => (line, None),
+    };
+    let columns = rule
+        .split("  ")
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    if let [to, action_column, from] = columns[..] {
+        let mut words = action_column.split(' ');
+        let action = match words.next() {
+            Some("ALLOW") => FirewallAction::Allow,
+            Some("DENY") => FirewallAction::Deny,
+            Some("LIMIT") => FirewallAction::Limit,
+            Some("REJECT") => FirewallAction::Reject,
+            _ => return Err(anyhow!("unknown firewall action: {}", action_column)),
+        };
+        let direction = match words.next() {
+            None | Some("IN") => FirewallDirection::In,
+            Some("OUT") => FirewallDirection::Out,
+            Some("FWD") => FirewallDirection::Forward,
+            _ => return Err(anyhow!("unknown firewall direction: {}", action_column)),
+        };
+        Ok(FirewallRule {
+            to: to.to_string(),
+            action,
+            from: from.to_string(),
+            direction,
+            comment,
+        })
+    } else {
+        Err(anyhow!("invalid firewall rule: {}", line))
+    }
+}
+
+fn rule_target(port: u16, proto: FirewallProtocol) -> String {
+    match proto {
+        FirewallProtocol::Any => port.to_string(),
+        FirewallProtocol::Tcp => format!("{port}/tcp"),
+        FirewallProtocol::Udp => format!("{port}/udp"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_incoming_rule() {
+        let rule = parse_rule("22/tcp                     ALLOW       Anywhere").unwrap();
+        assert_eq!(rule.to, "22/tcp");
+        assert_eq!(rule.action, FirewallAction::Allow);
+        assert_eq!(rule.from, "Anywhere");
+        assert_eq!(rule.direction, FirewallDirection::In);
+        assert_eq!(rule.comment, None);
+
+        let rule = parse_rule("80 (v6)                    DENY IN     Anywhere (v6)").unwrap();
+        assert_eq!(rule.to, "80 (v6)");
+        assert_eq!(rule.action, FirewallAction::Deny);
+        assert_eq!(rule.from, "Anywhere (v6)");
+        assert_eq!(rule.direction, FirewallDirection::In);
+    }
+
+    #[test]
+    fn parse_rule_with_direction_and_comment() {
+        let rule = parse_rule(
+            "Anywhere                   ALLOW OUT   10.0.0.0/8                 # backup  server",
+        )
+        .unwrap();
+        assert_eq!(rule.to, "Anywhere");
+        assert_eq!(rule.action, FirewallAction::Allow);
+        assert_eq!(rule.from, "10.0.0.0/8");
+        assert_eq!(rule.direction, FirewallDirection::Out);
+        assert_eq!(rule.comment.as_deref(), Some("backup  server"));
+
+        let rule =
+            parse_rule("10022/tcp                  LIMIT       Anywhere                   # ssh")
+                .unwrap();
+        assert_eq!(rule.action, FirewallAction::Limit);
+        assert_eq!(rule.direction, FirewallDirection::In);
+        assert_eq!(rule.comment.as_deref(), Some("ssh"));
+    }
+
+    #[test]
+    fn parse_invalid_rule() {
+        assert!(parse_rule("22/tcp                     ACCEPT      Anywhere").is_err());
+        assert!(parse_rule("22/tcp                     ALLOW UP    Anywhere").is_err());
+        assert!(parse_rule("22/tcp                     ALLOW").is_err());
+    }
+}
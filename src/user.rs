@@ -1,2 +1,4 @@
+pub mod health;
 pub mod hwinfo;
+pub mod net;
 pub mod usg;
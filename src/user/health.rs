@@ -0,0 +1,130 @@
+use super::usg::disk_space;
+use crate::common::DEFAULT_PATH_ENV;
+use anyhow::Result;
+use pnet::datalink;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use sysinfo::{RefreshKind, System, SystemExt};
+
+/// The disk usage in percent over which the system is degraded.
+pub const DISK_DEGRADED_PERCENT: f32 = 80.0;
+
+/// The disk usage in percent over which the system is critical.
+pub const DISK_CRITICAL_PERCENT: f32 = 95.0;
+
+/// The overall health of the system.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HealthStatus {
+    Healthy,
+    Degraded,
+    Critical,
+}
+
+/// The result of `health_check`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HealthReport {
+    /// The overall verdict.
+    ///
+    /// * `Critical` if a service is not active or the disk usage is over
+    ///   [`DISK_CRITICAL_PERCENT`].
+    /// * `Degraded` if the clock is not synchronized by NTP, a link is down,
+    ///   or the disk usage is over [`DISK_DEGRADED_PERCENT`].
+    pub status: HealthStatus,
+
+    /// Each service and whether it is active.
+    pub services: Vec<(String, bool)>,
+
+    /// Whether the clock is synchronized by NTP. `None` if it is unknown.
+    pub ntp_synchronized: Option<bool>,
+
+    /// The disk usage in percent, of `/data` or the largest disk.
+    pub disk_usage: f32,
+
+    /// Each of the given network interfaces, and whether its link is up. An
+    /// interface that does not exist is reported as down.
+    pub links: Vec<(String, bool)>,
+}
+
+/// Checks the states of `services`, NTP synchronization, disk usage, and
+/// links of `interfaces`, and returns them with the overall verdict.
+///
+/// Only the links of `interfaces` count toward the verdict, so that an unused
+/// port, e.g. an unplugged spare NIC, does not degrade the system.
+///
+/// # Errors
+///
+/// * If it fails to get the state of a service, then an error is returned.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = roxy::health_check(&["sshd", "rsyslog"], &["eno1"])?;
+/// if report.status != HealthStatus::Healthy {
+///     println!("{report:?}");
+/// }
+/// ```
+pub fn check(services: &[&str], interfaces: &[&str]) -> Result<HealthReport> {
+    let services = services
+        .iter()
+        .map(|&unit| systemctl::is_active(unit).map(|active| (unit.to_string(), active)))
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    let ntp_synchronized = ntp_synchronized();
+
+    let system = System::new_with_specifics(RefreshKind::new().with_disks_list());
+    let (total, used) = disk_space(&system);
+    #[allow(clippy::cast_precision_loss)]
+    let disk_usage = if total == 0 {
+        0.0
+    } else {
+        used as f32 * 100.0 / total as f32
+    };
+
+    let ifaces = datalink::interfaces();
+    let links = interfaces
+        .iter()
+        .map(|&name| {
+            let up = ifaces
+                .iter()
+                .any(|iface| iface.name == name && iface.is_up() && iface.is_running());
+            (name.to_string(), up)
+        })
+        .collect::<Vec<_>>();
+
+    let status = if services.iter().any(|(_, active)| !active) || disk_usage > DISK_CRITICAL_PERCENT
+    {
+        HealthStatus::Critical
+    } else if ntp_synchronized != Some(true)
+        || links.iter().any(|(_, up)| !up)
+        || disk_usage > DISK_DEGRADED_PERCENT
+    {
+        HealthStatus::Degraded
+    } else {
+        HealthStatus::Healthy
+    };
+
+    Ok(HealthReport {
+        status,
+        services,
+        ntp_synchronized,
+        disk_usage,
+        links,
+    })
+}
+
+// Returns whether the clock is synchronized, from timedatectl.
+fn ntp_synchronized() -> Option<bool> {
+    let output = Command::new("timedatectl")
+        .env("PATH", DEFAULT_PATH_ENV)
+        .args(["show", "--property=NTPSynchronized", "--value"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
@@ -1,9 +1,56 @@
-use std::{fmt, fs::File, io::Read, time::Duration};
+use super::usg::{resource_usage, ResourceUsage};
+use crate::common::{DEFAULT_PATH_ENV, VERSION_PATH};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
 use thiserror::Error;
 
 const DEFAULT_VERSION_STRING: &str = "AICE security";
-// TODO: should change this path to /usr/local/aice/conf/version?
-const DEFAULT_VERSION_PATH: &str = "/etc/version";
+const UPTIME_PATH: &str = "/proc/uptime";
+const CMDLINE_PATH: &str = "/proc/cmdline";
+const THERMAL_PATH: &str = "/sys/class/thermal";
+const HWMON_PATH: &str = "/sys/class/hwmon";
+
+/// The temperature of a thermal zone.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ThermalZone {
+    /// The type of the zone, e.g. "x86_pkg_temp", "acpitz".
+    pub name: String,
+    /// The temperature in degrees Celsius.
+    pub temperature: f64,
+}
+
+/// The speed of a fan reported by hwmon.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Fan {
+    /// The hwmon device and the label of the fan, e.g. "nct6775/fan1".
+    pub name: String,
+    /// The speed in RPM.
+    pub rpm: u32,
+}
+
+/// The usage of a mounted filesystem in bytes, reported by `df`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DiskUsageBytes {
+    /// The mount point, e.g. "/data".
+    pub mount_point: String,
+    /// The size of the filesystem in bytes.
+    pub size: u64,
+    /// The used space in bytes.
+    pub used: u64,
+    /// The space available to unprivileged users in bytes.
+    pub available: u64,
+    /// The used space in percent, rounded up by `df`. `None` if `df` does
+    /// not report it, e.g. for a pseudo filesystem with no size.
+    pub percent: Option<u8>,
+}
 
 #[derive(Debug, Error)]
 pub struct UptimeError {
@@ -16,6 +63,159 @@ impl fmt::Display for UptimeError {
     }
 }
 
+/// The summary of the system taken at once.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SysInfo {
+    /// The hostname.
+    pub hostname: String,
+
+    /// How long the system has been running.
+    pub uptime: Duration,
+
+    /// The OS version in [`VERSION_PATH`].
+    pub os_version: String,
+
+    /// The product version in [`VERSION_PATH`].
+    pub product_version: String,
+
+    /// CPU, memory, and disk usage.
+    pub usage: ResourceUsage,
+}
+
+/// Returns the hostname, uptime, versions, and resource usage together, for
+/// a dashboard showing them at the same point in time.
+///
+/// # Errors
+///
+/// Returns an error if the operating system does not return uptime or boottime.
+///
+/// # Example
+///
+/// ```ignore
+/// let info = roxy::sysinfo().await?;
+/// println!("{} up {:?}, cpu {}%", info.hostname, info.uptime, info.usage.cpu_usage);
+/// ```
+pub async fn sysinfo() -> Result<SysInfo, UptimeError> {
+    let uptime = uptime()?;
+    let (os_version, product_version) = version();
+    Ok(SysInfo {
+        hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+        uptime,
+        os_version,
+        product_version,
+        usage: resource_usage().await,
+    })
+}
+
+/// Returns the pretty hostname of systemd, a free-form name for display, e.g.
+/// "Sensor in rack 3". Returns `None` if it is not set.
+///
+/// # Errors
+///
+/// * If `hostnamectl` fails, then an error is returned.
+pub fn pretty_hostname() -> anyhow::Result<Option<String>> {
+    let name = command_output("hostnamectl", &["--pretty"])?;
+    let name = name.trim();
+    Ok((!name.is_empty()).then(|| name.to_string()))
+}
+
+/// Returns the fully qualified domain name of the host, e.g.
+/// "sensor1.example.com". Use `hostname` for the name without the domain.
+///
+/// # Errors
+///
+/// * If `hostname --fqdn` fails, e.g. the hostname is not resolved, then an
+///   error is returned.
+pub fn fqdn() -> anyhow::Result<String> {
+    Ok(command_output("hostname", &["--fqdn"])?.trim().to_string())
+}
+
+/// Returns the command line the kernel booted with, e.g. to confirm boot
+/// parameters like `hugepages` or `isolcpus`.
+///
+/// # Errors
+///
+/// * If it fails to read `/proc/cmdline`, then an error is returned.
+pub fn kernel_cmdline() -> anyhow::Result<String> {
+    Ok(fs::read_to_string(CMDLINE_PATH)?.trim_end().to_string())
+}
+
+/// Returns the release of the running kernel, e.g. "5.15.0-91-generic".
+///
+/// # Errors
+///
+/// * If `uname(2)` fails, then an error is returned.
+pub fn kernel_version() -> anyhow::Result<String> {
+    let uts = nix::sys::utsname::uname()?;
+    Ok(uts.release().to_string_lossy().into_owned())
+}
+
+/// Returns the exact usage in bytes of each mounted filesystem from
+/// `df -B1`, e.g. to compute the growth rate of a disk.
+///
+/// # Errors
+///
+/// * If `df` fails, then an error is returned.
+/// * If the output of `df` is not recognized, then an error is returned.
+pub fn disk_usage_bytes() -> anyhow::Result<Vec<DiskUsageBytes>> {
+    let output = command_output("df", &["-B1", "--output=target,size,used,avail,pcent"])?;
+    parse_df(&output)
+}
+
+// Parses the output of `df -B1 --output=target,size,used,avail,pcent` like:
+//
+// Mounted on          1B-blocks        Used       Avail Use%
+// /                 105089261568 45157642240 54546268160  46%
+// /data            1967317823488 25426972672 1841869905920   2%
+//
+// The last four fields are the numbers, and the rest is the mount point,
+// which may have spaces.
+fn parse_df(output: &str) -> anyhow::Result<Vec<DiskUsageBytes>> {
+    let mut disks = Vec::new();
+    for line in output.lines().skip(1) {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.is_empty() {
+            continue;
+        }
+        let Some(split) = fields.len().checked_sub(4).filter(|n| *n > 0) else {
+            return Err(anyhow!("unrecognized df output: {}", line));
+        };
+        let (mount_point, [size, used, available, percent]) = (&fields[..split], &fields[split..])
+        else {
+            return Err(anyhow!("unrecognized df output: {}", line));
+        };
+        let number = |value: &str| {
+            value
+                .parse::<u64>()
+                .map_err(|_| anyhow!("unrecognized df output: {}", line))
+        };
+        disks.push(DiskUsageBytes {
+            mount_point: mount_point.join(" "),
+            size: number(size)?,
+            used: number(used)?,
+            available: number(available)?,
+            percent: percent.strip_suffix('%').and_then(|v| v.parse().ok()),
+        });
+    }
+    Ok(disks)
+}
+
+fn command_output(cmd: &str, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new(cmd)
+        .env("PATH", DEFAULT_PATH_ENV)
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} {} failed: {}",
+            cmd,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
 /// Returns how long the system has been running.
 ///
 /// # Errors
@@ -35,7 +235,125 @@ pub fn uptime() -> Result<Duration, UptimeError> {
     uptime_lib::get().map_err(|e| UptimeError { message: e })
 }
 
-/// Returns OS and Product versions by reading /etc/version.
+/// Returns how long the system has been running in seconds, by reading
+/// `/proc/uptime`. Returns `None` if it is not available.
+///
+/// # Examples
+///
+/// ```ignore
+/// if roxy::uptime_secs().is_some_and(|secs| secs < 300) {
+///     println!("the system has just booted");
+/// }
+/// ```
+#[must_use]
+pub fn uptime_secs() -> Option<u64> {
+    let contents = std::fs::read_to_string(UPTIME_PATH).ok()?;
+    let secs = contents.split_whitespace().next()?.parse::<f64>().ok()?;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let secs = secs as u64;
+    Some(secs)
+}
+
+/// Returns the temperatures of the thermal zones in `/sys/class/thermal`,
+/// sorted by the zone number. Returns an empty vector if there is no thermal
+/// zone, e.g. in a VM.
+///
+/// # Errors
+///
+/// * If it fails to read `/sys/class/thermal`, then an error is returned.
+///
+/// # Example
+///
+/// ```ignore
+/// for zone in roxy::thermals()? {
+///     println!("{}: {:.1}°C", zone.name, zone.temperature);
+/// }
+/// ```
+pub fn thermals() -> io::Result<Vec<ThermalZone>> {
+    let mut zones = Vec::new();
+    for (index, path) in sys_entries(THERMAL_PATH, "thermal_zone")? {
+        // A zone may fail to report its temperature, e.g. when its sensor is
+        // powered off.
+        let Some(millidegree) = read_sys_value::<i64>(&path.join("temp")) else {
+            continue;
+        };
+        let name = read_sys_string(&path.join("type")).unwrap_or_else(|| format!("zone{index}"));
+        #[allow(clippy::cast_precision_loss)]
+        let temperature = millidegree as f64 / 1000.0;
+        zones.push(ThermalZone { name, temperature });
+    }
+    Ok(zones)
+}
+
+/// Returns the speeds of the fans reported by hwmon in `/sys/class/hwmon`.
+/// Returns an empty vector if there is no fan, e.g. in a fanless appliance.
+///
+/// # Errors
+///
+/// * If it fails to read `/sys/class/hwmon`, then an error is returned.
+pub fn fans() -> io::Result<Vec<Fan>> {
+    let mut fans = Vec::new();
+    for (index, hwmon) in sys_entries(HWMON_PATH, "hwmon")? {
+        let device =
+            read_sys_string(&hwmon.join("name")).unwrap_or_else(|| format!("hwmon{index}"));
+        let mut inputs = fs::read_dir(&hwmon)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file = entry.file_name().to_string_lossy().into_owned();
+                let fan = file.strip_suffix("_input")?.to_string();
+                let number = fan.strip_prefix("fan")?.parse::<u32>().ok()?;
+                Some((number, fan))
+            })
+            .collect::<Vec<_>>();
+        inputs.sort_unstable();
+        for (_, fan) in inputs {
+            let Some(rpm) = read_sys_value::<u32>(&hwmon.join(format!("{fan}_input"))) else {
+                continue;
+            };
+            let label = read_sys_string(&hwmon.join(format!("{fan}_label"))).unwrap_or(fan);
+            fans.push(Fan {
+                name: format!("{device}/{label}"),
+                rpm,
+            });
+        }
+    }
+    Ok(fans)
+}
+
+// Returns the numbered entries like "thermal_zone0" in the sysfs class
+// directory, sorted by the number. A missing directory has no entry.
+fn sys_entries(dir: &str, prefix: &str) -> io::Result<Vec<(u32, PathBuf)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut ret = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let index = name.to_str()?.strip_prefix(prefix)?.parse::<u32>().ok()?;
+            Some((index, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    ret.sort_unstable_by_key(|(index, _)| *index);
+    Ok(ret)
+}
+
+fn read_sys_string(path: &Path) -> Option<String> {
+    let value = fs::read_to_string(path).ok()?.trim().to_string();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+fn read_sys_value<T: std::str::FromStr>(path: &Path) -> Option<T> {
+    read_sys_string(path)?.parse::<T>().ok()
+}
+
+/// Returns OS and Product versions by reading [`VERSION_PATH`].
 ///
 /// # Example
 ///
@@ -47,7 +365,7 @@ pub fn uptime() -> Result<Duration, UptimeError> {
 pub fn version() -> (String, String) {
     let mut os_version = DEFAULT_VERSION_STRING.to_string();
     let mut product_version = DEFAULT_VERSION_STRING.to_string();
-    if let Ok(mut file) = File::open(DEFAULT_VERSION_PATH) {
+    if let Ok(mut file) = File::open(VERSION_PATH) {
         let mut contents = String::new();
         if file.read_to_string(&mut contents).is_ok() {
             let lines = contents.lines();
@@ -70,3 +388,57 @@ pub fn version() -> (String, String) {
     }
     (os_version, product_version)
 }
+
+/// Returns OS version by reading [`VERSION_PATH`].
+#[must_use]
+pub fn os_version() -> String {
+    version().0
+}
+
+/// Returns Product version by reading [`VERSION_PATH`].
+#[must_use]
+pub fn product_version() -> String {
+    version().1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_df_mount_points() {
+        let output = "\
+Mounted on          1B-blocks        Used       Avail Use%
+/                 105089261568 45157642240 54546268160  46%
+/data            1967317823488 25426972672 1841869905920   2%
+/media/usb disk      31000000000 1000000000 30000000000   4%
+/proc                       0           0           0    -
+";
+        let disks = parse_df(output).unwrap();
+        assert_eq!(disks.len(), 4);
+        assert_eq!(disks[0].mount_point, "/");
+        assert_eq!(disks[0].size, 105_089_261_568);
+        assert_eq!(disks[0].used, 45_157_642_240);
+        assert_eq!(disks[0].available, 54_546_268_160);
+        assert_eq!(disks[0].percent, Some(46));
+        assert_eq!(disks[1].mount_point, "/data");
+        assert_eq!(disks[1].percent, Some(2));
+        assert_eq!(disks[2].mount_point, "/media/usb disk");
+        assert_eq!(disks[3].mount_point, "/proc");
+        assert_eq!(disks[3].size, 0);
+        assert_eq!(disks[3].percent, None);
+    }
+
+    #[test]
+    fn parse_df_header_only() {
+        let output = "Mounted on 1B-blocks Used Avail Use%\n";
+        assert!(parse_df(output).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_df_unrecognized() {
+        let header = "Mounted on          1B-blocks        Used       Avail Use%\n";
+        assert!(parse_df(&format!("{header}/ 100 50 50%\n")).is_err());
+        assert!(parse_df(&format!("{header}/ 100 fifty 50 50%\n")).is_err());
+    }
+}
@@ -0,0 +1,672 @@
+use crate::common::DEFAULT_PATH_ENV;
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{fs, net::IpAddr, path::Path, process::Command};
+
+const SYS_CLASS_NET: &str = "/sys/class/net";
+
+/// The result of `ping`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct PingResult {
+    /// The number of packets transmitted.
+    pub transmitted: u32,
+
+    /// The number of packets received.
+    pub received: u32,
+
+    /// The average round-trip time in milliseconds. `None` if no packet is
+    /// received.
+    pub avg_rtt: Option<f64>,
+}
+
+impl PingResult {
+    /// True if at least one packet is received.
+    #[must_use]
+    pub fn is_reachable(&self) -> bool {
+        self.received > 0
+    }
+}
+
+/// The duplex mode negotiated by a link.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Duplex {
+    Full,
+    Half,
+}
+
+/// The state of a link reported in `/sys/class/net`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct LinkInfo {
+    /// The negotiated speed in Mbps. `None` if the link is down or the
+    /// driver does not report it, e.g. a virtual interface.
+    pub speed_mbps: Option<u32>,
+
+    /// The negotiated duplex mode. `None` if the link is down or unknown.
+    pub duplex: Option<Duplex>,
+
+    /// The operational state, e.g. "up", "down", "dormant", "unknown".
+    pub operstate: String,
+
+    /// True if the cable is connected. `None` if the interface is
+    /// administratively down.
+    pub carrier: Option<bool>,
+}
+
+/// Returns the speed, duplex, operational state, and carrier of a link, e.g.
+/// to check whether it negotiated 1000 Mbps or 100 Mbps.
+///
+/// # Errors
+///
+/// * If the interface is not found, then an error is returned.
+///
+/// # Example
+///
+/// ```ignore
+/// let link = roxy::link_info("eno1")?;
+/// if link.speed_mbps.is_some_and(|speed| speed < 1000) {
+///     println!("eno1 is slower than 1G");
+/// }
+/// ```
+pub fn link_info(ifname: &str) -> Result<LinkInfo> {
+    if ifname.is_empty() || ifname.contains('/') || ifname.starts_with('.') {
+        return Err(anyhow!("invalid interface name: {}", ifname));
+    }
+    let dir = Path::new(SYS_CLASS_NET).join(ifname);
+    let operstate = fs::read_to_string(dir.join("operstate"))
+        .map_err(|e| anyhow!("interface \"{}\" not found. {}", ifname, e))?
+        .trim()
+        .to_string();
+
+    // Reading these fails with EINVAL while the link is down.
+    let read = |file: &str| {
+        fs::read_to_string(dir.join(file))
+            .ok()
+            .map(|v| v.trim().to_string())
+    };
+    // The driver reports -1 if the speed is unknown.
+    let speed_mbps = read("speed").and_then(|v| v.parse::<u32>().ok());
+    let duplex = match read("duplex").as_deref() {
+        Some("full") => Some(Duplex::Full),
+        Some("half") => Some(Duplex::Half),
+        _ => None,
+    };
+    let carrier = match read("carrier").as_deref() {
+        Some("1") => Some(true),
+        Some("0") => Some(false),
+        _ => None,
+    };
+
+    Ok(LinkInfo {
+        speed_mbps,
+        duplex,
+        operstate,
+        carrier,
+    })
+}
+
+/// Returns the names of the physical interfaces, sorted by name. Interfaces
+/// without a device in `/sys/class/net/<if>/device`, e.g. loopback, bridges,
+/// vlans, docker, and veth, are excluded.
+///
+/// # Errors
+///
+/// * If it fails to read `/sys/class/net`, then an error is returned.
+///
+/// # Example
+///
+/// ```ignore
+/// for ifname in roxy::physical_interfaces()? {
+///     println!("{ifname}");
+/// }
+/// ```
+pub fn physical_interfaces() -> Result<Vec<String>> {
+    let mut names = fs::read_dir(SYS_CLASS_NET)?
+        .flatten()
+        .filter(|entry| entry.path().join("device").exists())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(names)
+}
+
+/// Sends `count` ICMP echo requests to `target` and returns the statistics.
+///
+/// An unreachable target is not an error. Check `received` of the result.
+///
+/// # Errors
+///
+/// * If `count` is zero or `target` is invalid, then an error is returned.
+/// * If it fails to execute `ping`, then an error is returned.
+/// * If the output of `ping` is not recognized, then an error is returned.
+///
+/// # Example
+///
+/// ```ignore
+/// let ret = roxy::ping("192.168.0.1", 3)?;
+/// if !ret.is_reachable() {
+///     println!("gateway is unreachable");
+/// }
+/// ```
+pub fn ping(target: &str, count: u32) -> Result<PingResult> {
+    if count == 0 {
+        return Err(anyhow!("count should be greater than zero"));
+    }
+    if target.is_empty() || target.starts_with('-') {
+        return Err(anyhow!("invalid target: {}", target));
+    }
+
+    let output = Command::new("ping")
+        .env("PATH", DEFAULT_PATH_ENV)
+        .args(["-c", &count.to_string(), target])
+        .output()?;
+    parse_ping(&String::from_utf8_lossy(&output.stdout))
+}
+
+// Parses the statistics of ping output like:
+//
+// 4 packets transmitted, 4 received, 0% packet loss, time 3004ms
+// rtt min/avg/max/mdev = 0.045/0.060/0.072/0.010 ms
+fn parse_ping(output: &str) -> Result<PingResult> {
+    let re_packets = Regex::new(r"(\d+) packets transmitted, (\d+) (?:packets )?received")?;
+    let re_rtt = Regex::new(r"min/avg/max/(?:mdev|stddev) = [\d.]+/([\d.]+)/")?;
+
+    let cap = re_packets
+        .captures(output)
+        .ok_or_else(|| anyhow!("unrecognized ping output"))?;
+    let transmitted = cap[1].parse::<u32>()?;
+    let received = cap[2].parse::<u32>()?;
+    let avg_rtt = re_rtt
+        .captures(output)
+        .and_then(|cap| cap[1].parse::<f64>().ok());
+
+    Ok(PingResult {
+        transmitted,
+        received,
+        avg_rtt,
+    })
+}
+
+/// A route in the running route table.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RouteEntry {
+    /// The destination, e.g. "default", "192.168.0.0/24", "2001:db8::/64".
+    pub destination: String,
+
+    /// The gateway. `None` if the destination is directly connected.
+    pub gateway: Option<IpAddr>,
+
+    /// The interface of the route.
+    pub dev: Option<String>,
+
+    /// The metric of the route. `None` if not set, i.e. zero.
+    pub metric: Option<u32>,
+
+    /// Where the route comes from, e.g. "kernel", "static", "dhcp".
+    pub proto: Option<String>,
+}
+
+/// Returns the IPv4 and IPv6 routes in the main route table, i.e. the output
+/// of `ip route show` and `ip -6 route show`.
+///
+/// # Errors
+///
+/// * If it fails to execute `ip route`, then an error is returned.
+///
+/// # Example
+///
+/// ```ignore
+/// for route in roxy::routes()? {
+///     println!("{} via {:?} dev {:?}", route.destination, route.gateway, route.dev);
+/// }
+/// ```
+pub fn routes() -> Result<Vec<RouteEntry>> {
+    let mut ret = Vec::new();
+    for args in [&["route", "show"][..], &["-6", "route", "show"][..]] {
+        let output = Command::new("ip")
+            .env("PATH", DEFAULT_PATH_ENV)
+            .args(args)
+            .output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "ip {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        ret.extend(parse_routes(&String::from_utf8_lossy(&output.stdout)));
+    }
+    Ok(ret)
+}
+
+// Parses the output of `ip route show` like:
+//
+// default via 192.168.0.1 dev eno1 proto dhcp src 192.168.0.5 metric 100
+// 192.168.0.0/24 dev eno1 proto kernel scope link src 192.168.0.5
+// unreachable 10.0.0.0/8 metric 1024
+fn parse_routes(output: &str) -> Vec<RouteEntry> {
+    const ROUTE_TYPES: &[&str] = &[
+        "unicast",
+        "local",
+        "broadcast",
+        "multicast",
+        "throw",
+        "unreachable",
+        "prohibit",
+        "blackhole",
+        "nat",
+        "anycast",
+    ];
+
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().peekable();
+            fields.next_if(|field| ROUTE_TYPES.contains(field));
+            let mut route = RouteEntry {
+                destination: fields.next()?.to_string(),
+                gateway: None,
+                dev: None,
+                metric: None,
+                proto: None,
+            };
+            while let Some(field) = fields.next() {
+                match field {
+                    "via" => route.gateway = fields.next().and_then(|v| v.parse().ok()),
+                    "dev" => route.dev = fields.next().map(ToString::to_string),
+                    "metric" => route.metric = fields.next().and_then(|v| v.parse().ok()),
+                    "proto" => route.proto = fields.next().map(ToString::to_string),
+                    _ => {}
+                }
+            }
+            Some(route)
+        })
+        .collect()
+}
+
+/// An entry of the neighbor table, i.e. ARP for IPv4 and NDP for IPv6.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Neighbor {
+    /// The address of the neighbor.
+    pub ip: IpAddr,
+
+    /// The MAC address of the neighbor. `None` if it is not resolved.
+    pub mac: Option<String>,
+
+    /// The interface of the neighbor.
+    pub dev: String,
+
+    /// The state of the entry, e.g. "REACHABLE", "STALE", "FAILED".
+    pub state: String,
+}
+
+/// Returns the neighbor table, i.e. the output of `ip neigh show`. A gateway
+/// whose entry is "FAILED" or "INCOMPLETE" does not respond to ARP.
+///
+/// # Errors
+///
+/// * If it fails to execute `ip neigh`, then an error is returned.
+pub fn neighbors() -> Result<Vec<Neighbor>> {
+    let output = Command::new("ip")
+        .env("PATH", DEFAULT_PATH_ENV)
+        .args(["neigh", "show"])
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ip neigh show failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_neighbors(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// Parses the output of `ip neigh show` like:
+//
+// 192.168.0.1 dev eno1 lladdr 00:11:22:33:44:55 REACHABLE
+// 192.168.0.9 dev eno1 FAILED
+// fe80::1 dev eno1 lladdr 00:11:22:33:44:66 router STALE
+fn parse_neighbors(output: &str) -> Vec<Neighbor> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let ip = fields.next()?.parse::<IpAddr>().ok()?;
+            let mut mac = None;
+            let mut dev = None;
+            let mut state = String::new();
+            while let Some(field) = fields.next() {
+                match field {
+                    "dev" => dev = fields.next(),
+                    "lladdr" => mac = fields.next().map(ToString::to_string),
+                    // The state is the last field, after flags like "router".
+                    _ => state = field.to_string(),
+                }
+            }
+            Some(Neighbor {
+                ip,
+                mac,
+                dev: dev?.to_string(),
+                state,
+            })
+        })
+        .collect()
+}
+
+/// The driver and firmware of a network adapter reported by `ethtool -i`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DriverInfo {
+    /// The kernel driver, e.g. "ixgbe".
+    pub driver: String,
+    /// The version of the driver. Empty if the driver does not report it.
+    pub version: String,
+    /// The firmware version of the adapter, e.g. "0x800003e7". Empty if
+    /// the driver does not report it.
+    pub firmware_version: String,
+    /// The bus address of the adapter, e.g. "0000:03:00.0". Empty for a
+    /// virtual interface.
+    pub bus_info: String,
+}
+
+/// Returns the driver, firmware version, and bus address of a network
+/// adapter, e.g. to correlate packet drops with a firmware version.
+///
+/// # Errors
+///
+/// * If the interface name is invalid, then an error is returned.
+/// * If it fails to execute `ethtool -i`, e.g. the interface is not found,
+///   then an error is returned.
+pub fn driver_info(ifname: &str) -> Result<DriverInfo> {
+    if ifname.is_empty() || ifname.starts_with('-') || ifname.contains('/') {
+        return Err(anyhow!("invalid interface name: {}", ifname));
+    }
+    let output = Command::new("ethtool")
+        .env("PATH", DEFAULT_PATH_ENV)
+        .args(["-i", ifname])
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ethtool -i {} failed: {}",
+            ifname,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_driver_info(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// Parses the output of `ethtool -i` like:
+//
+// driver: ixgbe
+// version: 5.15.0-91-generic
+// firmware-version: 0x800003e7
+// bus-info: 0000:03:00.0
+fn parse_driver_info(output: &str) -> DriverInfo {
+    let mut info = DriverInfo::default();
+    for (key, value) in output.lines().filter_map(|line| line.split_once(':')) {
+        let value = value.trim().to_string();
+        match key.trim() {
+            "driver" => info.driver = value,
+            "version" => info.version = value,
+            "firmware-version" => info.firmware_version = value,
+            "bus-info" => info.bus_info = value,
+            _ => {}
+        }
+    }
+    info
+}
+
+/// Returns the interface name and the gateway address of the active IPv4
+/// default route, or `None` if there is no default route.
+///
+/// Unlike `gateway4` in netplan, this includes a gateway assigned by DHCP. If
+/// there are multiple default routes, the one with the lowest metric is used.
+///
+/// # Errors
+///
+/// * If it fails to execute `ip route`, then an error is returned.
+///
+/// # Example
+///
+/// ```ignore
+/// if let Some((ifname, gateway)) = roxy::default_route()? {
+///     println!("default via {gateway} dev {ifname}");
+/// }
+/// ```
+pub fn default_route() -> Result<Option<(String, IpAddr)>> {
+    let output = Command::new("ip")
+        .env("PATH", DEFAULT_PATH_ENV)
+        .args(["route", "show", "default"])
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ip route show default failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_default_route(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+// Parses the output of `ip route show default` like:
+//
+// default via 192.168.0.1 dev eno1 proto dhcp src 192.168.0.5 metric 100
+fn parse_default_route(output: &str) -> Option<(String, IpAddr)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut gateway = None;
+            let mut dev = None;
+            let mut metric = 0;
+            let mut fields = line.split_whitespace();
+            while let Some(field) = fields.next() {
+                match field {
+                    "via" => gateway = fields.next().and_then(|v| v.parse::<IpAddr>().ok()),
+                    "dev" => dev = fields.next(),
+                    "metric" => metric = fields.next().and_then(|v| v.parse().ok()).unwrap_or(0),
+                    _ => {}
+                }
+            }
+            Some((metric, dev?.to_string(), gateway?))
+        })
+        .min_by_key(|(metric, _, _)| *metric)
+        .map(|(_, dev, gateway)| (dev, gateway))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ping_linux() {
+        let output = "\
+PING 192.168.0.1 (192.168.0.1) 56(84) bytes of data.
+64 bytes from 192.168.0.1: icmp_seq=1 ttl=64 time=0.045 ms
+64 bytes from 192.168.0.1: icmp_seq=2 ttl=64 time=0.072 ms
+
+--- 192.168.0.1 ping statistics ---
+3 packets transmitted, 2 received, 33.3333% packet loss, time 2003ms
+rtt min/avg/max/mdev = 0.045/0.058/0.072/0.013 ms
+";
+        let ret = parse_ping(output).unwrap();
+        assert_eq!(ret.transmitted, 3);
+        assert_eq!(ret.received, 2);
+        assert_eq!(ret.avg_rtt, Some(0.058));
+        assert!(ret.is_reachable());
+    }
+
+    #[test]
+    fn parse_ping_unreachable() {
+        let output = "\
+PING 192.168.0.9 (192.168.0.9) 56(84) bytes of data.
+From 192.168.0.5 icmp_seq=1 Destination Host Unreachable
+
+--- 192.168.0.9 ping statistics ---
+3 packets transmitted, 0 received, +3 errors, 100% packet loss, time 2031ms
+";
+        let ret = parse_ping(output).unwrap();
+        assert_eq!(ret.transmitted, 3);
+        assert_eq!(ret.received, 0);
+        assert_eq!(ret.avg_rtt, None);
+        assert!(!ret.is_reachable());
+    }
+
+    #[test]
+    fn parse_ping_bsd() {
+        let output = "\
+--- 192.168.0.1 ping statistics ---
+2 packets transmitted, 2 packets received, 0.0% packet loss
+round-trip min/avg/max/stddev = 0.101/0.150/0.199/0.049 ms
+";
+        let ret = parse_ping(output).unwrap();
+        assert_eq!(ret.received, 2);
+        assert_eq!(ret.avg_rtt, Some(0.15));
+    }
+
+    #[test]
+    fn parse_ping_unrecognized() {
+        assert!(parse_ping("ping: unknown host example.invalid\n").is_err());
+        assert!(parse_ping("").is_err());
+    }
+
+    #[test]
+    fn parse_routes_v4_and_v6() {
+        let output = "\
+default via 192.168.0.1 dev eno1 proto dhcp src 192.168.0.5 metric 100
+192.168.0.0/24 dev eno1 proto kernel scope link src 192.168.0.5
+unreachable 10.0.0.0/8 metric 1024
+2001:db8::/64 via fe80::1 dev eno2 proto static metric 256 pref medium
+";
+        assert_eq!(
+            parse_routes(output),
+            vec![
+                RouteEntry {
+                    destination: "default".to_string(),
+                    gateway: Some("192.168.0.1".parse().unwrap()),
+                    dev: Some("eno1".to_string()),
+                    metric: Some(100),
+                    proto: Some("dhcp".to_string()),
+                },
+                RouteEntry {
+                    destination: "192.168.0.0/24".to_string(),
+                    gateway: None,
+                    dev: Some("eno1".to_string()),
+                    metric: None,
+                    proto: Some("kernel".to_string()),
+                },
+                RouteEntry {
+                    destination: "10.0.0.0/8".to_string(),
+                    gateway: None,
+                    dev: None,
+                    metric: Some(1024),
+                    proto: None,
+                },
+                RouteEntry {
+                    destination: "2001:db8::/64".to_string(),
+                    gateway: Some("fe80::1".parse().unwrap()),
+                    dev: Some("eno2".to_string()),
+                    metric: Some(256),
+                    proto: Some("static".to_string()),
+                },
+            ]
+        );
+        assert!(parse_routes("").is_empty());
+    }
+
+    #[test]
+    fn parse_neighbors_states() {
+        let output = "\
+192.168.0.1 dev eno1 lladdr 00:11:22:33:44:55 REACHABLE
+192.168.0.9 dev eno1 FAILED
+fe80::1 dev eno1 lladdr 00:11:22:33:44:66 router STALE
+not-an-address dev eno1 FAILED
+192.168.0.7 lladdr 00:11:22:33:44:77 STALE
+";
+        assert_eq!(
+            parse_neighbors(output),
+            vec![
+                Neighbor {
+                    ip: "192.168.0.1".parse().unwrap(),
+                    mac: Some("00:11:22:33:44:55".to_string()),
+                    dev: "eno1".to_string(),
+                    state: "REACHABLE".to_string(),
+                },
+                Neighbor {
+                    ip: "192.168.0.9".parse().unwrap(),
+                    mac: None,
+                    dev: "eno1".to_string(),
+                    state: "FAILED".to_string(),
+                },
+                Neighbor {
+                    ip: "fe80::1".parse().unwrap(),
+                    mac: Some("00:11:22:33:44:66".to_string()),
+                    dev: "eno1".to_string(),
+                    state: "STALE".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_driver_info_physical_and_virtual() {
+        let output = "\
+driver: ixgbe
+version: 5.15.0-91-generic
+firmware-version: 0x800003e7
+expansion-rom-version:
+bus-info: 0000:03:00.0
+supports-statistics: yes
+";
+        assert_eq!(
+            parse_driver_info(output),
+            DriverInfo {
+                driver: "ixgbe".to_string(),
+                version: "5.15.0-91-generic".to_string(),
+                firmware_version: "0x800003e7".to_string(),
+                bus_info: "0000:03:00.0".to_string(),
+            }
+        );
+
+        let output = "\
+driver: veth
+version: 1.0
+firmware-version:
+bus-info:
+";
+        assert_eq!(
+            parse_driver_info(output),
+            DriverInfo {
+                driver: "veth".to_string(),
+                version: "1.0".to_string(),
+                ..DriverInfo::default()
+            }
+        );
+    }
+
+    #[test]
+    fn parse_default_route_lowest_metric() {
+        let output = "\
+default via 10.0.0.1 dev wlan0 proto dhcp metric 600
+default via 192.168.0.1 dev eno1 proto dhcp src 192.168.0.5 metric 100
+";
+        assert_eq!(
+            parse_default_route(output),
+            Some(("eno1".to_string(), "192.168.0.1".parse().unwrap()))
+        );
+
+        // no metric is zero, the lowest
+        let output = "\
+default via 10.0.0.1 dev wlan0 metric 600
+default via 192.168.0.1 dev eno1
+";
+        assert_eq!(
+            parse_default_route(output),
+            Some(("eno1".to_string(), "192.168.0.1".parse().unwrap()))
+        );
+    }
+
+    #[test]
+    fn parse_default_route_without_gateway() {
+        assert_eq!(parse_default_route(""), None);
+        assert_eq!(parse_default_route("default dev ppp0 scope link\n"), None);
+    }
+}
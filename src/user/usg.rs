@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::path::Path;
+use sysinfo::{DiskExt, System, SystemExt};
 
 /// CPU, memory, and disk usage.
 #[derive(Debug, Deserialize, Serialize)]
@@ -22,7 +23,7 @@ pub struct ResourceUsage {
 
 /// Returns CPU, memory, and disk usage.
 pub async fn resource_usage() -> ResourceUsage {
-    use sysinfo::{CpuExt, CpuRefreshKind, DiskExt, RefreshKind, System, SystemExt};
+    use sysinfo::{CpuExt, CpuRefreshKind, RefreshKind};
 
     let refresh = RefreshKind::new()
         .with_cpu(CpuRefreshKind::new().with_cpu_usage())
@@ -30,22 +31,7 @@ pub async fn resource_usage() -> ResourceUsage {
         .with_memory();
     let mut system = System::new_with_specifics(refresh);
 
-    let (total_disk_space, used_disk_space) = {
-        let disks = system.disks();
-        if let Some(d) = disks
-            .iter()
-            .find(|&disk| disk.mount_point() == Path::new("/data"))
-        {
-            (d.total_space(), d.total_space() - d.available_space())
-        } else {
-            // Find the disk with the largest space if `/data` is not found
-            if let Some(d) = disks.iter().max_by_key(|&disk| disk.total_space()) {
-                (d.total_space(), d.total_space() - d.available_space())
-            } else {
-                (0, 0)
-            }
-        }
-    };
+    let (total_disk_space, used_disk_space) = disk_space(&system);
 
     // Calculating CPU usage requires a time interval.
     tokio::time::sleep(std::time::Duration::from_millis(200)).await;
@@ -59,3 +45,19 @@ pub async fn resource_usage() -> ResourceUsage {
         used_disk_space,
     }
 }
+
+// Returns the total and used space in bytes of `/data`, or of the disk with
+// the largest space if `/data` is not found.
+pub(crate) fn disk_space(system: &System) -> (u64, u64) {
+    let disks = system.disks();
+    if let Some(d) = disks
+        .iter()
+        .find(|&disk| disk.mount_point() == Path::new("/data"))
+    {
+        (d.total_space(), d.total_space() - d.available_space())
+    } else if let Some(d) = disks.iter().max_by_key(|&disk| disk.total_space()) {
+        (d.total_space(), d.total_space() - d.available_space())
+    } else {
+        (0, 0)
+    }
+}